@@ -0,0 +1,465 @@
+//! Command line flags, and the server addresses and names they take.
+
+use crate::{config::Config, theme::Theme, DisplayStyle, PasteMode, Settings};
+use protocol::{
+    valid_name, wordlist::WordList, DEFAULT_PORT, LOBBY, MAX_NAME_CHARS, MAX_ROOM_CHARS,
+};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// How a server is listed and remembered: the host, plus the port when it
+/// isn't the default. Secure servers keep their `wss://` scheme and, unless
+/// one is given, use the standard HTTPS port of the proxy in front of them.
+fn server_addr(host: &str, port: Option<u16>, tls: bool) -> String {
+    let host = if host.parse::<std::net::Ipv6Addr>().is_ok() {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    };
+    match port {
+        Some(port) if tls => format!("wss://{}:{}", host, port),
+        None if tls => format!("wss://{}", host),
+        Some(port) if port != DEFAULT_PORT => format!("{}:{}", host, port),
+        _ => host,
+    }
+}
+
+/// WebSocket URL of the game server on `addr`, see `server_addr`
+pub fn server_url(addr: &str) -> String {
+    // Full URLs, given on the command line or for secure servers, are used as is
+    if addr.contains("://") {
+        return addr.to_string();
+    }
+    // Bare IPv6 literals, e.g. remembered before ports were, need brackets in a URL
+    if addr.parse::<std::net::Ipv6Addr>().is_ok() {
+        return format!("ws://[{}]:{}", addr, DEFAULT_PORT);
+    }
+    let has_port = addr
+        .rsplit_once(':')
+        .is_some_and(|(host, _)| !host.contains(':') || host.ends_with(']'));
+    if has_port {
+        format!("ws://{}", addr)
+    } else {
+        format!("ws://{}:{}", addr, DEFAULT_PORT)
+    }
+}
+
+/// `server_url` with the room and, when resuming, the session and sentence
+/// version we last saw as query parameters. The lobby needs no parameter.
+pub fn connect_url(server_url: &str, resume: Option<(&str, u64)>, room: &str) -> String {
+    if resume.is_none() && room == LOBBY {
+        return server_url.to_string();
+    }
+    // Anything we'd connect to was checked to parse when it was given
+    let Ok(mut url) = url::Url::parse(server_url) else {
+        return server_url.to_string();
+    };
+    {
+        let mut query = url.query_pairs_mut();
+        if let Some((session, version)) = resume {
+            query.append_pair("session", session);
+            query.append_pair("version", &version.to_string());
+        }
+        if room != LOBBY {
+            query.append_pair("room", room);
+        }
+    }
+    url.into()
+}
+
+#[derive(Debug)]
+pub struct Cli {
+    /// Server as listed by `server_addr`, `None` falls back to the most
+    /// recently used one
+    pub server: Option<String>,
+    pub settings: Settings,
+    /// `--config` file to read instead of the default one
+    pub config: Option<PathBuf>,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Cli, String> {
+    let mut server_ip = None;
+    let mut port = None;
+    let mut tls = false;
+    let mut settings = Settings {
+        theme: Theme::from_env(),
+        ..Settings::default()
+    };
+    let mut config = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--client-filter-file" => {
+                let path = flag_value(&mut iter, arg)?;
+                settings.client_filter = load_word_list(path)?;
+            }
+            "--debug" => settings.debug = true,
+            "--tls" => tls = true,
+            "--no-stats" => settings.hide_stats = true,
+            "--quiet-reconnect" => settings.quiet_reconnect = true,
+            "--rhythm-report" => settings.rhythm_report = true,
+            "--json-events" => settings.json_events = true,
+            "--practice-caps" => settings.practice.capitalize = true,
+            "--practice-punctuation" => settings.practice.punctuation = true,
+            "--practice-numbers" => settings.practice.numbers = true,
+            "--practice-count" => {
+                let value = flag_value(&mut iter, arg)?;
+                settings.practice.count = value
+                    .parse()
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or_else(|| format!("Invalid practice word count: {}", value))?;
+            }
+            "--practice-words" => {
+                let path = flag_value(&mut iter, arg)?;
+                let words = WordList::load(Path::new(path))
+                    .map_err(|err| format!("Could not read {}: {}", path, err))?;
+                if words.is_empty() {
+                    return Err(format!("{} has no words", path));
+                }
+                settings.practice_words = words;
+            }
+            "--name" => {
+                let name = flag_value(&mut iter, arg)?;
+                check_name(name)?;
+                settings.name = Some(name.to_string());
+            }
+            "--room" => {
+                let room = flag_value(&mut iter, arg)?.to_lowercase();
+                check_room(&room)?;
+                settings.room = room;
+            }
+            "--export-dir" => {
+                settings.export_dir = Some(PathBuf::from(flag_value(&mut iter, arg)?));
+            }
+            "--config" => config = Some(PathBuf::from(flag_value(&mut iter, arg)?)),
+            "--prefill" => settings.prefill = flag_value(&mut iter, arg)?.to_string(),
+            "--wpm-goal" => {
+                let value = flag_value(&mut iter, arg)?;
+                let goal = value
+                    .parse()
+                    .ok()
+                    .filter(|goal| *goal > 0)
+                    .ok_or_else(|| format!("Invalid WPM goal: {}", value))?;
+                settings.wpm_goal = Some(goal);
+            }
+            "--connect-timeout" => {
+                let value = flag_value(&mut iter, arg)?;
+                let secs = value
+                    .parse()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or_else(|| format!("Invalid connect timeout: {}", value))?;
+                settings.connect_timeout = Duration::from_secs(secs);
+            }
+            "--min-redraw-ms" => {
+                let value = flag_value(&mut iter, arg)?;
+                let ms = value
+                    .parse()
+                    .map_err(|_| format!("Invalid redraw interval: {}", value))?;
+                settings.min_redraw = Duration::from_millis(ms);
+            }
+            "--render-cap" => {
+                let value = flag_value(&mut iter, arg)?;
+                let cap = value
+                    .parse()
+                    .ok()
+                    .filter(|cap| *cap > 0)
+                    .ok_or_else(|| format!("Invalid render cap: {}", value))?;
+                settings.render_cap = Some(cap);
+            }
+            "--seed" => {
+                let value = flag_value(&mut iter, arg)?;
+                settings.seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid seed: {}", value))?,
+                );
+            }
+            "--display-style" => {
+                let value = flag_value(&mut iter, arg)?;
+                settings.display_style = DisplayStyle::parse(value)
+                    .ok_or_else(|| format!("Invalid display style: {}", value))?;
+            }
+            "--theme" => {
+                let value = flag_value(&mut iter, arg)?;
+                settings.theme =
+                    Theme::parse(value).ok_or_else(|| format!("Invalid theme: {}", value))?;
+            }
+            "--paste-mode" => {
+                let value = flag_value(&mut iter, arg)?;
+                settings.paste_mode = PasteMode::parse(value)
+                    .ok_or_else(|| format!("Invalid paste mode: {}", value))?;
+            }
+            flag if flag.starts_with("--") => {
+                return Err(format!("Unknown option: {}", flag));
+            }
+            addr if server_ip.is_none() => server_ip = Some(addr),
+            value if port.is_none() => {
+                port = Some(
+                    value
+                        .parse::<u16>()
+                        .ok()
+                        .filter(|port| *port > 0)
+                        .ok_or_else(|| format!("Invalid port (1-65535): {}", value))?,
+                );
+            }
+            extra => return Err(format!("Unexpected argument: {}", extra)),
+        }
+    }
+
+    let server = resolve_server(server_ip, port, tls)?;
+
+    Ok(Cli {
+        server,
+        settings,
+        config,
+    })
+}
+
+/// Checks a server given as a URL, or as a host with an optional port, and
+/// lists it the way `server_addr` does
+fn resolve_server(
+    server_ip: Option<&str>,
+    port: Option<u16>,
+    tls: bool,
+) -> Result<Option<String>, String> {
+    match server_ip {
+        Some(url) if url.contains("://") => {
+            let valid = url::Url::parse(url).is_ok_and(|parsed| {
+                matches!(parsed.scheme(), "ws" | "wss") && parsed.host().is_some()
+            });
+            if !valid {
+                return Err(format!("Invalid server URL: {}", url));
+            }
+            if port.is_some() || tls {
+                return Err("Put the port and scheme into the URL itself".to_string());
+            }
+            Ok(Some(url.trim_end_matches('/').to_string()))
+        }
+        Some(host) => {
+            // Validate IP format (basic check)
+            if !is_valid_ip_or_hostname(host) {
+                return Err(format!("Invalid IP address or hostname: {}", host));
+            }
+            Ok(Some(server_addr(host, port, tls)))
+        }
+        None if tls => Err("TLS needs a server address".to_string()),
+        None if port.is_some() => Err("A port needs a server address".to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Fills in whatever the command line left out from the config file
+pub fn apply_config(cli: &mut Cli, config: Config) -> Result<(), String> {
+    if cli.server.is_none() {
+        cli.server = resolve_server(config.server_ip.as_deref(), config.port, config.tls)?;
+    }
+    if cli.settings.name.is_none() {
+        if let Some(name) = config.username {
+            check_name(&name)?;
+            cli.settings.name = Some(name);
+        }
+    }
+    cli.settings.quiet_reconnect |= config.auto_reconnect;
+    Ok(())
+}
+
+/// Rooms the server accepts, they go into the URL as they are
+fn check_room(room: &str) -> Result<(), String> {
+    if room.is_empty()
+        || room.len() > MAX_ROOM_CHARS
+        || !room
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "Room names need 1 to {} letters, digits, - or _: {}",
+            MAX_ROOM_CHARS, room
+        ));
+    }
+    Ok(())
+}
+
+/// Names the server accepts: a handful of characters and no spaces
+pub fn check_name(name: &str) -> Result<(), String> {
+    if !valid_name(name) {
+        return Err(format!(
+            "Names need 1 to {} characters and no spaces: {}",
+            MAX_NAME_CHARS, name
+        ));
+    }
+    Ok(())
+}
+
+fn flag_value<'a>(
+    iter: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<&'a str, String> {
+    iter.next()
+        .map(String::as_str)
+        .ok_or_else(|| format!("Missing value for {}", flag))
+}
+
+/// Reads a blocklist with one word per line, skipping blanks and `#` comments
+fn load_word_list(path: &str) -> Result<HashSet<String>, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect())
+}
+
+fn is_valid_ip_or_hostname(addr: &str) -> bool {
+    // Check if it's a valid IPv4 address
+    if addr.parse::<std::net::Ipv4Addr>().is_ok() {
+        return true;
+    }
+
+    // Check if it's a valid IPv6 address
+    if addr.parse::<std::net::Ipv6Addr>().is_ok() {
+        return true;
+    }
+
+    // Basic hostname validation (allows letters, numbers, dots, and hyphens)
+    if addr.is_empty() || addr.len() > 253 {
+        return false;
+    }
+
+    addr.chars()
+        .all(|c| c.is_alphanumeric() || c == '.' || c == '-')
+        && !addr.starts_with('-')
+        && !addr.ends_with('-')
+        && !addr.starts_with('.')
+        && !addr.ends_with('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn ipv6_hosts_get_brackets() {
+        assert_eq!(server_url("::1"), "ws://[::1]:9001");
+        assert_eq!(
+            server_url("2001:db8:85a3::8a2e:370:7334"),
+            "ws://[2001:db8:85a3::8a2e:370:7334]:9001"
+        );
+        assert_eq!(
+            server_url("2001:0db8:0000:0000:0000:ff00:0042:8329"),
+            "ws://[2001:0db8:0000:0000:0000:ff00:0042:8329]:9001"
+        );
+        assert_eq!(server_url("[::1]:8080"), "ws://[::1]:8080");
+    }
+
+    #[test]
+    fn ipv4_and_hostnames_stay_as_they_are() {
+        assert_eq!(server_url("192.168.1.5"), "ws://192.168.1.5:9001");
+        assert_eq!(server_url("192.168.1.5:8080"), "ws://192.168.1.5:8080");
+        assert_eq!(
+            server_url("chaos.example.com"),
+            "ws://chaos.example.com:9001"
+        );
+        assert_eq!(server_url("localhost:8080"), "ws://localhost:8080");
+        assert_eq!(
+            server_url("wss://chaos.example.com/game"),
+            "wss://chaos.example.com/game"
+        );
+    }
+
+    #[test]
+    fn servers_are_listed_from_the_command_line() {
+        let server = |given: &[&str]| parse_args(&args(given)).unwrap().server;
+        assert_eq!(server(&["::1"]), Some("[::1]".to_string()));
+        assert_eq!(server(&["::1", "8080"]), Some("[::1]:8080".to_string()));
+        assert_eq!(server(&["fe80::1", "9001"]), Some("[fe80::1]".to_string()));
+        assert_eq!(server(&["10.0.0.2"]), Some("10.0.0.2".to_string()));
+        assert_eq!(
+            server(&["chaos.example.com", "--tls"]),
+            Some("wss://chaos.example.com".to_string())
+        );
+        assert_eq!(server(&[]), None);
+
+        for listed in ["[::1]", "[::1]:8080", "10.0.0.2", "wss://chaos.example.com"] {
+            assert!(url::Url::parse(&server_url(listed)).is_ok(), "{listed}");
+        }
+    }
+
+    #[test]
+    fn bad_servers_are_refused() {
+        for given in [
+            &["not a host"][..],
+            &["-bad.example"],
+            &["http://chaos.example.com"],
+            &["ws://chaos.example.com", "8080"],
+            &["10.0.0.2", "0"],
+            &["10.0.0.2", "80", "extra"],
+            &["--tls"],
+        ] {
+            assert!(parse_args(&args(given)).is_err(), "{given:?}");
+        }
+    }
+
+    #[test]
+    fn connect_urls_carry_the_room_and_resume() {
+        assert_eq!(
+            connect_url("ws://[::1]:9001", None, LOBBY),
+            "ws://[::1]:9001"
+        );
+        assert_eq!(
+            connect_url("ws://[::1]:9001", Some(("1a2b", 7)), "poets"),
+            "ws://[::1]:9001/?session=1a2b&version=7&room=poets"
+        );
+    }
+
+    #[test]
+    fn connect_urls_keep_the_path_and_query() {
+        assert_eq!(
+            connect_url("wss://example.com/game", None, "poets"),
+            "wss://example.com/game?room=poets"
+        );
+        assert_eq!(
+            connect_url(
+                "wss://example.com/game?token=a%20b",
+                Some(("1a2b", 7)),
+                LOBBY
+            ),
+            "wss://example.com/game?token=a%20b&session=1a2b&version=7"
+        );
+    }
+
+    #[test]
+    fn bad_names_say_what_names_need() {
+        assert!(check_name("ada").is_ok());
+        assert_eq!(
+            check_name("ada lovelace"),
+            Err(format!(
+                "Names need 1 to {} characters and no spaces: ada lovelace",
+                MAX_NAME_CHARS
+            ))
+        );
+    }
+
+    #[test]
+    fn room_names_are_short_and_url_safe() {
+        assert!(check_room("poets").is_ok());
+        assert!(check_room("night-owls_2").is_ok());
+        assert!(check_room(&"r".repeat(MAX_ROOM_CHARS)).is_ok());
+        assert!(check_room("").is_err());
+        assert!(check_room("two words").is_err());
+        assert!(check_room("a/b").is_err());
+        assert!(check_room("café").is_err());
+        assert!(check_room(&"r".repeat(MAX_ROOM_CHARS + 1)).is_err());
+    }
+}
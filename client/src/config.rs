@@ -0,0 +1,107 @@
+//! Settings from `~/.config/chaostype/config.toml`, or the file given with
+//! `--config`, so the usual server and name don't need typing every time.
+//!
+//! The file is TOML read with serde, unknown keys are an error so a typo
+//! doesn't go unnoticed. Anything given on the command line wins.
+
+use crate::prefs;
+use serde::Deserialize;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Host, IP or `ws://`/`wss://` URL of the server to start on
+    pub server_ip: Option<String>,
+    pub port: Option<u16>,
+    pub tls: bool,
+    pub username: Option<String>,
+    /// Same as `--quiet-reconnect`
+    pub auto_reconnect: bool,
+}
+
+impl Config {
+    /// Reads `path`, or the default file when it's `None`. Only a file asked
+    /// for explicitly has to exist.
+    pub fn load(path: Option<&Path>) -> Result<Config, String> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Config::parse(&text).map_err(|err| format!("{}: {}", path.display(), err)),
+            Err(err) if err.kind() == ErrorKind::NotFound && !required => Ok(Config::default()),
+            Err(err) => Err(format!("Could not read {}: {}", path.display(), err)),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(text).map_err(|err| err.to_string())?;
+        if config.port == Some(0) {
+            return Err("Invalid port (1-65535): 0".to_string());
+        }
+        Ok(config)
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    Some(prefs::config_dir()?.join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_setting() {
+        let text = r#"
+# where we play
+server_ip = "192.168.1.100"   # the LAN box
+port = 9001
+tls = true
+username = "Ada"
+auto_reconnect = false
+"#;
+        assert_eq!(
+            Config::parse(text),
+            Ok(Config {
+                server_ip: Some("192.168.1.100".to_string()),
+                port: Some(9001),
+                tls: true,
+                username: Some("Ada".to_string()),
+                auto_reconnect: false,
+            })
+        );
+    }
+
+    #[test]
+    fn strings_keep_escapes_and_hashes() {
+        let config = Config::parse(r#"username = "a\"b # c\\" # comment"#).unwrap();
+        assert_eq!(config.username.as_deref(), Some(r#"a"b # c\"#));
+        let config = Config::parse(r#"username = "tab\there\u00e9""#).unwrap();
+        assert_eq!(config.username.as_deref(), Some("tab\there\u{e9}"));
+    }
+
+    #[test]
+    fn bad_files_are_refused() {
+        for text in [
+            "username = \"open",
+            "username = \"bad \\q\"",
+            "username = \"a\" extra",
+            "port = 0",
+            "port = 70000",
+            "tls = yes",
+            "colour = \"red\"",
+            "just words",
+        ] {
+            assert!(Config::parse(text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Headless `--json-events` mode.
+//!
+//! Instead of the TUI, every message from the server is written to stdout as
+//! one line of JSON in the wire format, so overlays and bots can follow a game
+//! with any JSON parser.
+
+use futures::StreamExt;
+use protocol::ServerMessage;
+use std::io::{self, Write};
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+
+/// Follows the game on `url` until the server closes the connection
+pub async fn run(url: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (ws_stream, _) = connect_async(url).await?;
+    let (_write, mut read) = ws_stream.split();
+    let mut tagged = None;
+
+    while let Some(msg) = read.next().await {
+        let line = match msg? {
+            Message::Text(text) => {
+                let msg = ServerMessage::decode(&text);
+                // Servers without the protocol only ever send the sentence
+                let tagged =
+                    *tagged.get_or_insert(matches!(msg, Some(ServerMessage::Hello { .. })));
+                match msg {
+                    Some(msg) if tagged => msg.encode(),
+                    _ if tagged => continue,
+                    _ => ServerMessage::SentenceUpdate {
+                        version: 0,
+                        sentence: text,
+                        author: None,
+                    }
+                    .encode(),
+                }
+            }
+            Message::Close(frame) => {
+                let reason = frame.map(|f| f.reason.to_string()).unwrap_or_default();
+                emit(&closed(&reason))?;
+                return Ok(());
+            }
+            _ => continue,
+        };
+        emit(&line)?;
+    }
+
+    emit(&closed(""))?;
+    Ok(())
+}
+
+/// The last line, shaped like the server's messages
+fn closed(reason: &str) -> String {
+    serde_json::json!({ "type": "closed", "data": { "reason": reason } }).to_string()
+}
+
+/// Writes one line and flushes right away, stdout is usually a pipe here
+fn emit(line: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    writeln!(stdout, "{}", line)?;
+    stdout.flush()
+}
@@ -0,0 +1,144 @@
+//! Past sessions, kept for the history screen.
+//!
+//! Every online session we typed in and every finished practice test adds one
+//! line of JSON to `~/.local/share/chaostype/history.jsonl`. Lines that don't
+//! parse are skipped, and any problem with the file just leaves the history
+//! empty or the session unrecorded.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Unix time the session ended
+    pub ended_at: u64,
+    /// The server we played on, `None` for practice
+    pub server: Option<String>,
+    pub wpm: f64,
+    pub accuracy: f64,
+    #[serde(rename = "secs", with = "whole_secs")]
+    pub duration: Duration,
+}
+
+impl Session {
+    /// A session that ends now
+    pub fn new(server: Option<String>, wpm: f64, accuracy: f64, duration: Duration) -> Session {
+        let ended_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        Session {
+            ended_at,
+            server,
+            wpm,
+            accuracy,
+            duration,
+        }
+    }
+}
+
+/// Durations are kept to the second
+mod whole_secs {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+}
+
+/// Every recorded session, oldest first
+pub fn load() -> Vec<Session> {
+    let Some(contents) = history_path().and_then(|path| fs::read_to_string(path).ok()) else {
+        return Vec::new();
+    };
+    contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+pub fn record(session: &Session) {
+    let Some(path) = history_path() else {
+        return;
+    };
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let Ok(line) = serde_json::to_string(session) else {
+        return;
+    };
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    if cfg!(test) {
+        return None;
+    }
+    let data_dir = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+        })?;
+    Some(data_dir.join("chaostype").join("history.jsonl"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(server: Option<&str>) -> Session {
+        Session {
+            ended_at: 1_700_000_000,
+            server: server.map(String::from),
+            wpm: 61.5,
+            accuracy: 97.2,
+            duration: Duration::from_secs(95),
+        }
+    }
+
+    #[test]
+    fn sessions_read_back_what_was_written() {
+        for server in [
+            None,
+            Some("127.0.0.1:9001"),
+            Some(r#"odd,"server":"name\with}"#),
+            Some("wss://tést.example/✓\n"),
+        ] {
+            let written = session(server);
+            let line = serde_json::to_string(&written).unwrap();
+            assert_eq!(serde_json::from_str::<Session>(&line).unwrap(), written);
+        }
+    }
+
+    #[test]
+    fn lines_keep_their_format() {
+        let line = serde_json::to_string(&session(None)).unwrap();
+        assert_eq!(
+            line,
+            r#"{"ended_at":1700000000,"server":null,"wpm":61.5,"accuracy":97.2,"secs":95}"#
+        );
+    }
+
+    #[test]
+    fn broken_lines_are_skipped() {
+        for line in [
+            "",
+            "not json",
+            r#"{"ended_at":1,"server":"open,"wpm":1.0,"accuracy":1.0,"secs":1}"#,
+            r#"{"ended_at":1,"server":null,"wpm":1.0,"accuracy":1.0}"#,
+        ] {
+            assert!(serde_json::from_str::<Session>(line).is_err(), "{line}");
+        }
+    }
+}
@@ -1,26 +1,62 @@
+mod cli;
+mod config;
+mod events;
+mod history;
+mod net;
+mod practice;
+mod prefs;
+mod race;
+mod rhythm;
+mod theme;
+
+use cli::{apply_config, check_name, connect_url, parse_args, server_url};
+use config::Config;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+    cursor,
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use futures::{SinkExt, StreamExt};
+use history::Session;
+use net::{
+    run_websocket_client, MAX_QUIET_RECONNECTS, MAX_RECONNECTS, MAX_RECONNECT_DELAY,
+    RECONNECT_BASE_DELAY,
+};
+use practice::Practice;
+use prefs::Prefs;
+use protocol::{
+    wordlist::{WordList, WordOptions},
+    ClientMessage, Player, RaceState, RoundState, Score, ServerMessage, TurnState, LOBBY,
+    MAX_NAME_CHARS, PROTOCOL_VERSION,
+};
+use race::RaceView;
+use rand::{rngs::StdRng, Rng};
 use ratatui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Clear, Paragraph, Wrap},
     Frame, Terminal,
 };
+use rhythm::{Report, Rhythm};
 use std::{
+    borrow::Cow,
+    cell::Cell,
+    collections::{HashSet, VecDeque},
     env,
     error::Error,
-    io,
+    fs, io,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use theme::Theme;
 use tokio::sync::mpsc;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
 
 #[derive(Debug, Clone, PartialEq)]
 enum AppState {
@@ -28,11 +64,398 @@ enum AppState {
     Connecting,
     Connected,
     Disconnected,
+    /// Typing test against local words, no server involved
+    Practice,
+    /// Past sessions
+    History,
+}
+
+/// What to do with a bracketed paste that spans several lines
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PasteMode {
+    /// Submit every non-empty line on its own
+    Split,
+    /// Join the lines with spaces and submit them once
+    Join,
+    /// Refuse the paste and show a notice
+    Reject,
+}
+
+impl PasteMode {
+    fn parse(value: &str) -> Option<PasteMode> {
+        match value {
+            "split" => Some(PasteMode::Split),
+            "join" => Some(PasteMode::Join),
+            "reject" => Some(PasteMode::Reject),
+            _ => None,
+        }
+    }
+}
+
+/// How the sentence is drawn, purely cosmetic
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DisplayStyle {
+    Plain,
+    /// Every word in its own highlighted box
+    Boxed,
+    /// Letters spread apart like a typewriter
+    Spaced,
+}
+
+impl DisplayStyle {
+    fn parse(value: &str) -> Option<DisplayStyle> {
+        match value {
+            "plain" => Some(DisplayStyle::Plain),
+            "boxed" => Some(DisplayStyle::Boxed),
+            "spaced" => Some(DisplayStyle::Spaced),
+            _ => None,
+        }
+    }
+}
+
+/// Client options set from the command line
+#[derive(Debug, Clone)]
+struct Settings {
+    paste_mode: PasteMode,
+    /// Lowercased words masked out of the displayed sentence
+    client_filter: HashSet<String>,
+    /// Show sync details such as the sentence version
+    debug: bool,
+    /// Start with the stats and controls row hidden
+    hide_stats: bool,
+    display_style: DisplayStyle,
+    theme: Theme,
+    /// Seed for anything random the client does, shown in the debug footer
+    seed: Option<u64>,
+    /// Keep showing the game while retrying a dropped connection
+    quiet_reconnect: bool,
+    /// Time keystrokes and show a rhythm report once disconnected
+    rhythm_report: bool,
+    /// Text the input starts with, again after every submit
+    prefill: String,
+    /// Only render the last this many characters of the sentence
+    render_cap: Option<usize>,
+    /// Typing speed to aim for, shown next to the current one
+    wpm_goal: Option<u32>,
+    /// Shortest time between two redraws, for terminals that flicker
+    min_redraw: Duration,
+    /// No TUI, print every server message as a line of JSON instead
+    json_events: bool,
+    /// Name to play under, a random guest name otherwise
+    name: Option<String>,
+    /// Room to play in, lowercased
+    room: String,
+    /// Where saved sentences go, the working directory otherwise
+    export_dir: Option<PathBuf>,
+    /// Give up on a connection attempt, name lookup included, after this long
+    connect_timeout: Duration,
+    /// Words offline practice draws from
+    practice_words: WordList,
+    /// How many practice words, and whether they come with capitals,
+    /// punctuation and numbers
+    practice: WordOptions,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            paste_mode: PasteMode::Split,
+            client_filter: HashSet::new(),
+            debug: false,
+            hide_stats: false,
+            display_style: DisplayStyle::Plain,
+            theme: Theme::DEFAULT,
+            seed: None,
+            quiet_reconnect: false,
+            rhythm_report: false,
+            prefill: String::new(),
+            render_cap: None,
+            wpm_goal: None,
+            min_redraw: Duration::ZERO,
+            json_events: false,
+            name: None,
+            room: LOBBY.to_string(),
+            export_dir: None,
+            connect_timeout: Duration::from_secs(10),
+            practice_words: WordList::built_in(),
+            practice: WordOptions {
+                count: practice::TEST_WORDS,
+                capitalize: false,
+                punctuation: false,
+                numbers: false,
+            },
+        }
+    }
+}
+
+/// Something the user can trigger, from a key or the command palette
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Connect,
+    Disconnect,
+    ToggleHelp,
+    ToggleStats,
+    Undo,
+    Redo,
+    Retract,
+    Clear,
+    ToggleLeaderboard,
+    ToggleChat,
+    CopySentence,
+    SaveSentence,
+    ResetStats,
+    TogglePause,
+    NextServer,
+    EditName,
+    Practice,
+    History,
+    CancelReconnect,
+    NextTheme,
+    Quit,
+}
+
+impl Action {
+    const ALL: [Action; 21] = [
+        Action::Connect,
+        Action::Disconnect,
+        Action::ToggleHelp,
+        Action::ToggleStats,
+        Action::Undo,
+        Action::Redo,
+        Action::Retract,
+        Action::Clear,
+        Action::ToggleLeaderboard,
+        Action::ToggleChat,
+        Action::CopySentence,
+        Action::SaveSentence,
+        Action::ResetStats,
+        Action::TogglePause,
+        Action::NextServer,
+        Action::EditName,
+        Action::Practice,
+        Action::History,
+        Action::CancelReconnect,
+        Action::NextTheme,
+        Action::Quit,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Action::Connect => "Connect to server",
+            Action::Disconnect => "Disconnect / back to menu",
+            Action::ToggleHelp => "Toggle help",
+            Action::ToggleStats => "Toggle stats pane",
+            Action::Undo => "Undo edit, or take back my last word",
+            Action::Redo => "Redo edit",
+            Action::Retract => "Take back all my words",
+            Action::Clear => "Clear the sentence for everyone",
+            Action::ToggleLeaderboard => "Toggle leaderboard",
+            Action::ToggleChat => "Switch between words and chat",
+            Action::CopySentence => "Copy the sentence to the clipboard",
+            Action::SaveSentence => "Save the sentence to a file",
+            Action::ResetStats => "Reset my speed and accuracy",
+            Action::TogglePause => "Pause, away from the keyboard",
+            Action::NextServer => "Switch to next server",
+            Action::EditName => "Change my name",
+            Action::Practice => "Practice offline",
+            Action::History => "Browse past sessions",
+            Action::CancelReconnect => "Stop reconnecting",
+            Action::NextTheme => "Switch to the next color theme",
+            Action::Quit => "Quit",
+        }
+    }
+
+    fn key_hint(self) -> &'static str {
+        match self {
+            Action::Connect => "Enter",
+            Action::Disconnect => "Esc",
+            Action::ToggleHelp => "H / F1",
+            Action::ToggleStats => "F3",
+            Action::Undo => "Ctrl+Z",
+            Action::Redo => "Ctrl+Y",
+            Action::Retract => "Ctrl+R",
+            Action::Clear => "Ctrl+L",
+            Action::ToggleLeaderboard => "F2",
+            Action::ToggleChat => "Tab",
+            Action::CopySentence => "F4",
+            Action::SaveSentence => "Ctrl+S",
+            Action::ResetStats => "F5",
+            Action::TogglePause => "F6",
+            Action::NextServer => "Tab",
+            Action::EditName => "N",
+            Action::Practice => "P",
+            Action::History => "S",
+            Action::CancelReconnect => "C",
+            Action::NextTheme => "F7",
+            Action::Quit => "Q",
+        }
+    }
+
+    fn is_available(self, state: &AppState) -> bool {
+        match self {
+            Action::Connect => matches!(state, AppState::Welcome | AppState::Disconnected),
+            Action::Disconnect => *state != AppState::Welcome,
+            Action::ToggleStats
+            | Action::Undo
+            | Action::Redo
+            | Action::Retract
+            | Action::Clear
+            | Action::ToggleLeaderboard
+            | Action::ToggleChat
+            | Action::CopySentence
+            | Action::SaveSentence
+            | Action::ResetStats
+            | Action::TogglePause => *state == AppState::Connected,
+            Action::NextServer | Action::EditName | Action::History => *state == AppState::Welcome,
+            Action::Practice => matches!(state, AppState::Welcome | AppState::Practice),
+            Action::CancelReconnect => *state == AppState::Disconnected,
+            Action::ToggleHelp | Action::NextTheme | Action::Quit => true,
+        }
+    }
+}
+
+/// Key bindings for every state; keys not listed here are handled as typing
+fn action_for_key(state: &AppState, key: KeyEvent) -> Option<Action> {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    match (state, key.code) {
+        (AppState::Connected, KeyCode::Char('z')) if ctrl => Some(Action::Undo),
+        (AppState::Connected, KeyCode::Char('y')) if ctrl => Some(Action::Redo),
+        (AppState::Connected, KeyCode::Char('r')) if ctrl => Some(Action::Retract),
+        (AppState::Connected, KeyCode::Char('l')) if ctrl => Some(Action::Clear),
+        (AppState::Connected, KeyCode::Char('s')) if ctrl => Some(Action::SaveSentence),
+        (AppState::Welcome | AppState::Disconnected, KeyCode::Enter) => Some(Action::Connect),
+        (AppState::Welcome, KeyCode::Tab) => Some(Action::NextServer),
+        (AppState::Welcome, KeyCode::Char('n' | 'N')) => Some(Action::EditName),
+        (AppState::Welcome, KeyCode::Char('p' | 'P')) => Some(Action::Practice),
+        (AppState::Welcome, KeyCode::Char('s' | 'S')) => Some(Action::History),
+        (AppState::Disconnected, KeyCode::Char('c' | 'C')) => Some(Action::CancelReconnect),
+        (AppState::Connected, KeyCode::Tab) => Some(Action::ToggleChat),
+        (AppState::Connected, KeyCode::Esc)
+        | (AppState::Connecting, KeyCode::Esc)
+        | (AppState::Disconnected, KeyCode::Esc)
+        | (AppState::Practice, KeyCode::Esc)
+        | (AppState::History, KeyCode::Esc) => Some(Action::Disconnect),
+        (AppState::Welcome, KeyCode::Char('h' | 'H')) | (AppState::Connected, KeyCode::F(1)) => {
+            Some(Action::ToggleHelp)
+        }
+        (AppState::Connected, KeyCode::F(2)) => Some(Action::ToggleLeaderboard),
+        (AppState::Connected, KeyCode::F(3)) => Some(Action::ToggleStats),
+        (AppState::Connected, KeyCode::F(4)) => Some(Action::CopySentence),
+        (AppState::Connected, KeyCode::F(5)) => Some(Action::ResetStats),
+        (AppState::Connected, KeyCode::F(6)) => Some(Action::TogglePause),
+        (_, KeyCode::F(7)) => Some(Action::NextTheme),
+        (AppState::Connected | AppState::Practice, KeyCode::Char(_)) => None,
+        (_, KeyCode::Char('q' | 'Q')) => Some(Action::Quit),
+        _ => None,
+    }
+}
+
+/// Chat lines kept, older ones are dropped
+const MAX_CHAT_HISTORY: usize = 200;
+
+/// Chat lines shown at once
+const CHAT_PANE_LINES: u16 = 5;
+
+/// Sessions PgUp/PgDn scroll the history by
+const HISTORY_PAGE: usize = 10;
+
+/// Width of the players panel beside the sentence
+const PLAYERS_PANEL_WIDTH: u16 = 30;
+
+/// Narrower screens leave the whole width to the sentence
+const PLAYERS_PANEL_MIN_SCREEN: u16 = 80;
+
+/// Lines one PgUp/PgDn scrolls the chat
+const CHAT_SCROLL_STEP: isize = 3;
+
+/// Lines one PgUp/PgDn scrolls the sentence
+const SENTENCE_SCROLL_STEP: i16 = 3;
+
+/// How often our speed is sent for the leaderboard
+const WPM_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a first Ctrl+R or Ctrl+L waits for the confirming second one
+const CONFIRM_WINDOW: Duration = Duration::from_secs(3);
+
+/// The round clock turns red for the last few seconds
+const ROUND_HURRY_SECS: u64 = 10;
+
+/// How long the stats pane says where the sentence was copied or saved to
+const COPY_STATUS_TIME: Duration = Duration::from_secs(3);
+
+/// How long the input border stays red after the server turned a word down
+const REJECT_FLASH: Duration = Duration::from_millis(500);
+
+/// Most edits of the current input that can be undone
+const MAX_EDIT_HISTORY: usize = 100;
+
+/// Undo/redo stacks for the word being composed, cleared on submit
+#[derive(Debug, Default)]
+struct EditHistory {
+    undo: Vec<String>,
+    redo: Vec<String>,
+}
+
+impl EditHistory {
+    /// Remembers the input as it was before an edit
+    fn record(&mut self, before: String) {
+        if self.undo.len() == MAX_EDIT_HISTORY {
+            self.undo.remove(0);
+        }
+        self.undo.push(before);
+        self.redo.clear();
+    }
+
+    /// Returns `false` when there was nothing to undo
+    fn undo(&mut self, current: &mut String) -> bool {
+        let Some(previous) = self.undo.pop() else {
+            return false;
+        };
+        self.redo.push(std::mem::replace(current, previous));
+        true
+    }
+
+    fn redo(&mut self, current: &mut String) {
+        if let Some(next) = self.redo.pop() {
+            self.undo.push(std::mem::replace(current, next));
+        }
+    }
+
+    fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+/// The ':' command palette
+#[derive(Debug, Default)]
+struct Palette {
+    query: String,
+    selected: usize,
+}
+
+impl Palette {
+    fn matches(&self, state: &AppState) -> Vec<Action> {
+        let query = self.query.to_lowercase();
+        Action::ALL
+            .into_iter()
+            .filter(|action| action.is_available(state))
+            .filter(|action| action.label().to_lowercase().contains(&query))
+            .collect()
+    }
 }
 
 #[derive(Debug)]
 pub enum AppEvent {
     SendWord(String),
+    Retract,
+    UndoWord,
+    Clear,
+    ReportWpm(u32),
+    SendChat(String),
+    Join,
+    /// Characters of the race passage typed correctly so far
+    RaceProgress(usize),
     Connect,
     Disconnect,
     Quit,
@@ -45,13 +468,132 @@ struct App {
     sentence: String,
     connection_status: String,
     users_count: usize,
+    /// Players typing rather than watching, when the server tells us
+    active_typists: Option<usize>,
+    /// Watching only, the server wants us to join before typing
+    lurking: bool,
     typing_speed: f64,
+    /// `--wpm-goal` was reached this session, celebrated once
+    goal_reached: bool,
     start_time: Option<Instant>,
+    /// Away from the keyboard since then, typing and the speed clock are on hold
+    paused_at: Option<Instant>,
+    /// Time spent paused before the current pause, left out of the speed
+    paused_total: Duration,
     chars_typed: usize,
+    /// Characters and backspaces typed into the word input
+    keystrokes: usize,
+    /// Characters typed into the word input since the last word was sent
+    typed_since_send: usize,
+    /// Typed characters that made it into a sent word
+    accepted_chars: usize,
+    /// Percentage, from the first sent word on
+    accuracy: Option<f64>,
     error_message: Option<String>,
+    /// The last session ended because the server went away on purpose
+    server_shut_down: bool,
+    /// What a left click does where, from the last frame, topmost last
+    click_areas: Vec<(Rect, Action)>,
+    notice: Option<String>,
     server_url: String,
+    /// Name we tell the server once it greets us
+    username: String,
+    /// Servers Tab cycles through on the welcome screen, `server_url` is the selected one
+    servers: Vec<String>,
+    server_index: usize,
     should_quit: bool,
     show_help: bool,
+    settings: Settings,
+    /// Set while waiting for the server's first frame
+    awaiting_hello: bool,
+    /// The sentence from the server arrived since connecting, until then
+    /// whatever we show may be stale or just not there yet
+    synced: bool,
+    /// Protocol spoken by the server, 0 for servers that predate the JSON
+    /// protocol and only ever send the raw sentence
+    server_protocol: u32,
+    /// Whether we already told the player an untagged server only gives us
+    /// the raw sentence
+    compat_notice_shown: bool,
+    /// Start and end of the current submit cooldown sent by the server
+    cooldown: Option<(Instant, Instant)>,
+    /// When the server announced it will go away
+    shutdown_at: Option<Instant>,
+    /// When the server last turned down one of our words
+    rejected_at: Option<Instant>,
+    /// Outcome of the last copy or save of the sentence, and when it happened
+    copy_status: Option<(Instant, Result<String, String>)>,
+    /// Timed round on servers that play them, with the seconds left
+    round: Option<RoundState>,
+    /// Final sentence and scores of the round that just ended, shown until the
+    /// next one starts
+    round_results: Option<(String, Vec<Score>)>,
+    /// Whose turn it is, on servers playing turns
+    turn: Option<TurnState>,
+    /// The race and what we typed of it, on servers started with `--race`
+    race: Option<RaceView>,
+    /// The offline typing test, while practicing
+    practice: Option<Practice>,
+    /// The finished test made it into the history
+    practice_recorded: bool,
+    /// Past sessions, newest first, read when the history screen opens
+    history: Vec<Session>,
+    /// Sessions scrolled past at the top of the history screen
+    history_scroll: usize,
+    /// Round trip of our last answered ping, kept while a pong is late
+    latency_ms: Option<u64>,
+    palette: Option<Palette>,
+    /// Name being typed on the welcome screen, with why the last one was refused
+    name_edit: Option<(String, Option<String>)>,
+    /// Bottom stats and controls row hidden to give the sentence more room
+    hide_stats: bool,
+    /// Server run and last sentence version we saw, sent on reconnect to only fetch what we missed
+    server_session: Option<String>,
+    last_version: Option<u64>,
+    /// Crate version the server announced, servers before protocol 15 don't
+    server_version: Option<String>,
+    /// Quiet reconnect attempt in progress, the game screen stays up meanwhile
+    reconnect_attempt: Option<u32>,
+    /// Reconnect attempt made from the disconnected screen, once quiet ones are
+    /// used up or off, and when it's due
+    retry: Option<(u32, Instant)>,
+    rng: StdRng,
+    edit_history: EditHistory,
+    /// Word budget announced by the server, the sentence resets when it's used up
+    max_words: Option<usize>,
+    /// What the room shows between words, when it isn't a space
+    separator: Option<String>,
+    /// Words sent but not yet acked, the server answers them in order
+    unacked: VecDeque<String>,
+    /// Keystroke timing, only recorded with `--rhythm-report`
+    rhythm: Option<Rhythm>,
+    /// When taking back our words was first asked for, waiting for confirmation
+    retract_armed: Option<Instant>,
+    /// Same for clearing the sentence
+    clear_armed: Option<Instant>,
+    /// Who wrote each word, for the sentence version it was sent with
+    authors: Option<(u64, Vec<Option<String>>)>,
+    /// Fastest players as last ranked by the server
+    leaderboard: Vec<(String, u32)>,
+    /// Everyone in the room, for the players panel
+    players: Vec<Player>,
+    /// Who wrote the most, as last ranked by the server
+    scoreboard: Vec<Score>,
+    show_leaderboard: bool,
+    /// When our speed was last sent for the leaderboard
+    last_wpm_report: Option<Instant>,
+    /// The input goes to the chat instead of the sentence
+    chat_mode: bool,
+    /// Whatever was in the other input when switching between word and chat
+    other_input: String,
+    /// `name: message` lines, oldest first
+    chat_history: Vec<String>,
+    /// Chat lines scrolled back from the newest one
+    chat_scroll: usize,
+    /// First sentence line shown, `None` keeps up with the newest words
+    sentence_scroll: Option<u16>,
+    /// Furthest the sentence scrolled on the last frame, set while drawing
+    sentence_scroll_max: Cell<u16>,
 }
 
 impl Default for App {
@@ -62,32 +604,97 @@ impl Default for App {
             sentence: String::new(),
             connection_status: "Not connected".to_string(),
             users_count: 1,
+            active_typists: None,
+            lurking: false,
             typing_speed: 0.0,
+            goal_reached: false,
             start_time: None,
+            paused_at: None,
+            paused_total: Duration::ZERO,
             chars_typed: 0,
+            keystrokes: 0,
+            typed_since_send: 0,
+            accepted_chars: 0,
+            accuracy: None,
             error_message: None,
-            server_url: "ws://127.0.0.1:9001".to_string(),
+            server_shut_down: false,
+            click_areas: Vec::new(),
+            notice: None,
+            server_url: server_url("127.0.0.1"),
+            username: "Guest".to_string(),
+            servers: vec!["127.0.0.1".to_string()],
+            server_index: 0,
             should_quit: false,
             show_help: false,
+            settings: Settings::default(),
+            awaiting_hello: false,
+            synced: false,
+            server_protocol: PROTOCOL_VERSION,
+            compat_notice_shown: false,
+            cooldown: None,
+            shutdown_at: None,
+            rejected_at: None,
+            copy_status: None,
+            round: None,
+            round_results: None,
+            turn: None,
+            latency_ms: None,
+            palette: None,
+            name_edit: None,
+            hide_stats: false,
+            server_session: None,
+            server_version: None,
+            last_version: None,
+            reconnect_attempt: None,
+            retry: None,
+            rng: protocol::rng::new(None),
+            edit_history: EditHistory::default(),
+            max_words: None,
+            separator: None,
+            unacked: VecDeque::new(),
+            rhythm: None,
+            retract_armed: None,
+            clear_armed: None,
+            authors: None,
+            leaderboard: Vec::new(),
+            players: Vec::new(),
+            scoreboard: Vec::new(),
+            race: None,
+            practice: None,
+            practice_recorded: false,
+            history: Vec::new(),
+            history_scroll: 0,
+            show_leaderboard: false,
+            last_wpm_report: None,
+            chat_mode: false,
+            other_input: String::new(),
+            chat_history: Vec::new(),
+            chat_scroll: 0,
+            sentence_scroll: None,
+            sentence_scroll_max: Cell::new(0),
         }
     }
 }
 
 impl App {
-    fn new(server_url: String) -> App {
+    fn new(servers: Vec<String>, settings: Settings) -> App {
+        let hide_stats = settings.hide_stats;
+        let seed = settings.seed;
+        let rhythm = settings.rhythm_report.then(Rhythm::default);
+        let mut rng = protocol::rng::new(seed);
+        let username = settings
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("Guest-{:04}", rng.gen_range(0..10000)));
         App {
-            state: AppState::Welcome,
-            current_input: String::new(),
-            sentence: String::new(),
-            connection_status: "Not connected".to_string(),
-            users_count: 1,
-            typing_speed: 0.0,
-            start_time: None,
-            chars_typed: 0,
-            error_message: None,
-            server_url,
-            should_quit: false,
-            show_help: false,
+            server_url: server_url(&servers[0]),
+            username,
+            servers,
+            settings,
+            hide_stats,
+            rng,
+            rhythm,
+            ..App::default()
         }
     }
 }
@@ -97,322 +704,1357 @@ impl App {
         self.state = AppState::Connecting;
         self.connection_status = "Connecting...".to_string();
         self.error_message = None;
+        self.server_shut_down = false;
     }
 
     fn set_connected(&mut self) {
         self.state = AppState::Connected;
         self.connection_status = "Connected".to_string();
-        self.start_time = Some(Instant::now());
+        self.retry = None;
+        // A quiet reconnect carries on with the same stats and chat
+        let quiet = self.reconnect_attempt.take().is_some();
+        if !quiet {
+            self.start_time = Some(Instant::now());
+            self.paused_at = None;
+            self.paused_total = Duration::ZERO;
+            self.goal_reached = false;
+            if self.rhythm.is_some() {
+                self.rhythm = Some(Rhythm::default());
+            }
+            // Becomes the default server next time
+            let mut prefs = Prefs::load();
+            prefs.remember_server(&self.servers[self.server_index]);
+            prefs.save();
+        }
+        if self.current_input.is_empty() {
+            self.current_input = self.settings.prefill.clone();
+        }
+        self.awaiting_hello = true;
+        self.synced = false;
+        self.lurking = false;
+        self.active_typists = None;
+        self.max_words = None;
+        self.separator = None;
+        self.authors = None;
+        self.leaderboard.clear();
+        self.players.clear();
+        self.scoreboard.clear();
+        self.last_wpm_report = None;
+        if !quiet {
+            self.chat_history.clear();
+            self.chat_scroll = 0;
+        }
+        self.sentence_scroll = None;
+        // The connection dropped before these were acked, so they may or may
+        // not be in the sentence the server resyncs us with
+        if quiet && !self.unacked.is_empty() {
+            let words: Vec<&str> = self.unacked.iter().map(String::as_str).collect();
+            self.notice = Some(format!(
+                "Reconnected, check these made it: {}",
+                words.join(" ")
+            ));
+        }
+        self.unacked.clear();
+        self.shutdown_at = None;
+        self.round = None;
+        self.round_results = None;
+        self.turn = None;
+        self.race = None;
+        self.latency_ms = None;
+        self.server_version = None;
     }
 
     fn set_disconnected(&mut self, error: Option<String>) {
+        self.record_session();
         self.state = AppState::Disconnected;
+        self.reconnect_attempt = None;
         self.connection_status = "Disconnected".to_string();
         if let Some(err) = error {
             self.error_message = Some(err);
         }
     }
 
-    fn send_word(&mut self) -> Option<String> {
-        if !self.current_input.trim().is_empty() {
-            let word = self.current_input.trim().to_string();
-            self.current_input.clear();
-            self.chars_typed += word.len() + 1;
-            self.update_typing_speed();
-            Some(word)
-        } else {
-            None
-        }
+    /// The server closed every connection because it's stopping
+    fn set_server_shut_down(&mut self) {
+        self.set_disconnected(None);
+        self.error_message = None;
+        self.server_shut_down = true;
     }
 
-    fn update_typing_speed(&mut self) {
-        if let Some(start) = self.start_time {
-            let elapsed = start.elapsed().as_secs_f64();
-            if elapsed > 0.0 {
-                let words = self.chars_typed as f64 / 5.0;
-                self.typing_speed = (words / elapsed) * 60.0;
-            }
-        }
+    /// Keeps the game screen up while retrying, returns how long to wait first
+    fn start_quiet_reconnect(&mut self, attempt: u32) -> Duration {
+        self.reconnect_attempt = Some(attempt);
+        self.connection_status = "Reconnecting...".to_string();
+        self.reconnect_delay(attempt)
     }
 
-    fn update_sentence(&mut self, new_sentence: String) {
-        self.sentence = new_sentence;
+    /// Retries from the disconnected screen, returns how long to wait first
+    fn schedule_reconnect(&mut self, attempt: u32) -> Duration {
+        let delay = self.reconnect_delay(attempt);
+        self.retry = Some((attempt, Instant::now() + delay));
+        delay
     }
 
-    fn toggle_help(&mut self) {
-        self.show_help = !self.show_help;
+    /// Doubles with every attempt up to `MAX_RECONNECT_DELAY`, plus some
+    /// jitter so clients dropped together don't all come back at once
+    fn reconnect_delay(&mut self, attempt: u32) -> Duration {
+        let backoff = RECONNECT_BASE_DELAY
+            .saturating_mul(1 << attempt.saturating_sub(1).min(16))
+            .min(MAX_RECONNECT_DELAY);
+        backoff + Duration::from_millis(self.rng.gen_range(0..250))
     }
-}
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    // Parse command line arguments
-    let args: Vec<String> = env::args().collect();
-    let server_ip = if args.len() > 1 {
-        &args[1]
-    } else {
-        "127.0.0.1"
-    };
+    fn send_word(&mut self) -> Option<String> {
+        let word = self.current_input.trim().to_string();
+        if word.is_empty() || !self.submit(&word) {
+            return None;
+        }
+        self.current_input = self.settings.prefill.clone();
+        self.edit_history.clear();
+        // Prefilled or pasted characters weren't typed
+        self.accepted_chars += word.chars().count().min(self.typed_since_send);
+        self.typed_since_send = 0;
+        self.sentence_scroll = None;
+        Some(word)
+    }
 
-    // Validate IP format (basic check)
-    if !is_valid_ip_or_hostname(server_ip) {
-        eprintln!("Error: Invalid IP address or hostname: {}", server_ip);
-        eprintln!("Usage: {} [IP_ADDRESS|HOSTNAME]", args[0]);
-        eprintln!("Example: {} 192.168.1.100", args[0]);
-        std::process::exit(1);
+    /// Counts `word` as sent and waits for its ack, unless it can't go out
+    /// now. Typed and pasted words both come through here.
+    fn submit(&mut self, word: &str) -> bool {
+        if self.is_cooling_down() {
+            return false;
+        }
+        if self.reconnect_attempt.is_some() {
+            self.notice = Some("Reconnecting, hold on...".to_string());
+            return false;
+        }
+        self.record_submission(word);
+        // Untagged servers never answer
+        if self.server_protocol != 0 {
+            self.unacked.push_back(word.to_string());
+        }
+        true
     }
 
-    let server_url = format!("ws://{}:9001", server_ip);
+    fn record_keystroke(&mut self, backspace: bool) {
+        self.keystrokes += 1;
+        if !backspace {
+            self.typed_since_send += 1;
+        }
+        if let Some(rhythm) = self.rhythm.as_mut() {
+            rhythm.key(Instant::now());
+        }
+    }
 
-    // Setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    /// Applies an edit to the input, keeping it undoable
+    fn edit_input(&mut self, edit: impl FnOnce(&mut String)) {
+        let before = self.current_input.clone();
+        edit(&mut self.current_input);
+        if self.current_input != before {
+            self.edit_history.record(before);
+        }
+    }
 
-    let app = Arc::new(Mutex::new(App::new(server_url)));
-    let (event_tx, event_rx) = mpsc::unbounded_channel::<AppEvent>();
+    /// Words are turned away until the server starts the next round
+    fn round_over(&self) -> bool {
+        matches!(self.round, Some(RoundState::Over { .. }))
+    }
 
-    // Spawn WebSocket client task
-    let app_clone = Arc::clone(&app);
-    let ws_handle = tokio::spawn(async move {
-        run_websocket_client(app_clone, event_rx).await;
-    });
+    /// Someone else is to add the next word, ours would be turned down
+    fn waiting_for_turn(&self) -> bool {
+        matches!(self.turn, Some(TurnState::Waiting(_)))
+    }
 
-    let res = run_app(&mut terminal, app, event_tx).await;
+    /// Takes the chat message out of the input, if there is one
+    fn send_chat(&mut self) -> Option<String> {
+        // It would be dropped before reaching the server, keep it for later
+        if self.reconnect_attempt.is_some() {
+            self.notice = Some("Reconnecting, hold on...".to_string());
+            return None;
+        }
+        let text = std::mem::take(&mut self.current_input);
+        self.edit_history.clear();
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        self.chat_scroll = 0;
+        Some(text.to_string())
+    }
 
-    // Cleanup
-    ws_handle.abort();
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+    fn scroll_chat(&mut self, lines: isize) {
+        let max = self.chat_history.len().saturating_sub(1);
+        self.chat_scroll = self.chat_scroll.saturating_add_signed(lines).min(max);
+    }
 
-    if let Err(err) = res {
-        println!("{err:?}");
+    /// Scrolling back down to the end follows new words again
+    fn scroll_sentence(&mut self, lines: i16) {
+        let max = self.sentence_scroll_max.get();
+        let top = self
+            .sentence_scroll
+            .unwrap_or(max)
+            .saturating_add_signed(lines)
+            .min(max);
+        self.sentence_scroll = (top < max).then_some(top);
     }
 
-    Ok(())
-}
+    fn record_submission(&mut self, word: &str) {
+        self.notice = None;
+        self.chars_typed += word.chars().count() + 1;
+        self.update_typing_speed();
+        self.update_accuracy();
+    }
 
-async fn run_websocket_client(
-    app: Arc<Mutex<App>>,
-    mut event_rx: mpsc::UnboundedReceiver<AppEvent>,
-) {
-    loop {
-        // Wait for connect event
-        while let Some(event) = event_rx.recv().await {
-            match event {
-                AppEvent::Connect => {
-                    let url = {
-                        let app_lock = app.lock().unwrap();
-                        app_lock.server_url.clone()
-                    };
-
-                    // Attempt connection
-                    match connect_async(&url).await {
-                        Ok((ws_stream, _)) => {
-                            {
-                                let mut app_lock = app.lock().unwrap();
-                                app_lock.set_connected();
-                            }
-
-                            let (mut write, mut read) = ws_stream.split();
-
-                            // Handle the WebSocket connection
-                            loop {
-                                tokio::select! {
-                                    // Handle incoming WebSocket messages
-                                    msg_result = read.next() => {
-                                        match msg_result {
-                                            Some(Ok(msg)) => {
-                                                if let Ok(text) = msg.to_text() {
-                                                    let mut app_lock = app.lock().unwrap();
-                                                    app_lock.update_sentence(text.to_string());
-                                                }
-                                            }
-                                            Some(Err(_)) | None => {
-                                                let mut app_lock = app.lock().unwrap();
-                                                app_lock.set_disconnected(Some("Connection lost".to_string()));
-                                                break;
-                                            }
-                                        }
-                                    }
-
-                                    // Handle outgoing events
-                                    event = event_rx.recv() => {
-                                        match event {
-                                            Some(AppEvent::SendWord(word)) => {
-                                                if write.send(Message::Text(word)).await.is_err() {
-                                                    let mut app_lock = app.lock().unwrap();
-                                                    app_lock.set_disconnected(Some("Failed to send message".to_string()));
-                                                    break;
-                                                }
-                                            }
-                                            Some(AppEvent::Disconnect) => {
-                                                break;
-                                            }
-                                            Some(AppEvent::Quit) => {
-                                                return;
-                                            }
-                                            Some(AppEvent::Connect) => {
-                                                // Already connected, ignore
-                                            }
-                                            None => break,
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            let mut app_lock = app.lock().unwrap();
-                            app_lock.set_disconnected(Some(format!("Connection failed: {}", e)));
-                        }
-                    }
-                }
-                AppEvent::Quit => return,
-                _ => {} // Ignore other events when not connected
+    /// Applies the paste policy and returns the submissions to send, if any.
+    /// Single-line pastes just go into the input field.
+    fn paste(&mut self, text: &str) -> Vec<String> {
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        if lines.len() <= 1 {
+            if let Some(line) = lines.first() {
+                self.edit_input(|input| input.push_str(line));
             }
+            return Vec::new();
         }
+
+        let submissions: Vec<String> = match self.settings.paste_mode {
+            PasteMode::Split => lines.iter().map(|line| line.to_string()).collect(),
+            PasteMode::Join => vec![lines.join(" ")],
+            PasteMode::Reject => {
+                self.notice = Some(format!("Multi-line paste rejected ({} lines)", lines.len()));
+                return Vec::new();
+            }
+        };
+
+        submissions
+            .into_iter()
+            .filter(|word| self.submit(word))
+            .collect()
     }
-}
 
-async fn run_app<B: Backend>(
-    terminal: &mut Terminal<B>,
-    app: Arc<Mutex<App>>,
-    event_tx: mpsc::UnboundedSender<AppEvent>,
-) -> io::Result<()> {
-    let mut last_tick = Instant::now();
-    let tick_rate = Duration::from_millis(250);
+    fn start_cooldown(&mut self, retry_after: Duration, reason: String) {
+        let now = Instant::now();
+        self.cooldown = Some((now, now + retry_after));
+        if !reason.is_empty() {
+            self.notice = Some(reason);
+        }
+    }
 
-    loop {
-        let should_quit = {
-            let app_lock = app.lock().unwrap();
-            app_lock.should_quit
+    /// Fraction of the cooldown still left (1.0 = just started), if one is running
+    fn cooldown_progress(&self) -> Option<(Duration, f64)> {
+        let (start, until) = self.cooldown?;
+        let remaining = until.checked_duration_since(Instant::now())?;
+        let total = until.duration_since(start).as_secs_f64();
+        let ratio = if total > 0.0 {
+            remaining.as_secs_f64() / total
+        } else {
+            0.0
         };
+        Some((remaining, ratio))
+    }
 
-        if should_quit {
-            let _ = event_tx.send(AppEvent::Quit);
-            break;
+    fn is_cooling_down(&self) -> bool {
+        self.cooldown_progress().is_some()
+    }
+
+    /// Starts or ends a break, during which we can't type and our speed stands still
+    fn toggle_pause(&mut self) {
+        match self.paused_at.take() {
+            Some(at) => {
+                self.paused_total += at.elapsed();
+                self.notice = Some("Welcome back".to_string());
+            }
+            None => self.paused_at = Some(Instant::now()),
         }
+    }
 
-        terminal.draw(|f| ui(f, &app))?;
+    /// How long we've been playing since `start`, breaks left out
+    fn active_time(&self, start: Instant) -> Duration {
+        let paused = self.paused_total + self.paused_at.map_or(Duration::ZERO, |at| at.elapsed());
+        start.elapsed().saturating_sub(paused)
+    }
 
-        let timeout = tick_rate
-            .checked_sub(last_tick.elapsed())
-            .unwrap_or_else(|| Duration::from_secs(0));
+    fn update_typing_speed(&mut self) {
+        if self.paused_at.is_some() {
+            return;
+        }
+        if let Some(start) = self.start_time {
+            let active = self.active_time(start);
+            let elapsed = active.as_secs_f64();
+            if elapsed > 0.0 {
+                let words = self.chars_typed as f64 / 5.0;
+                self.typing_speed = (words / elapsed) * 60.0;
+            }
 
-        if crossterm::event::poll(timeout)? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    handle_key_event(key.code, &app, &event_tx).await;
+            // The first few words give wild speeds, don't celebrate those
+            if let Some(goal) = self.settings.wpm_goal {
+                if !self.goal_reached && active >= MIN_GOAL_TIME && self.typing_speed >= goal as f64
+                {
+                    self.goal_reached = true;
+                    self.notice = Some(format!("🎉 You reached your {} WPM goal!", goal));
                 }
             }
         }
+    }
 
-        if last_tick.elapsed() >= tick_rate {
-            let mut app_lock = app.lock().unwrap();
-            app_lock.update_typing_speed();
-            drop(app_lock);
-            last_tick = Instant::now();
+    /// Starts speed and accuracy over from now, for timing a fresh burst. Only
+    /// our own numbers, the sentence stays as it is.
+    fn reset_stats(&mut self) {
+        self.start_time = Some(Instant::now());
+        self.paused_total = Duration::ZERO;
+        if self.paused_at.is_some() {
+            self.paused_at = self.start_time;
         }
+        self.chars_typed = 0;
+        self.typing_speed = 0.0;
+        self.keystrokes = 0;
+        // What's in the input already wasn't typed since the reset
+        self.typed_since_send = 0;
+        self.accepted_chars = 0;
+        self.accuracy = None;
+        self.notice = Some("Speed and accuracy reset".to_string());
     }
 
-    Ok(())
-}
+    /// Share of keystrokes that ended up in a sent word. A backspace is a
+    /// keystroke too, so every correction costs twice: the wrong character and
+    /// its removal. Input thrown away without sending only adds keystrokes,
+    /// while Enter, including on an empty input, counts for nothing.
+    fn update_accuracy(&mut self) {
+        if self.keystrokes > 0 {
+            self.accuracy = Some(self.accepted_chars as f64 / self.keystrokes as f64 * 100.0);
+        }
+    }
 
-async fn handle_key_event(
-    key: KeyCode,
-    app: &Arc<Mutex<App>>,
-    event_tx: &mpsc::UnboundedSender<AppEvent>,
-) {
-    let mut app_lock = app.lock().unwrap();
+    /// Our speed when it's time to report it for the leaderboard again
+    fn wpm_report(&mut self) -> Option<u32> {
+        let due = match self.last_wpm_report {
+            Some(at) => at.elapsed() >= WPM_REPORT_INTERVAL,
+            None => true,
+        };
+        // An untagged server would take the report for a word
+        if !due
+            || self.state != AppState::Connected
+            || self.server_protocol == 0
+            || self.lurking
+            || self.paused_at.is_some()
+            || self.reconnect_attempt.is_some()
+            || self.chars_typed == 0
+        {
+            return None;
+        }
+        self.last_wpm_report = Some(Instant::now());
+        Some(self.typing_speed.round() as u32)
+    }
 
-    match app_lock.state {
-        AppState::Welcome => match key {
-            KeyCode::Enter => {
-                app_lock.connect();
-                drop(app_lock);
-                let _ = event_tx.send(AppEvent::Connect);
+    fn update_sentence(&mut self, new_sentence: String) {
+        self.sentence = new_sentence;
+    }
+
+    /// Returns a frame to send back, if the server's message calls for one
+    fn handle_server_text(&mut self, text: &str) -> Option<String> {
+        let mut reply = None;
+        if self.awaiting_hello {
+            self.awaiting_hello = false;
+            self.server_protocol = match ServerMessage::decode(text) {
+                Some(ServerMessage::Hello { protocol, session }) => {
+                    // A different run of the server can't resume our versions
+                    if self.server_session.as_ref() != Some(&session) {
+                        self.last_version = None;
+                    }
+                    self.server_session = Some(session);
+                    protocol
+                }
+                _ => {
+                    self.server_session = None;
+                    self.last_version = None;
+                    0
+                }
+            };
+            if self.server_protocol == 0 {
+                self.enter_compat_mode();
+            } else if self.server_protocol > PROTOCOL_VERSION {
+                self.notice = Some(format!(
+                    "⚠️  Server speaks protocol {}, this client only {}. Update the client, some features may not work",
+                    self.server_protocol, PROTOCOL_VERSION
+                ));
             }
-            KeyCode::Char('q') | KeyCode::Char('Q') => {
-                app_lock.should_quit = true;
+            // Such a server ignores the room and puts us with everyone else
+            if self.server_protocol == 0 && self.settings.room != LOBBY {
+                self.notice =
+                    Some("This server has no rooms, you share its one sentence".to_string());
             }
-            KeyCode::Char('h') | KeyCode::Char('H') => {
-                app_lock.toggle_help();
+            // An untagged server would take the command for a word
+            if self.server_protocol != 0 {
+                reply = Some(ClientMessage::Name(self.username.clone()).encode());
             }
-            _ => {}
-        },
-        AppState::Connected => match key {
-            KeyCode::Char(c) => {
-                app_lock.current_input.push(c);
+        }
+
+        if self.server_protocol == 0 {
+            self.synced = true;
+            self.update_sentence(text.to_string());
+            return None;
+        }
+
+        match ServerMessage::decode(text) {
+            Some(ServerMessage::Hello { .. }) => {}
+            Some(ServerMessage::ServerVersion(version)) => self.server_version = Some(version),
+            Some(ServerMessage::SentenceUpdate {
+                version, sentence, ..
+            }) => {
+                self.synced = true;
+                self.last_version = Some(version);
+                self.update_sentence(sentence);
             }
-            KeyCode::Backspace => {
-                app_lock.current_input.pop();
+            Some(ServerMessage::Append { version, text }) => {
+                self.synced = true;
+                self.last_version = Some(version);
+                if !text.is_empty() {
+                    let mut sentence = std::mem::take(&mut self.sentence);
+                    if !sentence.is_empty() {
+                        sentence.push(' ');
+                    }
+                    sentence.push_str(&text);
+                    self.update_sentence(sentence);
+                }
             }
-            KeyCode::Enter => {
-                if let Some(word) = app_lock.send_word() {
-                    drop(app_lock);
-                    let _ = event_tx.send(AppEvent::SendWord(word));
+            Some(ServerMessage::Authors { version, names }) => {
+                self.authors = Some((version, names));
+            }
+            Some(ServerMessage::Notice(notice)) => self.notice = Some(notice),
+            Some(ServerMessage::Cooldown {
+                retry_after_ms,
+                reason,
+            }) => self.start_cooldown(Duration::from_millis(retry_after_ms), reason),
+            Some(ServerMessage::MaxWords(max_words)) => self.max_words = Some(max_words),
+            Some(ServerMessage::Separator(separator)) => self.separator = Some(separator),
+            Some(ServerMessage::JoinRequired) => self.lurking = true,
+            Some(ServerMessage::Presence { connected, active }) => {
+                self.users_count = connected;
+                self.active_typists = Some(active);
+            }
+            Some(ServerMessage::Leaderboard(entries)) => self.leaderboard = entries,
+            Some(ServerMessage::Players(players)) => self.players = players,
+            Some(ServerMessage::Scoreboard(scores)) => self.scoreboard = scores,
+            Some(ServerMessage::Chat { name, text }) => {
+                if self.chat_history.len() == MAX_CHAT_HISTORY {
+                    self.chat_history.remove(0);
+                }
+                self.chat_history.push(format!("{}: {}", name, text));
+                // Stay on the lines being read while new ones come in
+                if self.chat_scroll > 0 {
+                    self.chat_scroll += 1;
                 }
             }
-            KeyCode::Esc => {
-                app_lock.state = AppState::Welcome;
-                app_lock.current_input.clear();
-                drop(app_lock);
-                let _ = event_tx.send(AppEvent::Disconnect);
+            Some(ServerMessage::Ack) => {
+                if let Some(word) = self.unacked.pop_front() {
+                    self.notice = Some(format!("✓ {word}"));
+                }
             }
-            KeyCode::F(1) => {
-                app_lock.toggle_help();
+            Some(ServerMessage::Nack(reason)) => {
+                if let Some(word) = self.unacked.pop_front() {
+                    self.notice = Some(format!("✗ {word}: {reason}"));
+                    self.rejected_at = Some(Instant::now());
+                }
             }
-            _ => {}
-        },
-        AppState::Connecting => match key {
-            KeyCode::Esc => {
-                app_lock.state = AppState::Welcome;
+            Some(ServerMessage::Round(state)) => {
+                if matches!(state, RoundState::Over { .. })
+                    && !matches!(self.round, Some(RoundState::Over { .. }))
+                {
+                    self.notice = Some("🏁 Time's up!".to_string());
+                }
+                if !matches!(state, RoundState::Over { .. }) {
+                    self.round_results = None;
+                }
+                self.round = Some(state);
             }
-            KeyCode::Char('q') | KeyCode::Char('Q') => {
-                app_lock.should_quit = true;
+            Some(ServerMessage::RoundResults { sentence, scores }) => {
+                self.round_results = Some((sentence, scores));
             }
-            _ => {}
-        },
-        AppState::Disconnected => match key {
-            KeyCode::Enter => {
-                app_lock.connect();
-                drop(app_lock);
-                let _ = event_tx.send(AppEvent::Connect);
+            Some(ServerMessage::Turn(state)) => {
+                if state == TurnState::Yours && self.turn != Some(TurnState::Yours) {
+                    self.notice = Some("Your turn!".to_string());
+                }
+                self.turn = Some(state);
             }
-            KeyCode::Esc => {
-                app_lock.state = AppState::Welcome;
+            Some(ServerMessage::Race(state)) => {
+                if matches!(state, RaceState::Running { .. })
+                    && !self.race.as_ref().is_some_and(RaceView::is_running)
+                {
+                    self.notice = Some("🏁 Go!".to_string());
+                }
+                match self.race.as_mut() {
+                    Some(race) => race.update(state),
+                    None => self.race = Some(RaceView::new(state)),
+                }
             }
-            KeyCode::Char('q') | KeyCode::Char('Q') => {
-                app_lock.should_quit = true;
+            Some(ServerMessage::Racers(racers)) => {
+                if let Some(race) = self.race.as_mut() {
+                    let placed =
+                        |race: &RaceView| race.racer(&self.username).and_then(|racer| racer.place);
+                    let was_placed = placed(race).is_some();
+                    race.racers = racers;
+                    if let (Some(place), false) = (placed(race), was_placed) {
+                        let wpm = race.racer(&self.username).map_or(0, |racer| racer.wpm);
+                        self.notice = Some(format!("🏁 You finished #{} at {} WPM", place, wpm));
+                    }
+                }
             }
-            _ => {}
-        },
+            Some(ServerMessage::Shutdown { in_secs }) => {
+                self.shutdown_at = Some(Instant::now() + Duration::from_secs(in_secs));
+            }
+            None => {}
+        }
+        reply
     }
-}
 
-fn ui(f: &mut Frame, app: &Arc<Mutex<App>>) {
-    let app_lock = app.lock().unwrap();
-
-    match app_lock.state {
-        AppState::Welcome => draw_welcome_screen(f, &app_lock),
-        AppState::Connecting => draw_connecting_screen(f, &app_lock),
-        AppState::Connected => draw_game_screen(f, &app_lock),
-        AppState::Disconnected => draw_disconnected_screen(f, &app_lock),
+    /// Author of every word currently shown, when it's known for this version
+    fn word_authors(&self) -> Option<&[Option<String>]> {
+        let (version, names) = self.authors.as_ref()?;
+        (Some(*version) == self.last_version
+            && names.len() == self.sentence.split_whitespace().count())
+        .then_some(names.as_slice())
     }
 
-    if app_lock.show_help {
-        draw_help_popup(f);
+    /// Most recent distinct authors of the sentence, newest last
+    fn recent_authors(&self) -> Vec<&str> {
+        let mut recent: Vec<&str> = Vec::new();
+        for name in self.word_authors().unwrap_or_default().iter().rev() {
+            let Some(name) = name.as_deref() else {
+                continue;
+            };
+            if !recent.contains(&name) {
+                recent.push(name);
+                if recent.len() == MAX_LEGEND_AUTHORS {
+                    break;
+                }
+            }
+        }
+        recent.reverse();
+        recent
+    }
+
+    /// URL for the next connection, asking to resume where we left off if we can
+    fn connect_url(&self) -> String {
+        let resume = match (&self.server_session, self.last_version) {
+            (Some(session), Some(version)) => Some((session.as_str(), version)),
+            _ => None,
+        };
+        connect_url(&self.server_url, resume, &self.settings.room)
+    }
+
+    fn enter_compat_mode(&mut self) {
+        if !self.compat_notice_shown {
+            self.compat_notice_shown = true;
+            self.notice =
+                Some("Server is older than this client, running in compatibility mode".to_string());
+        }
+    }
+
+    /// Adds the online session we're leaving to the history, if we typed
+    fn record_session(&mut self) {
+        if self.state != AppState::Connected || self.chars_typed == 0 {
+            return;
+        }
+        let Some(start) = self.start_time else {
+            return;
+        };
+        self.update_typing_speed();
+        history::record(&Session::new(
+            Some(self.servers[self.server_index].clone()),
+            self.typing_speed,
+            self.accuracy.unwrap_or_default(),
+            self.active_time(start),
+        ));
+    }
+
+    /// Adds the practice test to the history once it's finished
+    fn record_practice(&mut self) {
+        let Some(practice) = &self.practice else {
+            return;
+        };
+        if self.practice_recorded || !practice.is_started() || !practice.is_finished() {
+            return;
+        }
+        let results = practice.results();
+        history::record(&Session::new(
+            None,
+            results.wpm,
+            results.accuracy,
+            results.time,
+        ));
+        self.practice_recorded = true;
+    }
+
+    /// A fresh test, the clock waits for the first key
+    fn start_practice(&mut self) {
+        self.record_practice();
+        self.practice_recorded = false;
+        let words = self
+            .settings
+            .practice_words
+            .generate(&mut self.rng, &self.settings.practice);
+        self.practice = Some(Practice::new(&words));
+        self.state = AppState::Practice;
+    }
+
+    fn toggle_help(&mut self) {
+        self.show_help = !self.show_help;
+    }
+
+    /// Runs an action and returns the event to hand to the WebSocket task, if any
+    fn apply_action(&mut self, action: Action) -> Option<AppEvent> {
+        if !action.is_available(&self.state) {
+            return None;
+        }
+        match action {
+            Action::Connect => {
+                // Also cuts short the wait for a pending reconnect
+                self.retry = None;
+                self.connect();
+                Some(AppEvent::Connect)
+            }
+            Action::Disconnect => {
+                self.record_session();
+                self.record_practice();
+                self.state = AppState::Welcome;
+                self.practice = None;
+                self.current_input.clear();
+                self.reconnect_attempt = None;
+                self.retry = None;
+                // Also stops a connection attempt that's still in flight
+                Some(AppEvent::Disconnect)
+            }
+            Action::ToggleHelp => {
+                self.toggle_help();
+                None
+            }
+            Action::Practice => {
+                self.start_practice();
+                None
+            }
+            Action::CancelReconnect => {
+                // Stops the wait for the next attempt, we stay on this screen
+                self.retry.take()?;
+                self.connection_status = "Disconnected".to_string();
+                Some(AppEvent::Disconnect)
+            }
+            Action::History => {
+                self.history = history::load();
+                self.history.reverse();
+                self.history_scroll = 0;
+                self.state = AppState::History;
+                None
+            }
+            Action::Undo => {
+                if self.edit_history.undo(&mut self.current_input) {
+                    return None;
+                }
+                // Nothing typed since the last submit, so undo that submit
+                if self.current_input != self.settings.prefill || self.lurking {
+                    return None;
+                }
+                if self.server_protocol == 0 {
+                    self.notice = Some("This server can't take back single words".to_string());
+                    return None;
+                }
+                Some(AppEvent::UndoWord)
+            }
+            Action::Redo => {
+                self.edit_history.redo(&mut self.current_input);
+                None
+            }
+            Action::Retract => {
+                if self.server_protocol == 0 {
+                    self.notice = Some("This server can't take words back".to_string());
+                    return None;
+                }
+                // Ask twice before throwing away everything we wrote
+                match self.retract_armed.take() {
+                    Some(armed) if armed.elapsed() < CONFIRM_WINDOW => Some(AppEvent::Retract),
+                    _ => {
+                        self.retract_armed = Some(Instant::now());
+                        self.notice =
+                            Some("Press Ctrl+R again to remove every word you added".to_string());
+                        None
+                    }
+                }
+            }
+            Action::Clear => {
+                if self.server_protocol == 0 {
+                    self.notice = Some("This server can't clear the sentence".to_string());
+                    return None;
+                }
+                // Everyone loses the sentence, so ask twice
+                match self.clear_armed.take() {
+                    Some(armed) if armed.elapsed() < CONFIRM_WINDOW => Some(AppEvent::Clear),
+                    _ => {
+                        self.clear_armed = Some(Instant::now());
+                        self.notice = Some(
+                            "Press Ctrl+L again to clear the sentence for everyone".to_string(),
+                        );
+                        None
+                    }
+                }
+            }
+            Action::ToggleLeaderboard => {
+                self.show_leaderboard = !self.show_leaderboard;
+                None
+            }
+            Action::ToggleChat => {
+                if self.server_protocol == 0 {
+                    self.notice = Some("This server has no chat".to_string());
+                    return None;
+                }
+                self.chat_mode = !self.chat_mode;
+                std::mem::swap(&mut self.current_input, &mut self.other_input);
+                self.edit_history.clear();
+                None
+            }
+            Action::EditName => {
+                self.name_edit = Some((self.username.clone(), None));
+                None
+            }
+            Action::NextServer => {
+                self.server_index = (self.server_index + 1) % self.servers.len();
+                self.server_url = server_url(&self.servers[self.server_index]);
+                // Versions from another server mean nothing here
+                self.server_session = None;
+                self.last_version = None;
+                None
+            }
+            Action::CopySentence => {
+                self.copy_sentence();
+                None
+            }
+            Action::SaveSentence => {
+                self.save_sentence();
+                None
+            }
+            Action::ResetStats => {
+                self.reset_stats();
+                None
+            }
+            Action::TogglePause => {
+                self.toggle_pause();
+                None
+            }
+            Action::ToggleStats => {
+                self.hide_stats = !self.hide_stats;
+                let mut prefs = Prefs::load();
+                prefs.hide_stats = self.hide_stats;
+                prefs.save();
+                None
+            }
+            Action::NextTheme => {
+                let (name, theme) = self.settings.theme.next();
+                self.settings.theme = theme;
+                self.notice = Some(format!("Theme: {}", name));
+                None
+            }
+            Action::Quit => {
+                self.record_session();
+                self.record_practice();
+                self.should_quit = true;
+                None
+            }
+        }
+    }
+
+    /// Copies the sentence, or saves it to a file when there's no clipboard
+    /// (e.g. over SSH or without a display server)
+    fn copy_sentence(&mut self) {
+        if self.sentence.trim().is_empty() {
+            self.notice = Some("Nothing to copy yet".to_string());
+            return;
+        }
+        let copied = arboard::Clipboard::new()
+            .and_then(|mut clipboard| clipboard.set_text(self.sentence.clone()));
+        let status = match copied {
+            Ok(()) => Ok("📋 Copied to clipboard".to_string()),
+            Err(copy_err) => match save_sentence(&self.sentence, self.export_dir()) {
+                Ok(path) => Ok(saved_status(&path)),
+                Err(save_err) => Err(format!(
+                    "Could not copy ({}) or save ({}) the sentence",
+                    copy_err, save_err
+                )),
+            },
+        };
+        self.show_copy_status(status);
+    }
+
+    /// Saves the sentence to a file, which works without any clipboard
+    fn save_sentence(&mut self) {
+        if self.sentence.trim().is_empty() {
+            self.notice = Some("Nothing to save yet".to_string());
+            return;
+        }
+        let status = match save_sentence(&self.sentence, self.export_dir()) {
+            Ok(path) => Ok(saved_status(&path)),
+            Err(err) => Err(format!("Could not save the sentence: {}", err)),
+        };
+        self.show_copy_status(status);
+    }
+
+    fn export_dir(&self) -> &Path {
+        self.settings
+            .export_dir
+            .as_deref()
+            .unwrap_or_else(|| Path::new(""))
+    }
+
+    fn show_copy_status(&mut self, status: Result<String, String>) {
+        // Without the stats pane the input's notice is the only place to say so
+        if self.hide_stats {
+            self.notice = Some(match status {
+                Ok(ref message) | Err(ref message) => message.clone(),
+            });
+        }
+        self.copy_status = Some((Instant::now(), status));
+    }
+
+    /// Handles a key while the name is being edited on the welcome screen
+    fn name_key(&mut self, key: KeyCode) {
+        let Some((name, error)) = self.name_edit.as_mut() else {
+            return;
+        };
+        match key {
+            KeyCode::Esc => self.name_edit = None,
+            KeyCode::Enter => match check_name(name) {
+                Ok(()) => {
+                    self.username = std::mem::take(name);
+                    self.name_edit = None;
+                    // Becomes the default name next time
+                    let mut prefs = Prefs::load();
+                    prefs.name = Some(self.username.clone());
+                    prefs.save();
+                }
+                Err(err) => *error = Some(err),
+            },
+            KeyCode::Backspace => {
+                name.pop();
+            }
+            KeyCode::Char(c) => name.push(c),
+            _ => {}
+        }
+    }
+
+    /// Handles a key while the palette is open, returning the chosen action
+    fn palette_key(&mut self, key: KeyCode) -> Option<Action> {
+        let matches = self.palette.as_ref()?.matches(&self.state);
+        let palette = self.palette.as_mut()?;
+        match key {
+            KeyCode::Esc => self.palette = None,
+            KeyCode::Enter => {
+                let action = matches.get(palette.selected).copied();
+                self.palette = None;
+                return action;
+            }
+            KeyCode::Up => palette.selected = palette.selected.saturating_sub(1),
+            KeyCode::Down => {
+                palette.selected = (palette.selected + 1).min(matches.len().saturating_sub(1));
+            }
+            KeyCode::Backspace => {
+                palette.query.pop();
+                palette.selected = 0;
+            }
+            KeyCode::Char(c) => {
+                palette.query.push(c);
+                palette.selected = 0;
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    // Parse command line arguments
+    let args: Vec<String> = env::args().collect();
+    if args[1..].iter().any(|arg| arg == "--version") {
+        println!(
+            "chaos-type-client {} (protocol {})",
+            env!("CARGO_PKG_VERSION"),
+            PROTOCOL_VERSION
+        );
+        return Ok(());
+    }
+    let mut cli = match parse_args(&args[1..]) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            eprintln!(
+                "Usage: {} [IP_ADDRESS|HOSTNAME [PORT]|URL] [--tls] [--paste-mode split|join|reject] [--client-filter-file PATH] [--debug] [--no-stats] [--display-style plain|boxed|spaced] [--theme default|high-contrast|monochrome] [--seed N] [--quiet-reconnect] [--rhythm-report] [--prefill TEXT] [--render-cap CHARS] [--wpm-goal N] [--min-redraw-ms MS] [--json-events] [--name NAME] [--room NAME] [--export-dir PATH] [--connect-timeout SECS] [--practice-count N] [--practice-caps] [--practice-punctuation] [--practice-numbers] [--practice-words PATH] [--config PATH] [--version]",
+                args[0]
+            );
+            eprintln!("Example: {} 192.168.1.100 9001", args[0]);
+            std::process::exit(1);
+        }
+    };
+
+    let config = Config::load(cli.config.as_deref()).and_then(|config| {
+        apply_config(&mut cli, config).map_err(|err| format!("Config file: {}", err))
+    });
+    if let Err(err) = config {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+
+    let prefs = Prefs::load();
+    let mut settings = cli.settings;
+    settings.hide_stats |= prefs.hide_stats;
+    settings.name = settings.name.or(prefs.name);
+
+    // The server asked for first, then the ones used before
+    let mut servers: Vec<String> = cli.server.into_iter().collect();
+    for server in prefs.recent_servers {
+        if !servers.contains(&server) {
+            servers.push(server);
+        }
+    }
+    if servers.is_empty() {
+        servers.push("127.0.0.1".to_string());
+    }
+
+    if settings.json_events {
+        return events::run(&connect_url(&server_url(&servers[0]), None, &settings.room)).await;
+    }
+
+    // Setup terminal, the guard restores it however we leave
+    let guard = TerminalGuard::enter()?;
+    let backend = CrosstermBackend::new(io::stdout());
+    let mut terminal = Terminal::new(backend)?;
+
+    let app = Arc::new(Mutex::new(App::new(servers, settings)));
+    let (event_tx, event_rx) = mpsc::unbounded_channel::<AppEvent>();
+
+    // Spawn WebSocket client task
+    let app_clone = Arc::clone(&app);
+    let ws_handle = tokio::spawn(async move {
+        run_websocket_client(app_clone, event_rx).await;
+    });
+
+    let res = run_app(&mut terminal, app, event_tx).await;
+
+    // Cleanup
+    ws_handle.abort();
+    drop(guard);
+
+    if let Err(err) = res {
+        println!("{err:?}");
+    }
+
+    Ok(())
+}
+
+/// Raw mode and the alternate screen for as long as it lives. Dropping it puts
+/// the terminal back, so an early `?`, a failed draw or a panic can't leave
+/// the user's shell in raw mode.
+struct TerminalGuard {
+    restore: fn(),
+}
+
+impl TerminalGuard {
+    fn enter() -> io::Result<TerminalGuard> {
+        enable_raw_mode()?;
+        let guard = TerminalGuard {
+            restore: restore_terminal,
+        };
+        execute!(
+            io::stdout(),
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            EnableBracketedPaste
+        )?;
+
+        // Restore before the panic message is printed, or it's lost with the
+        // alternate screen
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        (self.restore)();
+    }
+}
+
+/// Best effort, the terminal may already be gone
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(
+        io::stdout(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        DisableBracketedPaste,
+        cursor::Show
+    );
+}
+
+/// How long a session has to run before its speed can count towards `--wpm-goal`
+const MIN_GOAL_TIME: Duration = Duration::from_secs(10);
+
+async fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: Arc<Mutex<App>>,
+    event_tx: mpsc::UnboundedSender<AppEvent>,
+) -> io::Result<()> {
+    let mut last_tick = Instant::now();
+    let tick_rate = Duration::from_millis(250);
+    let min_redraw = app.lock().unwrap().settings.min_redraw;
+    let mut last_draw: Option<Instant> = None;
+    let mut redraw_pending = true;
+
+    loop {
+        let should_quit = {
+            let app_lock = app.lock().unwrap();
+            app_lock.should_quit
+        };
+
+        if should_quit {
+            let _ = event_tx.send(AppEvent::Quit);
+            break;
+        }
+
+        // Updates closer together than `--min-redraw-ms` are coalesced into
+        // one draw once the floor has passed
+        let redraw_wait =
+            last_draw.map_or(Duration::ZERO, |at| min_redraw.saturating_sub(at.elapsed()));
+        if redraw_pending && redraw_wait.is_zero() {
+            terminal.draw(|f| ui(f, &app))?;
+            last_draw = Some(Instant::now());
+            redraw_pending = false;
+        }
+
+        let mut timeout = tick_rate
+            .checked_sub(last_tick.elapsed())
+            .unwrap_or_else(|| Duration::from_secs(0));
+        if redraw_pending {
+            timeout = timeout.min(redraw_wait);
+        }
+
+        if crossterm::event::poll(timeout)? {
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    handle_key_event(key, &app, &event_tx).await;
+                }
+                Event::Paste(text) => handle_paste(&text, &app, &event_tx),
+                Event::Mouse(mouse) => handle_mouse_event(mouse, &app, &event_tx),
+                // The last frame is garbled at the new size, so don't wait
+                // out `--min-redraw-ms` before drawing the next one
+                Event::Resize(..) => last_draw = None,
+                _ => {}
+            }
+        }
+        // An event, a tick or the WebSocket task may have changed what's shown
+        redraw_pending = true;
+
+        if last_tick.elapsed() >= tick_rate {
+            let mut app_lock = app.lock().unwrap();
+            app_lock.update_typing_speed();
+            app_lock.record_practice();
+            if let Some(wpm) = app_lock.wpm_report() {
+                let _ = event_tx.send(AppEvent::ReportWpm(wpm));
+            }
+            drop(app_lock);
+            last_tick = Instant::now();
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_key_event(
+    key: KeyEvent,
+    app: &Arc<Mutex<App>>,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) {
+    let mut app_lock = app.lock().unwrap();
+
+    if app_lock.name_edit.is_some() {
+        app_lock.name_key(key.code);
+        return;
+    }
+
+    let action = if app_lock.palette.is_some() {
+        app_lock.palette_key(key.code)
+    } else if key.code == KeyCode::Char(':')
+        && app_lock.state != AppState::Practice
+        && (app_lock.state != AppState::Connected
+            || app_lock.current_input == app_lock.settings.prefill)
+    {
+        app_lock.palette = Some(Palette::default());
+        return;
+    } else {
+        action_for_key(&app_lock.state, key)
+    };
+
+    if let Some(action) = action {
+        if let Some(event) = app_lock.apply_action(action) {
+            drop(app_lock);
+            let _ = event_tx.send(event);
+        }
+        return;
+    }
+
+    if app_lock.state == AppState::History {
+        let last = app_lock.history.len().saturating_sub(1);
+        let scroll = &mut app_lock.history_scroll;
+        match key.code {
+            KeyCode::Up => *scroll = scroll.saturating_sub(1),
+            KeyCode::Down => *scroll = (*scroll + 1).min(last),
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(HISTORY_PAGE),
+            KeyCode::PageDown => *scroll = (*scroll + HISTORY_PAGE).min(last),
+            _ => {}
+        }
+        return;
+    }
+
+    if let Some(practice) = app_lock.practice.as_mut() {
+        match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                practice.type_char(c)
+            }
+            KeyCode::Backspace => practice.backspace(),
+            KeyCode::Enter if practice.is_finished() => app_lock.start_practice(),
+            _ => {}
+        }
+        return;
+    }
+
+    // Chat is open to watchers too and doesn't count towards typing stats
+    if app_lock.state == AppState::Connected && app_lock.chat_mode {
+        match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app_lock.edit_input(|input| input.push(c));
+            }
+            KeyCode::Backspace => app_lock.edit_input(|input| {
+                input.pop();
+            }),
+            KeyCode::PageUp => app_lock.scroll_chat(CHAT_SCROLL_STEP),
+            KeyCode::PageDown => app_lock.scroll_chat(-CHAT_SCROLL_STEP),
+            KeyCode::Enter => {
+                if let Some(text) = app_lock.send_chat() {
+                    drop(app_lock);
+                    let _ = event_tx.send(AppEvent::SendChat(text));
+                }
+            }
+            _ => {}
+        }
+        return;
+    }
+
+    // Watchers can scroll back through the sentence too
+    if app_lock.state == AppState::Connected {
+        let input_empty = app_lock.current_input == app_lock.settings.prefill;
+        let lines = match key.code {
+            KeyCode::PageUp => Some(-SENTENCE_SCROLL_STEP),
+            KeyCode::PageDown => Some(SENTENCE_SCROLL_STEP),
+            KeyCode::Up if input_empty => Some(-1),
+            KeyCode::Down if input_empty => Some(1),
+            _ => None,
+        };
+        if let Some(lines) = lines {
+            app_lock.scroll_sentence(lines);
+            return;
+        }
+    }
+
+    // Nothing gets typed during a break, F6 ends it
+    if app_lock.state == AppState::Connected && app_lock.paused_at.is_some() {
+        return;
+    }
+
+    // Watchers have no input, Enter joins them as a typist
+    if app_lock.state == AppState::Connected && app_lock.lurking {
+        if key.code == KeyCode::Enter {
+            app_lock.lurking = false;
+            drop(app_lock);
+            let _ = event_tx.send(AppEvent::Join);
+        }
+        return;
+    }
+
+    // Races are typed key by key, there's no word to send
+    if let Some(race) = app_lock.race.as_mut() {
+        match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                if let Some(chars) = race.type_char(c) {
+                    drop(app_lock);
+                    let _ = event_tx.send(AppEvent::RaceProgress(chars));
+                }
+            }
+            KeyCode::Backspace => race.backspace(),
+            _ => {}
+        }
+        return;
+    }
+
+    if app_lock.state == AppState::Connected {
+        match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                app_lock.record_keystroke(false);
+                app_lock.edit_input(|input| input.push(c));
+            }
+            KeyCode::Backspace => {
+                app_lock.record_keystroke(true);
+                app_lock.edit_input(|input| {
+                    input.pop();
+                });
+            }
+            KeyCode::Enter if app_lock.round_over() => {
+                app_lock.notice = Some("The round is over, wait for the next one".to_string());
+            }
+            KeyCode::Enter if app_lock.waiting_for_turn() => {
+                app_lock.notice = Some("Not your turn yet".to_string());
+            }
+            KeyCode::Enter => {
+                if let Some(word) = app_lock.send_word() {
+                    if let Some(rhythm) = app_lock.rhythm.as_mut() {
+                        rhythm.submit(Instant::now());
+                    }
+                    drop(app_lock);
+                    let _ = event_tx.send(AppEvent::SendWord(word));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Left clicks run the action under the pointer, like its key would
+fn handle_mouse_event(
+    mouse: MouseEvent,
+    app: &Arc<Mutex<App>>,
+    event_tx: &mpsc::UnboundedSender<AppEvent>,
+) {
+    if mouse.kind != MouseEventKind::Down(MouseButton::Left) {
+        return;
+    }
+    let mut app_lock = app.lock().unwrap();
+    if app_lock.palette.is_some() {
+        return;
+    }
+    let clicked = app_lock
+        .click_areas
+        .iter()
+        .rev()
+        .find(|(area, _)| {
+            (area.left()..area.right()).contains(&mouse.column)
+                && (area.top()..area.bottom()).contains(&mouse.row)
+        })
+        .map(|(_, action)| *action);
+    let Some(action) = clicked.filter(|action| action.is_available(&app_lock.state)) else {
+        return;
+    };
+    if let Some(event) = app_lock.apply_action(action) {
+        drop(app_lock);
+        let _ = event_tx.send(event);
     }
 }
 
-fn draw_welcome_screen(f: &mut Frame, app: &App) {
+fn handle_paste(text: &str, app: &Arc<Mutex<App>>, event_tx: &mpsc::UnboundedSender<AppEvent>) {
+    let mut app_lock = app.lock().unwrap();
+    if app_lock.state != AppState::Connected {
+        return;
+    }
+    // A chat message is one line, whatever the paste mode
+    if app_lock.chat_mode {
+        let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        app_lock.edit_input(|input| input.push_str(&line));
+        return;
+    }
+    // Pasting the passage would win every race
+    if app_lock.lurking
+        || app_lock.race.is_some()
+        || app_lock.paused_at.is_some()
+        || app_lock.round_over()
+        || app_lock.waiting_for_turn()
+    {
+        return;
+    }
+
+    for word in app_lock.paste(text) {
+        let _ = event_tx.send(AppEvent::SendWord(word));
+    }
+}
+
+fn ui(f: &mut Frame, app: &Arc<Mutex<App>>) {
+    let mut app_lock = app.lock().unwrap();
+
+    // Between sessions the rhythm report, if any, sits below the screen
+    let mut area = f.size();
+    let report = match app_lock.state {
+        AppState::Welcome | AppState::Disconnected => {
+            app_lock.rhythm.as_ref().and_then(Rhythm::report)
+        }
+        _ => None,
+    };
+    if let Some(ref report) = report {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(0),
+                Constraint::Length(rhythm::BUCKET_BOUNDS_MS.len() as u16 + 6),
+            ])
+            .split(area);
+        area = chunks[0];
+        draw_rhythm_report(f, report, chunks[1], &app_lock.settings.theme);
+    }
+
+    let mut click_areas = match app_lock.state {
+        AppState::Welcome => draw_welcome_screen(f, &app_lock, area),
+        AppState::Connecting => {
+            draw_connecting_screen(f, &app_lock);
+            Vec::new()
+        }
+        AppState::Connected => draw_game_screen(f, &app_lock),
+        AppState::Disconnected => draw_disconnected_screen(f, &app_lock, area),
+        AppState::Practice => {
+            draw_practice_screen(f, &app_lock, area);
+            Vec::new()
+        }
+        AppState::History => {
+            draw_history_screen(f, &app_lock, area);
+            Vec::new()
+        }
+    };
+
+    if app_lock.round_over() && app_lock.state == AppState::Connected {
+        if let Some((sentence, scores)) = &app_lock.round_results {
+            draw_round_results(f, &app_lock, sentence, scores);
+        }
+    }
+
+    if app_lock.show_leaderboard && app_lock.state == AppState::Connected {
+        let popup = draw_leaderboard_popup(f, &app_lock);
+        click_areas.push((popup, Action::ToggleLeaderboard));
+    }
+
+    if app_lock.show_help {
+        let popup = draw_help_popup(f, &app_lock.settings.theme);
+        click_areas.push((popup, Action::ToggleHelp));
+    }
+    app_lock.click_areas = click_areas;
+
+    if let Some(ref palette) = app_lock.palette {
+        draw_command_palette(f, palette, &app_lock.state, &app_lock.settings.theme);
+    }
+}
+
+/// Returns where clicks connect or switch servers
+fn draw_welcome_screen(f: &mut Frame, app: &App, area: Rect) -> Vec<(Rect, Action)> {
+    let theme = &app.settings.theme;
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
@@ -420,15 +2062,11 @@ fn draw_welcome_screen(f: &mut Frame, app: &App) {
             Constraint::Min(10),
             Constraint::Length(3),
         ])
-        .split(f.size());
+        .split(area);
 
     // Title
     let title = Paragraph::new("Chaos Type")
-        .style(
-            Style::default()
-                .fg(Color::Cyan)
-                .add_modifier(Modifier::BOLD),
-        )
+        .style(theme.title.add_modifier(Modifier::BOLD))
         .alignment(Alignment::Center)
         .block(Block::default().borders(Borders::ALL));
     f.render_widget(title, chunks[0]);
@@ -437,73 +2075,86 @@ fn draw_welcome_screen(f: &mut Frame, app: &App) {
     let welcome_text = vec![
         Line::from(""),
         Line::from(vec![
-            Span::styled("Welcome to ", Style::default().fg(Color::White)),
-            Span::styled(
-                "Chaos Type",
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(Modifier::BOLD),
-            ),
-            Span::styled("!", Style::default().fg(Color::White)),
+            Span::styled("Welcome to ", theme.text),
+            Span::styled("Chaos Type", theme.accent.add_modifier(Modifier::BOLD)),
+            Span::styled("!", theme.text),
         ]),
         Line::from(""),
         Line::from("Connect with friends and build sentences together in real-time!"),
         Line::from(""),
         Line::from(vec![
-            Span::styled("📝 ", Style::default().fg(Color::Green)),
-            Span::styled(
-                "Type words and watch as others contribute",
-                Style::default().fg(Color::Gray),
-            ),
+            Span::styled("📝 ", theme.success),
+            Span::styled("Type words and watch as others contribute", theme.muted),
         ]),
         Line::from(vec![
-            Span::styled("⚡ ", Style::default().fg(Color::Yellow)),
-            Span::styled(
-                "See your typing speed in real-time",
-                Style::default().fg(Color::Gray),
-            ),
+            Span::styled("⚡ ", theme.accent),
+            Span::styled("See your typing speed in real-time", theme.muted),
         ]),
         Line::from(""),
+        match &app.name_edit {
+            Some((name, _)) => Line::from(vec![
+                Span::styled("Playing as: ", theme.text),
+                Span::styled(
+                    format!("{}▏", name),
+                    theme.accent.add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(" (ENTER to keep, ESC to cancel)", theme.muted),
+            ]),
+            None => Line::from(vec![
+                Span::styled("Playing as: ", theme.text),
+                Span::styled(&app.username, theme.accent),
+                Span::styled(" (N to change)", theme.muted),
+            ]),
+        },
+        match &app.name_edit {
+            Some((_, Some(error))) => Line::from(Span::styled(error, theme.error)),
+            _ => Line::from(""),
+        },
         Line::from(vec![
-            Span::styled("Server: ", Style::default().fg(Color::White)),
-            Span::styled(
-                &app.server_url,
-                Style::default()
-                    .fg(Color::Cyan)
-                    .add_modifier(Modifier::BOLD),
-            ),
+            Span::styled("Server: ", theme.text),
+            Span::styled(&app.server_url, theme.title.add_modifier(Modifier::BOLD)),
         ]),
+        Line::from(vec![
+            Span::styled("Room: ", theme.text),
+            Span::styled(&app.settings.room, theme.accent),
+        ]),
+        if app.servers.len() > 1 {
+            Line::from(Span::styled(
+                format!(
+                    "({}/{}, TAB for the next one)",
+                    app.server_index + 1,
+                    app.servers.len()
+                ),
+                theme.muted,
+            ))
+        } else {
+            Line::from("")
+        },
         Line::from(""),
         Line::from(vec![
-            Span::styled("Press ", Style::default().fg(Color::White)),
-            Span::styled(
-                "ENTER",
-                Style::default()
-                    .fg(Color::Green)
-                    .add_modifier(Modifier::BOLD),
-            ),
-            Span::styled(
-                " to connect to the server",
-                Style::default().fg(Color::White),
-            ),
+            Span::styled("Press ", theme.text),
+            Span::styled("ENTER", theme.success.add_modifier(Modifier::BOLD)),
+            Span::styled(" to connect to the server", theme.text),
         ]),
         Line::from(vec![
-            Span::styled("Press ", Style::default().fg(Color::White)),
-            Span::styled(
-                "H",
-                Style::default()
-                    .fg(Color::Blue)
-                    .add_modifier(Modifier::BOLD),
-            ),
-            Span::styled(" for help", Style::default().fg(Color::White)),
+            Span::styled("Press ", theme.text),
+            Span::styled("P", theme.accent.add_modifier(Modifier::BOLD)),
+            Span::styled(" to practice offline", theme.text),
         ]),
         Line::from(vec![
-            Span::styled("Press ", Style::default().fg(Color::White)),
-            Span::styled(
-                "Q",
-                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
-            ),
-            Span::styled(" to quit", Style::default().fg(Color::White)),
+            Span::styled("Press ", theme.text),
+            Span::styled("S", theme.accent.add_modifier(Modifier::BOLD)),
+            Span::styled(" for your past sessions", theme.text),
+        ]),
+        Line::from(vec![
+            Span::styled("Press ", theme.text),
+            Span::styled("H", theme.border.add_modifier(Modifier::BOLD)),
+            Span::styled(" for help", theme.text),
+        ]),
+        Line::from(vec![
+            Span::styled("Press ", theme.text),
+            Span::styled("Q", theme.error.add_modifier(Modifier::BOLD)),
+            Span::styled(" to quit", theme.text),
         ]),
     ];
 
@@ -513,175 +2164,1123 @@ fn draw_welcome_screen(f: &mut Frame, app: &App) {
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Blue)),
+                .border_style(theme.border),
         );
     f.render_widget(welcome, chunks[1]);
 
     // Footer
     let footer = Paragraph::new(format!("Server: {}", app.server_url))
-        .style(Style::default().fg(Color::Gray))
+        .style(theme.muted)
         .alignment(Alignment::Center)
         .block(Block::default().borders(Borders::ALL));
     f.render_widget(footer, chunks[2]);
+
+    vec![
+        (chunks[1], Action::Connect),
+        (chunks[2], Action::NextServer),
+    ]
+}
+
+fn draw_connecting_screen(f: &mut Frame, app: &App) {
+    let theme = &app.settings.theme;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0)])
+        .split(f.size());
+
+    let connecting = Paragraph::new(vec![
+        Line::from(""),
+        Line::from(match app.retry {
+            Some((attempt, _)) => format!(
+                "🔄 Reconnecting to server... (attempt {}/{})",
+                attempt, MAX_RECONNECTS
+            ),
+            None => "🔄 Connecting to server...".to_string(),
+        }),
+        Line::from(""),
+        Line::from("Please wait while we establish the connection."),
+        Line::from(""),
+        Line::from("Press ESC to cancel"),
+    ])
+    .style(theme.accent)
+    .alignment(Alignment::Center)
+    .block(Block::default().borders(Borders::ALL).title("Connecting"));
+
+    f.render_widget(connecting, chunks[0]);
+}
+
+/// Current speed for the header, with the goal next to it if there is one
+fn speed_label(app: &App) -> String {
+    match app.settings.wpm_goal {
+        Some(goal) => format!(
+            "Speed: {:.1} / {} WPM{}",
+            app.typing_speed,
+            goal,
+            if app.goal_reached { " ✓" } else { "" }
+        ),
+        None => format!("Speed: {:.1} WPM", app.typing_speed),
+    }
+}
+
+/// Returns where clicks switch between words and chat or open the help
+fn draw_game_screen(f: &mut Frame, app: &App) -> Vec<(Rect, Action)> {
+    let theme = &app.settings.theme;
+    let mut click_areas = Vec::new();
+    let legend = app.recent_authors();
+    let stats_height = match (app.hide_stats, legend.is_empty()) {
+        (true, _) => 0,
+        (false, true) => 5,
+        (false, false) => 6,
+    };
+    let show_chat = app.chat_mode || !app.chat_history.is_empty();
+    let chat_height = if show_chat { CHAT_PANE_LINES + 2 } else { 0 };
+    let round_height = if app.round.is_some() || app.race.is_some() {
+        1
+    } else {
+        0
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),            // Header
+            Constraint::Length(round_height), // Round clock
+            Constraint::Min(5),               // Sentence display
+            Constraint::Length(chat_height),  // Chat
+            Constraint::Length(3),            // Input
+            Constraint::Length(stats_height), // Stats
+        ])
+        .split(f.size());
+
+    // Header with connection status, or a banner for reconnects and shutdowns
+    let (header_text, header_style) = if let Some(attempt) = app.reconnect_attempt {
+        (
+            format!(
+                "🔄 Reconnecting... (attempt {}/{}) | {}",
+                attempt,
+                MAX_QUIET_RECONNECTS,
+                speed_label(app)
+            ),
+            theme.accent,
+        )
+    } else if let Some(at) = app.shutdown_at {
+        (
+            format!(
+                "⚠️  Server restarting in {}s | {}",
+                at.saturating_duration_since(Instant::now()).as_secs(),
+                speed_label(app)
+            ),
+            theme.error,
+        )
+    } else if app.paused_at.is_some() {
+        (
+            format!("⏸  Paused, your speed is on hold | {}", speed_label(app)),
+            theme.accent,
+        )
+    } else {
+        (
+            format!(
+                "🎮 Chaos Type | {}Status: {} | Ping: {} | {}",
+                if app.settings.room == LOBBY {
+                    String::new()
+                } else {
+                    format!("Room: {} | ", app.settings.room)
+                },
+                app.connection_status,
+                app.latency_ms
+                    .map_or_else(|| "--".to_string(), |ms| format!("{}ms", ms)),
+                speed_label(app)
+            ),
+            theme.success,
+        )
+    };
+    let mut header_block = Block::default().borders(Borders::ALL);
+    if let Some(ref version) = app.server_version {
+        header_block = header_block.title_bottom(
+            Line::from(Span::styled(format!(" server v{} ", version), theme.dim)).right_aligned(),
+        );
+    }
+    let header = Paragraph::new(header_text)
+        .style(header_style.add_modifier(Modifier::BOLD))
+        .alignment(Alignment::Center)
+        .block(header_block);
+    f.render_widget(header, chunks[0]);
+
+    if let Some(round) = app.round {
+        f.render_widget(round_clock(round, theme), chunks[1]);
+    }
+    if let Some(ref race) = app.race {
+        f.render_widget(race_status(race, &app.username, theme), chunks[1]);
+    }
+
+    // Sentence display, the full text stays in `app.sentence`
+    let visible = sentence_tail(&app.sentence, app.settings.render_cap);
+    let sentence_text = if !app.synced && app.sentence.is_empty() {
+        Line::from(Span::styled("Synchronizing with the server...", theme.dim))
+    } else if app.sentence.is_empty() {
+        Line::from("Start typing to begin the collaborative sentence...")
+    } else if !app.settings.client_filter.is_empty() {
+        styled_sentence(
+            &mask_words(&visible, &app.settings.client_filter),
+            app.settings.display_style,
+            app.word_authors(),
+            app.separator.as_deref(),
+            theme,
+        )
+    } else {
+        styled_sentence(
+            &visible,
+            app.settings.display_style,
+            app.word_authors(),
+            app.separator.as_deref(),
+            theme,
+        )
+    };
+
+    let sentence = Paragraph::new(sentence_text)
+        .style(theme.text)
+        .alignment(Alignment::Left)
+        .wrap(Wrap { trim: false });
+    // Players listed beside the sentence, when there's room for both
+    let (sentence_area, players_area) =
+        if app.players.is_empty() || chunks[2].width < PLAYERS_PANEL_MIN_SCREEN {
+            (chunks[2], None)
+        } else {
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Min(0), Constraint::Length(PLAYERS_PANEL_WIDTH)])
+                .split(chunks[2]);
+            (columns[0], Some(columns[1]))
+        };
+    if let Some(area) = players_area {
+        // The scoreboard goes under the players, as far as they leave room
+        let wanted = u16::try_from(app.scoreboard.len()).unwrap_or(u16::MAX) + 3;
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(4), Constraint::Length(wanted)])
+            .split(area);
+        if app.scoreboard.is_empty() {
+            draw_players_panel(f, app, area);
+        } else {
+            draw_players_panel(f, app, rows[0]);
+            draw_scoreboard(f, app, rows[1]);
+        }
+    }
+    if let Some(ref race) = app.race {
+        draw_race(f, app, race, sentence_area);
+    } else {
+        let inner = sentence_area.inner(&Margin::new(1, 1));
+        let lines = u16::try_from(sentence.line_count(inner.width)).unwrap_or(u16::MAX);
+        let max_top = lines.saturating_sub(inner.height);
+        app.sentence_scroll_max.set(max_top);
+        let top = app.sentence_scroll.map_or(max_top, |top| top.min(max_top));
+
+        let mut sentence_block = Block::default()
+            .borders(Borders::ALL)
+            .title(if !app.synced {
+                "📝 Current Sentence (synchronizing...)"
+            } else if top < max_top {
+                "📝 Current Sentence (scrolled back, PgDn for newer)"
+            } else {
+                "📝 Current Sentence"
+            })
+            .border_style(theme.border);
+        if app.settings.debug {
+            let version = app
+                .last_version
+                .map_or_else(|| "-".to_string(), |v| v.to_string());
+            sentence_block = sentence_block.title_bottom(
+                Line::from(Span::styled(
+                    format!(
+                        " v{} · session {}{} ",
+                        version,
+                        app.server_session.as_deref().unwrap_or("-"),
+                        app.settings
+                            .seed
+                            .map(|seed| format!(" · seed {}", seed))
+                            .unwrap_or_default()
+                    ),
+                    theme.dim,
+                ))
+                .right_aligned(),
+            );
+        }
+        let sentence = sentence.scroll((top, 0)).block(sentence_block);
+        f.render_widget(sentence, sentence_area);
+    }
+    if app.chat_mode {
+        click_areas.push((sentence_area, Action::ToggleChat));
+    }
+
+    // Input field
+    if show_chat {
+        draw_chat_pane(f, app, chunks[3]);
+        if !app.chat_mode {
+            click_areas.push((chunks[3], Action::ToggleChat));
+        }
+    }
+
+    // In a race the box shows the word we're on, the passage shows the rest
+    let input_text = match app.race {
+        Some(ref race) if !app.chat_mode => race.current_word(),
+        _ => app.current_input.as_str(),
+    };
+    let mut input_block = Block::default()
+        .borders(Borders::ALL)
+        .title(match &app.turn {
+            _ if app.chat_mode => "💬 Chat (Press ENTER to send, TAB for words)".to_string(),
+            _ if app.lurking => "👀 Watching (Press ENTER to join as a typist)".to_string(),
+            _ if app.paused_at.is_some() => "⏸  Paused (Press F6 to carry on)".to_string(),
+            _ if app.race.is_some() => "🏁 Race (type the passage, no ENTER needed)".to_string(),
+            Some(TurnState::Yours) => "✍️  Your turn (Press ENTER to send)".to_string(),
+            Some(TurnState::Waiting(name)) => {
+                format!("⏳ Waiting for {} (type ahead, send on your turn)", name)
+            }
+            _ => "✍️  Your Word (Press ENTER to send)".to_string(),
+        })
+        .title_top(
+            Line::from(Span::styled(
+                format!(" {} chars ", input_text.chars().count()),
+                theme.muted,
+            ))
+            .right_aligned(),
+        )
+        .border_style(theme.success);
+    if app
+        .rejected_at
+        .is_some_and(|at| at.elapsed() < REJECT_FLASH)
+    {
+        input_block = input_block.border_style(theme.error);
+    }
+    if let Some(preview) = word_split_preview(&app.current_input) {
+        input_block = input_block
+            .title_bottom(Line::from(Span::styled(preview, theme.muted)).right_aligned());
+    }
+    let cooldown = app.cooldown_progress();
+    if let Some((remaining, ratio)) = cooldown {
+        input_block = input_block
+            .border_style(theme.dim)
+            .title_bottom(Span::styled(
+                format!(
+                    " ⏳ {}wait {:.1}s {} ",
+                    app.notice
+                        .as_ref()
+                        .map(|n| format!("{} · ", n))
+                        .unwrap_or_default(),
+                    remaining.as_secs_f64(),
+                    cooldown_bar(ratio, 10)
+                ),
+                theme.accent,
+            ));
+    } else if let Some(ref notice) = app.notice {
+        input_block = input_block.title_bottom(Span::styled(format!(" {} ", notice), theme.error));
+    }
+    let input_style = if cooldown.is_some() || (app.waiting_for_turn() && !app.chat_mode) {
+        theme.dim
+    } else {
+        theme.input
+    };
+    // Long input scrolls sideways so its end, where the cursor is, stays in view
+    let inner = chunks[4].inner(&Margin::new(1, 1));
+    let width = u16::try_from(Span::raw(input_text).width()).unwrap_or(u16::MAX);
+    let scroll = width.saturating_sub(inner.width.saturating_sub(1));
+    let input = Paragraph::new(input_text.to_string())
+        .style(input_style)
+        .scroll((0, scroll))
+        .block(input_block);
+    f.render_widget(input, chunks[4]);
+    // Watchers have nothing to type, and popups go on top of the input
+    let popup_open = app.show_help
+        || app.show_leaderboard
+        || app.palette.is_some()
+        || (app.round_over() && app.round_results.is_some());
+    if !app.lurking && app.paused_at.is_none() && !popup_open && inner.width > 0 {
+        f.set_cursor(inner.x + width - scroll, inner.y);
+    }
+
+    // Stats
+    if app.hide_stats {
+        return click_areas;
+    }
+    let stats_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[5]);
+
+    let mut users_line = format!("Active users: {}", app.users_count);
+    if let Some(active) = app.active_typists {
+        users_line.push_str(&format!(" ({} typing)", active));
+    }
+    let (words, chars) = sentence_size(&app.sentence);
+    let words_left = match app.max_words {
+        Some(max_words) => format!(" ({} left)", max_words.saturating_sub(words)),
+        None => String::new(),
+    };
+    let sentence_line = match app.race {
+        Some(ref race) => format!(
+            "Race: {} WPM | Accuracy: {} | Errors: {} ({} corrected)",
+            race.racer(&app.username).map_or(0, |racer| racer.wpm),
+            race.accuracy()
+                .map_or_else(|| "--".to_string(), |accuracy| format!("{:.0}%", accuracy)),
+            race.errors,
+            race.corrected
+        ),
+        None => format!(
+            "Sentence: {} words{}, {} characters",
+            words, words_left, chars
+        ),
+    };
+    let mut stats_lines = vec![
+        Line::from(match app.accuracy {
+            Some(accuracy) => format!(
+                "Characters typed: {} | Accuracy: {:.0}%",
+                app.chars_typed, accuracy
+            ),
+            None => format!("Characters typed: {}", app.chars_typed),
+        }),
+        Line::from(users_line),
+        Line::from(sentence_line),
+    ];
+    if !legend.is_empty() {
+        let mut spans = vec![Span::raw("Authors:")];
+        for name in legend {
+            spans.push(Span::styled(format!(" ■ {}", name), theme.author(name)));
+        }
+        stats_lines.push(Line::from(spans));
+    }
+    let mut stats_block = Block::default().borders(Borders::ALL).title("📊 Stats");
+    if let Some((at, ref status)) = app.copy_status {
+        if at.elapsed() < COPY_STATUS_TIME {
+            let (message, style) = match status {
+                Ok(message) => (message, theme.success),
+                Err(message) => (message, theme.error),
+            };
+            stats_block = stats_block.title_bottom(Span::styled(format!(" {} ", message), style));
+        }
+    }
+    let stats_left = Paragraph::new(stats_lines)
+        .style(theme.title)
+        .block(stats_block);
+    f.render_widget(stats_left, stats_chunks[0]);
+
+    let help_text = vec![
+        Line::from("ESC: Back to menu"),
+        Line::from("F1: Toggle help  :  Commands"),
+    ];
+    let controls = Paragraph::new(help_text)
+        .style(theme.muted)
+        .block(Block::default().borders(Borders::ALL).title("🎮 Controls"));
+    f.render_widget(controls, stats_chunks[1]);
+    click_areas.push((stats_chunks[1], Action::ToggleHelp));
+    click_areas
+}
+
+/// Words and characters in the sentence as everyone sees it, runs of
+/// whitespace counting as one space
+fn sentence_size(sentence: &str) -> (usize, usize) {
+    let words = sentence.split_whitespace();
+    let (count, chars) = words.fold((0, 0), |(count, chars), word| {
+        (count + 1, chars + word.chars().count())
+    });
+    // The spaces between the words
+    (count, chars + count.saturating_sub(1))
+}
+
+/// Authors shown in the stats legend
+const MAX_LEGEND_AUTHORS: usize = 4;
+
+/// One line telling how the timed round is going
+fn round_clock(round: RoundState, theme: &Theme) -> Paragraph<'static> {
+    let (text, style) = match round {
+        RoundState::Waiting { length_secs } => (
+            format!(
+                "⏱  {} round, the clock starts with the next word",
+                clock(length_secs)
+            ),
+            theme.muted,
+        ),
+        RoundState::Running { remaining_secs } => (
+            format!("⏱  {} left", clock(remaining_secs)),
+            if remaining_secs <= ROUND_HURRY_SECS {
+                theme.error.add_modifier(Modifier::BOLD)
+            } else {
+                theme.accent
+            },
+        ),
+        RoundState::Over { next_in_secs } => (
+            format!("🏁 Round over, the next one starts in {}s", next_in_secs),
+            theme.error.add_modifier(Modifier::BOLD),
+        ),
+    };
+    Paragraph::new(text)
+        .style(style)
+        .alignment(Alignment::Center)
+}
+
+/// One line telling how the race is going, and how we did once it's over
+fn race_status(race: &RaceView, username: &str, theme: &Theme) -> Paragraph<'static> {
+    let secs = race.secs_left();
+    let (text, style) = match race.state {
+        RaceState::Waiting => ("🏁 Race mode, waiting for typists".to_string(), theme.muted),
+        RaceState::Countdown { .. } => (
+            format!("🏁 Get ready, the race starts in {}s", secs),
+            theme.accent.add_modifier(Modifier::BOLD),
+        ),
+        RaceState::Running { .. } => (
+            format!("🏁 Race on, {} left", clock(secs)),
+            if secs <= ROUND_HURRY_SECS {
+                theme.error.add_modifier(Modifier::BOLD)
+            } else {
+                theme.accent
+            },
+        ),
+        RaceState::Over { .. } => {
+            let placed = race
+                .racer(username)
+                .and_then(|racer| racer.place)
+                .map(|place| format!(" You placed #{},", place))
+                .unwrap_or_default();
+            (
+                format!("🏁 Race over.{} the next one starts in {}s", placed, secs),
+                theme.success.add_modifier(Modifier::BOLD),
+            )
+        }
+    };
+    Paragraph::new(text)
+        .style(style)
+        .alignment(Alignment::Center)
+}
+
+/// Racers shown below the passage, the rest are behind
+const MAX_RACERS_SHOWN: usize = 8;
+
+/// Width of a racer's progress bar
+const RACE_BAR_WIDTH: usize = 20;
+
+/// The passage with what we typed over it, and the standings below
+fn draw_race(f: &mut Frame, app: &App, race: &RaceView, area: Rect) {
+    let theme = &app.settings.theme;
+    let standings_height = if race.racers.is_empty() {
+        0
+    } else {
+        race.racers.len().min(MAX_RACERS_SHOWN) as u16 + 2
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(standings_height)])
+        .split(area);
+
+    let passage = if race.passage.is_empty() {
+        Line::from(Span::styled(
+            "The passage shows up once someone is ready to race",
+            theme.dim,
+        ))
+    } else {
+        typed_text(&race.passage, race.typed(), race.is_running(), theme)
+    };
+    let passage = Paragraph::new(passage).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("🏁 Race Passage")
+            .border_style(theme.border),
+    );
+    f.render_widget(passage, chunks[0]);
+    if race.racers.is_empty() {
+        return;
+    }
+
+    let length = race.passage.chars().count().max(1);
+    let name_width = usize::from(chunks[1].width.saturating_sub(2))
+        .saturating_sub(4 + RACE_BAR_WIDTH + 10)
+        .clamp(4, MAX_NAME_CHARS);
+    let lines: Vec<Line> = race
+        .racers
+        .iter()
+        .take(MAX_RACERS_SHOWN)
+        .map(|racer| {
+            let filled = racer.chars.min(length) * RACE_BAR_WIDTH / length;
+            let style = if racer.name == app.username {
+                theme.accent.add_modifier(Modifier::BOLD)
+            } else {
+                theme.author(&racer.name)
+            };
+            Line::from(vec![
+                Span::styled(
+                    racer
+                        .place
+                        .map_or_else(|| "    ".to_string(), |place| format!("#{:<3}", place)),
+                    theme.success,
+                ),
+                Span::styled(
+                    format!("{:<width$.width$} ", racer.name, width = name_width),
+                    style,
+                ),
+                Span::styled("█".repeat(filled), style),
+                Span::styled("░".repeat(RACE_BAR_WIDTH - filled), theme.dim),
+                Span::styled(format!(" {:>3} WPM", racer.wpm), theme.text),
+            ])
+        })
+        .collect();
+    let standings = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("🏆 Racers ({})", race.racers.len()))
+            .border_style(theme.border),
+    );
+    f.render_widget(standings, chunks[1]);
+}
+
+/// Typed characters in green, mistakes in red, the cursor highlighted and
+/// the rest of `text` dim
+fn typed_text(text: &str, typed: &str, show_cursor: bool, theme: &Theme) -> Line<'static> {
+    let mut typed = typed.chars();
+    let mut spans: Vec<Span> = Vec::new();
+    let mut cursor_shown = !show_cursor;
+    for wanted in text.chars() {
+        let (shown, style) = match typed.next() {
+            Some(c) if c == wanted => (wanted, theme.success),
+            // A wrong space would be invisible
+            Some(_) if wanted == ' ' => ('·', theme.error),
+            Some(_) => (wanted, theme.error.add_modifier(Modifier::UNDERLINED)),
+            None if !cursor_shown => {
+                cursor_shown = true;
+                (wanted, theme.text.add_modifier(Modifier::REVERSED))
+            }
+            None => (wanted, theme.dim),
+        };
+        match spans.last_mut() {
+            Some(span) if span.style == style => span.content.to_mut().push(shown),
+            _ => spans.push(Span::styled(shown.to_string(), style)),
+        }
+    }
+    Line::from(spans)
+}
+
+/// Typing time before the practice header shows a speed
+const MIN_PRACTICE_SPEED_TIME: Duration = Duration::from_secs(3);
+
+/// The test while it runs, the results once it's over
+fn draw_practice_screen(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.settings.theme;
+    let Some(ref practice) = app.practice else {
+        return;
+    };
+    if practice.is_finished() {
+        draw_practice_results(f, practice, area, theme);
+        return;
+    }
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // Header
+            Constraint::Min(5),    // Words
+            Constraint::Length(3), // Input
+            Constraint::Length(3), // Controls
+        ])
+        .split(area);
+
+    let results = practice.results();
+    let header_text = if practice.is_started() {
+        format!(
+            "⌨  Practice | {} left | {} WPM | Accuracy: {:.0}%",
+            clock(practice.time_left().as_secs_f64().ceil() as u64),
+            // A few keys in, the speed is all over the place
+            if practice.elapsed() < MIN_PRACTICE_SPEED_TIME {
+                "--".to_string()
+            } else {
+                format!("{:.0}", results.wpm)
+            },
+            results.accuracy
+        )
+    } else {
+        format!(
+            "⌨  Practice | the {}s clock starts with your first key",
+            practice::TEST_LENGTH.as_secs()
+        )
+    };
+    let header = Paragraph::new(header_text)
+        .style(theme.success.add_modifier(Modifier::BOLD))
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL));
+    f.render_widget(header, chunks[0]);
+
+    // Keep the line we're typing on in view, one line of context above it
+    let inner = chunks[1].inner(&Margin::new(1, 1));
+    let at = practice.typed().chars().count();
+    let word_end: String = practice
+        .text
+        .chars()
+        .enumerate()
+        .take_while(|&(i, c)| i < at || c != ' ')
+        .map(|(_, c)| c)
+        .collect();
+    let line = Paragraph::new(word_end)
+        .wrap(Wrap { trim: false })
+        .line_count(inner.width);
+    let top = u16::try_from(line.saturating_sub(2)).unwrap_or(u16::MAX);
+    let words = Paragraph::new(typed_text(&practice.text, practice.typed(), true, theme))
+        .wrap(Wrap { trim: false })
+        .scroll((top, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("📝 Practice Words")
+                .border_style(theme.border),
+        );
+    f.render_widget(words, chunks[1]);
+
+    let input_text = practice.current_word();
+    let input = Paragraph::new(input_text.to_string())
+        .style(theme.input)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("✍️  Type the words above, no ENTER needed")
+                .border_style(theme.success),
+        );
+    f.render_widget(input, chunks[2]);
+    let inner = chunks[2].inner(&Margin::new(1, 1));
+    let width = u16::try_from(Span::raw(input_text).width()).unwrap_or(u16::MAX);
+    if !app.show_help && inner.width > width {
+        f.set_cursor(inner.x + width, inner.y);
+    }
+
+    let controls = Paragraph::new("ESC: Back to menu")
+        .style(theme.muted)
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title("🎮 Controls"));
+    f.render_widget(controls, chunks[3]);
+}
+
+/// Past sessions newest first, under a summary of all of them
+fn draw_history_screen(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.settings.theme;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // Summary
+            Constraint::Min(3),    // Sessions
+        ])
+        .split(area);
+
+    let summary_text = if app.history.is_empty() {
+        "📜 No sessions yet, they're kept here once you've typed".to_string()
+    } else {
+        let best = app.history.iter().map(|s| s.wpm).fold(0.0, f64::max);
+        let average = app.history.iter().map(|s| s.wpm).sum::<f64>() / app.history.len() as f64;
+        let total: Duration = app.history.iter().map(|s| s.duration).sum();
+        format!(
+            "📜 {} sessions | Best: {:.0} WPM | Average: {:.0} WPM | Typed for {}",
+            app.history.len(),
+            best,
+            average,
+            clock(total.as_secs())
+        )
+    };
+    let summary = Paragraph::new(summary_text)
+        .style(theme.success.add_modifier(Modifier::BOLD))
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(theme.border),
+        );
+    f.render_widget(summary, chunks[0]);
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    // Where and speed keep their columns, the server gets what's left
+    let where_width = usize::from(chunks[1].width.saturating_sub(2 + 15 + 25));
+    let mut lines = vec![Line::from(Span::styled(
+        format!(
+            "{:<15}{:<width$.width$} {:>7} {:>8} {:>7}",
+            "When",
+            "Where",
+            "WPM",
+            "Accuracy",
+            "Time",
+            width = where_width
+        ),
+        theme.muted,
+    ))];
+    for session in app.history.iter().skip(app.history_scroll) {
+        let place = session.server.as_deref().unwrap_or("practice");
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("{:<15}", time_ago(now.saturating_sub(session.ended_at))),
+                theme.muted,
+            ),
+            Span::styled(
+                format!("{:<width$.width$}", place, width = where_width),
+                theme.text,
+            ),
+            Span::styled(
+                format!(" {:>7.0}", session.wpm),
+                theme.accent.add_modifier(Modifier::BOLD),
+            ),
+            Span::styled(
+                format!(
+                    " {:>7.1}% {:>7}",
+                    session.accuracy,
+                    clock(session.duration.as_secs())
+                ),
+                theme.text,
+            ),
+        ]));
+    }
+    let sessions = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("History (↑/↓ or PgUp/PgDn to scroll, ESC for the menu)")
+            .border_style(theme.border),
+    );
+    f.render_widget(sessions, chunks[1]);
 }
 
-fn draw_connecting_screen(f: &mut Frame, _app: &App) {
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([Constraint::Min(0)])
-        .split(f.size());
+/// Roughly how long ago, for the history
+fn time_ago(secs: u64) -> String {
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{} min ago", secs / 60),
+        3600..=86_399 => format!("{} h ago", secs / 3600),
+        86_400..=172_799 => "yesterday".to_string(),
+        _ => format!("{} days ago", secs / 86_400),
+    }
+}
 
-    let connecting = Paragraph::new(vec![
+fn draw_practice_results(f: &mut Frame, practice: &Practice, area: Rect, theme: &Theme) {
+    let results = practice.results();
+    let row = |label: &str, value: String| {
+        Line::from(vec![
+            Span::styled(format!("{:>12}  ", label), theme.muted),
+            Span::styled(
+                format!("{:<16}", value),
+                theme.text.add_modifier(Modifier::BOLD),
+            ),
+        ])
+    };
+    let lines = vec![
         Line::from(""),
-        Line::from("🔄 Connecting to server..."),
+        Line::from(Span::styled(
+            format!("{:.0} WPM", results.wpm),
+            theme.accent.add_modifier(Modifier::BOLD),
+        )),
         Line::from(""),
-        Line::from("Please wait while we establish the connection."),
+        row("Raw speed", format!("{:.0} WPM", results.raw_wpm)),
+        row("Accuracy", format!("{:.1}%", results.accuracy)),
+        row(
+            "Errors",
+            format!("{} ({} corrected)", results.errors, results.corrected),
+        ),
+        row("Time", format!("{:.1}s", results.time.as_secs_f64())),
         Line::from(""),
-        Line::from("Press ESC to cancel"),
-    ])
-    .style(Style::default().fg(Color::Yellow))
-    .alignment(Alignment::Center)
-    .block(Block::default().borders(Borders::ALL).title("Connecting"));
-
-    f.render_widget(connecting, chunks[0]);
+        Line::from(vec![
+            Span::styled("Press ", theme.text),
+            Span::styled("ENTER", theme.success.add_modifier(Modifier::BOLD)),
+            Span::styled(" to go again, ", theme.text),
+            Span::styled("ESC", theme.error.add_modifier(Modifier::BOLD)),
+            Span::styled(" for the menu", theme.text),
+        ]),
+    ];
+    let popup = centered_rect(60, 60, area);
+    let results = Paragraph::new(lines).alignment(Alignment::Center).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("🏁 Practice Results")
+            .border_style(theme.border),
+    );
+    f.render_widget(results, popup);
 }
 
-fn draw_game_screen(f: &mut Frame, app: &App) {
-    let chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(3), // Header
-            Constraint::Min(5),    // Sentence display
-            Constraint::Length(3), // Input
-            Constraint::Length(4), // Stats
-        ])
-        .split(f.size());
-
-    // Header with connection status
-    let header = Paragraph::new(format!(
-        "🎮 Chaos Type | Status: {} | Speed: {:.1} WPM",
-        app.connection_status, app.typing_speed
-    ))
-    .style(
-        Style::default()
-            .fg(Color::Green)
-            .add_modifier(Modifier::BOLD),
-    )
-    .alignment(Alignment::Center)
-    .block(Block::default().borders(Borders::ALL));
-    f.render_widget(header, chunks[0]);
+/// `secs` as `m:ss`
+fn clock(secs: u64) -> String {
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
 
-    // Sentence display
-    let sentence_text = if app.sentence.is_empty() {
-        "Start typing to begin the collaborative sentence...".to_string()
+/// The newest chat lines that fit, or older ones when scrolled back
+fn draw_chat_pane(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.settings.theme;
+    let end = app.chat_history.len() - app.chat_scroll.min(app.chat_history.len());
+    let start = end.saturating_sub(area.height.saturating_sub(2) as usize);
+    let lines: Vec<Line> = app.chat_history[start..end]
+        .iter()
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    let title = if app.chat_scroll > 0 {
+        format!("💬 Chat (↑{} older, PgDn for newer)", app.chat_scroll)
     } else {
-        app.sentence.clone()
+        "💬 Chat".to_string()
     };
+    let chat = Paragraph::new(lines).style(theme.text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(theme.chat_border),
+    );
+    f.render_widget(chat, area);
+}
 
-    let sentence = Paragraph::new(sentence_text)
-        .style(Style::default().fg(Color::White))
-        .alignment(Alignment::Left)
-        .wrap(Wrap { trim: false })
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("📝 Current Sentence")
-                .border_style(Style::default().fg(Color::Blue)),
-        );
-    f.render_widget(sentence, chunks[1]);
+/// `authors` covers the whole sentence, so it's matched up with the visible
+/// words from the end in case `--render-cap` cut off the start
+fn styled_sentence(
+    sentence: &str,
+    style: DisplayStyle,
+    authors: Option<&[Option<String>]>,
+    separator: Option<&str>,
+    theme: &Theme,
+) -> Line<'static> {
+    let words: Vec<&str> = sentence.split_whitespace().collect();
+    let capped = words.first() == Some(&"…");
+    let color_of = |i: usize| {
+        if capped && i == 0 {
+            return None;
+        }
+        let authors = authors?;
+        let index = (authors.len() + i).checked_sub(words.len())?;
+        theme.author_color(authors.get(index)?.as_deref()?)
+    };
+    match style {
+        DisplayStyle::Plain if authors.is_none() && separator.is_none() => {
+            Line::from(sentence.to_string())
+        }
+        DisplayStyle::Plain => {
+            let separator = separator.unwrap_or(" ");
+            let mut spans = Vec::new();
+            for (i, word) in words.iter().enumerate() {
+                if i > 0 {
+                    spans.push(Span::raw(separator.to_string()));
+                }
+                let style = color_of(i).map_or_else(Style::default, |c| Style::default().fg(c));
+                spans.push(Span::styled(word.to_string(), style));
+            }
+            Line::from(spans)
+        }
+        DisplayStyle::Boxed => {
+            let mut spans = Vec::new();
+            for (i, word) in words.iter().enumerate() {
+                let word_style = color_of(i).map_or(theme.boxed, |color| {
+                    Style::default().fg(Color::Black).bg(color)
+                });
+                spans.push(Span::styled(format!(" {} ", word), word_style));
+                spans.push(Span::raw(" "));
+            }
+            Line::from(spans)
+        }
+        DisplayStyle::Spaced => {
+            let mut spans = Vec::new();
+            for (i, word) in words.iter().enumerate() {
+                if i > 0 {
+                    spans.push(Span::raw("   "));
+                }
+                let spaced = word.chars().map(String::from).collect::<Vec<_>>().join(" ");
+                let style = color_of(i).map_or_else(Style::default, |c| Style::default().fg(c));
+                spans.push(Span::styled(spaced, style));
+            }
+            Line::from(spans)
+        }
+    }
+}
 
-    // Input field
-    let input = Paragraph::new(app.current_input.clone())
-        .style(Style::default().fg(Color::Yellow))
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .title("✍️  Your Word (Press ENTER to send)")
-                .border_style(Style::default().fg(Color::Green)),
-        );
-    f.render_widget(input, chunks[2]);
+/// Just the file name, the directory is the one we were started with and
+/// wouldn't fit the stats pane anyway
+fn saved_status(path: &Path) -> String {
+    let name = path.file_name().unwrap_or(path.as_os_str());
+    format!("💾 Saved to {}", name.to_string_lossy())
+}
 
-    // Stats
-    let stats_chunks = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-        .split(chunks[3]);
+/// Writes the sentence to `chaostype-session-<unix time>.txt` in `dir`,
+/// returning the file's path
+fn save_sentence(sentence: &str, dir: &Path) -> io::Result<PathBuf> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let path = dir.join(format!("chaostype-session-{}.txt", secs));
+    fs::write(&path, format!("{}\n", sentence.trim()))?;
+    Ok(path)
+}
 
-    let stats_left = Paragraph::new(vec![
-        Line::from(format!("Characters typed: {}", app.chars_typed)),
-        Line::from(format!("Active users: {}", app.users_count)),
-    ])
-    .style(Style::default().fg(Color::Cyan))
-    .block(Block::default().borders(Borders::ALL).title("📊 Stats"));
-    f.render_widget(stats_left, stats_chunks[0]);
+/// Roughly the last `cap` characters of the sentence, starting at a whole word
+/// and marked with a leading ellipsis when anything was cut
+fn sentence_tail(sentence: &str, cap: Option<usize>) -> Cow<'_, str> {
+    let Some(start) = cap.and_then(|cap| sentence.char_indices().rev().nth(cap - 1)) else {
+        return Cow::Borrowed(sentence);
+    };
+    let (head, tail) = sentence.split_at(start.0);
+    if head.is_empty() {
+        return Cow::Borrowed(sentence);
+    }
+    // Drop the word the cut landed in
+    let tail = match tail.split_once(char::is_whitespace) {
+        Some((_, rest)) if !head.ends_with(char::is_whitespace) => rest,
+        _ => tail,
+    };
+    Cow::Owned(format!("… {}", tail.trim_start()))
+}
 
-    let help_text = vec![
-        Line::from("ESC: Back to menu"),
-        Line::from("F1: Toggle help"),
-    ];
-    let controls = Paragraph::new(help_text)
-        .style(Style::default().fg(Color::Gray))
-        .block(Block::default().borders(Borders::ALL).title("🎮 Controls"));
-    f.render_widget(controls, stats_chunks[1]);
+/// Shows how a multi-word input will be split on submit, `None` for a single word
+fn word_split_preview(input: &str) -> Option<String> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    if words.len() < 2 {
+        return None;
+    }
+    Some(format!(" → {} words: {} ", words.len(), words.join(" │ ")))
+}
+
+/// Shrinking bar for the remaining share of a cooldown
+fn cooldown_bar(ratio: f64, width: usize) -> String {
+    let filled = ((ratio.clamp(0.0, 1.0) * width as f64).ceil() as usize).min(width);
+    format!("{}{}", "█".repeat(filled), "░".repeat(width - filled))
 }
 
-fn draw_disconnected_screen(f: &mut Frame, app: &App) {
+/// Returns where clicks retry, go back to the menu or quit
+fn draw_disconnected_screen(f: &mut Frame, app: &App, area: Rect) -> Vec<(Rect, Action)> {
+    let theme = &app.settings.theme;
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Min(0)])
-        .split(f.size());
-
-    let mut lines = vec![
-        Line::from(""),
-        Line::from("❌ Connection Lost"),
-        Line::from(""),
-    ];
+        .split(area);
+
+    let mut lines = if app.server_shut_down {
+        vec![
+            Line::from(""),
+            Line::from("👋 Server shutting down"),
+            Line::from(""),
+            Line::from("The server stopped for now, try again in a moment"),
+            Line::from(""),
+        ]
+    } else {
+        vec![
+            Line::from(""),
+            Line::from("❌ Connection Lost"),
+            Line::from(""),
+        ]
+    };
 
     if let Some(ref error) = app.error_message {
         lines.push(Line::from(format!("Error: {}", error)));
         lines.push(Line::from(""));
     }
+    if let Some((attempt, due)) = app.retry {
+        lines.push(Line::from(Span::styled(
+            format!(
+                "🔄 Reconnecting in {}s (attempt {}/{})",
+                due.saturating_duration_since(Instant::now())
+                    .as_secs_f64()
+                    .ceil(),
+                attempt,
+                MAX_RECONNECTS
+            ),
+            theme.accent,
+        )));
+        lines.push(Line::from(""));
+    }
 
+    let mut actions = vec![Action::Connect, Action::Disconnect, Action::Quit];
+    if app.retry.is_some() {
+        actions.insert(1, Action::CancelReconnect);
+    }
+    // One line each, inside the border
+    let inner = chunks[0].inner(&Margin::new(1, 1));
+    let click_areas = actions
+        .iter()
+        .copied()
+        .enumerate()
+        .map(|(i, action)| {
+            let row = Rect {
+                y: inner.y + (lines.len() + i) as u16,
+                height: 1,
+                ..inner
+            };
+            (row.intersection(inner), action)
+        })
+        .collect();
+
+    lines.push(Line::from(vec![
+        Span::styled("Press ", theme.text),
+        Span::styled("ENTER", theme.success.add_modifier(Modifier::BOLD)),
+        Span::styled(
+            if app.retry.is_some() {
+                " to retry now"
+            } else {
+                " to retry connection"
+            },
+            theme.text,
+        ),
+    ]));
+    if app.retry.is_some() {
+        lines.push(Line::from(vec![
+            Span::styled("Press ", theme.text),
+            Span::styled("C", theme.accent.add_modifier(Modifier::BOLD)),
+            Span::styled(" to stop reconnecting", theme.text),
+        ]));
+    }
     lines.extend([
         Line::from(vec![
-            Span::styled("Press ", Style::default().fg(Color::White)),
-            Span::styled(
-                "ENTER",
-                Style::default()
-                    .fg(Color::Green)
-                    .add_modifier(Modifier::BOLD),
-            ),
-            Span::styled(" to retry connection", Style::default().fg(Color::White)),
-        ]),
-        Line::from(vec![
-            Span::styled("Press ", Style::default().fg(Color::White)),
-            Span::styled(
-                "ESC",
-                Style::default()
-                    .fg(Color::Blue)
-                    .add_modifier(Modifier::BOLD),
-            ),
-            Span::styled(" to return to menu", Style::default().fg(Color::White)),
+            Span::styled("Press ", theme.text),
+            Span::styled("ESC", theme.border.add_modifier(Modifier::BOLD)),
+            Span::styled(" to return to menu", theme.text),
         ]),
         Line::from(vec![
-            Span::styled("Press ", Style::default().fg(Color::White)),
-            Span::styled(
-                "Q",
-                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
-            ),
-            Span::styled(" to quit", Style::default().fg(Color::White)),
+            Span::styled("Press ", theme.text),
+            Span::styled("Q", theme.error.add_modifier(Modifier::BOLD)),
+            Span::styled(" to quit", theme.text),
         ]),
     ]);
 
     let disconnected = Paragraph::new(lines)
-        .style(Style::default().fg(Color::Red))
+        .style(theme.error)
         .alignment(Alignment::Center)
         .block(Block::default().borders(Borders::ALL).title("Disconnected"));
 
     f.render_widget(disconnected, chunks[0]);
+    click_areas
+}
+
+/// Width of the fullest histogram bar in the rhythm report
+const RHYTHM_BAR_WIDTH: usize = 30;
+
+fn draw_rhythm_report(f: &mut Frame, report: &Report, area: Rect, theme: &Theme) {
+    let mut lines = vec![
+        Line::from(format!(
+            "Between keys: mean {}ms, median {}ms",
+            report.mean.as_millis(),
+            report.median.as_millis()
+        )),
+        Line::from(match report.mean_pause {
+            Some(pause) => format!("Pause before submitting: mean {}ms", pause.as_millis()),
+            None => "Pause before submitting: no words sent".to_string(),
+        }),
+        Line::from(""),
+    ];
+
+    // One bar per bucket, scaled to the fullest one
+    let most = report.histogram.iter().copied().max().unwrap_or(0).max(1);
+    let mut lower = 0;
+    for (i, count) in report.histogram.iter().enumerate() {
+        let label = match rhythm::BUCKET_BOUNDS_MS.get(i) {
+            Some(upper) => format!("{:>4}-{:<4}ms", lower, upper),
+            None => format!("{:>4}+ ms    ", lower),
+        };
+        if let Some(upper) = rhythm::BUCKET_BOUNDS_MS.get(i) {
+            lower = *upper;
+        }
+        lines.push(Line::from(vec![
+            Span::raw(format!("{} ", label)),
+            Span::styled("█".repeat(count * RHYTHM_BAR_WIDTH / most), theme.title),
+            Span::raw(format!(" {}", count)),
+        ]));
+    }
+
+    let report = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Typing Rhythm"),
+    );
+    f.render_widget(report, area);
 }
 
-fn draw_help_popup(f: &mut Frame) {
+/// Returns the popup's area, a click on it closes it
+fn draw_help_popup(f: &mut Frame, theme: &Theme) -> Rect {
     let popup_area = centered_rect(60, 70, f.size());
     f.render_widget(Clear, popup_area);
 
@@ -689,16 +3288,34 @@ fn draw_help_popup(f: &mut Frame) {
         Line::from(""),
         Line::from(vec![Span::styled(
             "Chaos Type - Help",
-            Style::default()
-                .fg(Color::Yellow)
-                .add_modifier(Modifier::BOLD),
+            theme.accent.add_modifier(Modifier::BOLD),
         )]),
         Line::from(""),
         Line::from("🎮 Game Controls:"),
         Line::from("  • Type words and press ENTER to send"),
+        Line::from("  • Multi-line pastes follow --paste-mode"),
         Line::from("  • ESC: Return to welcome screen"),
         Line::from("  • Q: Quit application"),
         Line::from("  • H: Toggle this help"),
+        Line::from("  • : Open the command palette"),
+        Line::from("  • F2: Show/hide the WPM leaderboard"),
+        Line::from("  • TAB: Switch between typing words and chatting"),
+        Line::from("  • PgUp/PgDn: Scroll the sentence, or the chat while chatting"),
+        Line::from("  • Mouse: Click a panel to connect, retry, switch to chat or open help"),
+        Line::from("  • F3: Hide/show the stats pane"),
+        Line::from("  • F4: Copy the sentence, or save it to a file without a clipboard"),
+        Line::from("  • Ctrl+S: Save the sentence to a file"),
+        Line::from("  • F5: Reset your speed and accuracy, the sentence stays"),
+        Line::from("  • F6: Pause while you're away, your speed waits for you"),
+        Line::from("  • F7: Switch to the next color theme"),
+        Line::from("  • Ctrl+Z / Ctrl+Y: Undo/redo edits to your word"),
+        Line::from("  • Ctrl+Z on an empty input: Take back your last word"),
+        Line::from("  • Ctrl+R twice: Take back every word you added"),
+        Line::from("  • Ctrl+L twice: Clear the sentence for everyone"),
+        Line::from("  • TAB (welcome screen): Switch to the next recent server"),
+        Line::from("  • P (welcome screen): Practice offline, a 30s typing test"),
+        Line::from("  • S (welcome screen): Past sessions, newest first"),
+        Line::from("  • C (disconnected): Stop reconnecting"),
         Line::from(""),
         Line::from("📝 How to Play:"),
         Line::from("  • Connect to the server"),
@@ -711,6 +3328,9 @@ fn draw_help_popup(f: &mut Frame) {
         Line::from("  • Typing speed tracking (WPM)"),
         Line::from("  • Live sentence updates"),
         Line::from("  • Multi-user support"),
+        Line::from("  • Servers from before the JSON protocol run"),
+        Line::from("    in compatibility mode:"),
+        Line::from("    sentence only, no server notices"),
         Line::from(""),
         Line::from("🔗 Connection:"),
         Line::from("  • Run with: ./client [IP_ADDRESS]"),
@@ -718,17 +3338,9 @@ fn draw_help_popup(f: &mut Frame) {
         Line::from("  • Example: ./client 192.168.1.100"),
         Line::from(""),
         Line::from(vec![
-            Span::styled("Press ", Style::default().fg(Color::White)),
-            Span::styled(
-                "H",
-                Style::default()
-                    .fg(Color::Blue)
-                    .add_modifier(Modifier::BOLD),
-            ),
-            Span::styled(
-                " again to close this help",
-                Style::default().fg(Color::White),
-            ),
+            Span::styled("Press ", theme.text),
+            Span::styled("H", theme.border.add_modifier(Modifier::BOLD)),
+            Span::styled(" again to close this help", theme.text),
         ]),
     ];
 
@@ -739,10 +3351,223 @@ fn draw_help_popup(f: &mut Frame) {
             Block::default()
                 .borders(Borders::ALL)
                 .title(" Help")
-                .border_style(Style::default().fg(Color::Yellow)),
+                .border_style(theme.accent),
         );
 
     f.render_widget(help, popup_area);
+    popup_area
+}
+
+/// Returns the popup's area, a click on it closes it
+/// Everyone in the room with their speed, ourselves highlighted
+fn draw_players_panel(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.settings.theme;
+    let lines: Vec<Line> = app
+        .players
+        .iter()
+        .map(|player| {
+            let style = if player.name == app.username {
+                theme.accent.add_modifier(Modifier::BOLD)
+            } else {
+                theme.author(&player.name)
+            };
+            let status = match (player.watching, player.wpm) {
+                (true, _) => Span::styled("👀", theme.dim),
+                (false, Some(wpm)) => Span::styled(format!("{:>3} WPM", wpm), theme.text),
+                (false, None) => Span::styled("  - WPM", theme.dim),
+            };
+            // Long names are cut short so the speed stays in view
+            let name_width = usize::from(area.width.saturating_sub(2 + 8));
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<width$.width$}", player.name, width = name_width),
+                    style,
+                ),
+                Span::raw(" "),
+                status,
+            ])
+        })
+        .collect();
+
+    let panel = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!("👥 Players ({})", app.players.len()))
+            .border_style(theme.border),
+    );
+    f.render_widget(panel, area);
+}
+
+/// Players ranked by the words they wrote, with their speed
+fn draw_scoreboard(f: &mut Frame, app: &App, area: Rect) {
+    let theme = &app.settings.theme;
+    // Rank, words and speed keep their columns, the name gets the rest
+    let name_width = usize::from(area.width.saturating_sub(2 + 4 + 11));
+    let mut lines = vec![Line::from(Span::styled(
+        format!(
+            "{:<4}{:<width$.width$} {:>5} {:>4}",
+            "#",
+            "Player",
+            "Words",
+            "WPM",
+            width = name_width
+        ),
+        theme.muted,
+    ))];
+    for (rank, score) in app.scoreboard.iter().enumerate() {
+        let style = if score.name == app.username {
+            theme.accent.add_modifier(Modifier::BOLD)
+        } else {
+            theme.author(&score.name)
+        };
+        let wpm = score
+            .wpm
+            .map_or_else(|| "-".to_string(), |wpm| wpm.to_string());
+        lines.push(Line::from(vec![
+            Span::raw(format!("{:>2}. ", rank + 1)),
+            Span::styled(
+                format!("{:<width$.width$}", score.name, width = name_width),
+                style,
+            ),
+            Span::styled(format!(" {:>5} {:>4}", score.words, wpm), theme.text),
+        ]));
+    }
+
+    let panel = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("🏆 Most words")
+            .border_style(theme.border),
+    );
+    f.render_widget(panel, area);
+}
+
+fn draw_leaderboard_popup(f: &mut Frame, app: &App) -> Rect {
+    let theme = &app.settings.theme;
+    let popup_area = centered_rect(40, 50, f.size());
+    f.render_widget(Clear, popup_area);
+
+    let mut lines = vec![Line::from("")];
+    if app.server_protocol == 0 {
+        lines.push(Line::from("  This server has no leaderboard"));
+    } else if app.leaderboard.is_empty() {
+        lines.push(Line::from("  Nobody has typed yet"));
+    }
+    for (rank, (name, wpm)) in app.leaderboard.iter().enumerate() {
+        let style = if *name == app.username {
+            theme.accent.add_modifier(Modifier::BOLD)
+        } else {
+            theme.author(name)
+        };
+        lines.push(Line::from(vec![
+            Span::raw(format!("  {:>2}. ", rank + 1)),
+            Span::styled(format!("{:<24}", name), style),
+            Span::raw(format!(" {:>3} WPM", wpm)),
+        ]));
+    }
+
+    let leaderboard = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" 🏆 Leaderboard (F2 to close) ")
+            .border_style(theme.accent),
+    );
+    f.render_widget(leaderboard, popup_area);
+    popup_area
+}
+
+/// The results screen between rounds: what the sentence came to and who
+/// wrote how much of it
+fn draw_round_results(f: &mut Frame, app: &App, sentence: &str, scores: &[Score]) {
+    let theme = &app.settings.theme;
+    let popup_area = centered_rect(60, 60, f.size());
+    f.render_widget(Clear, popup_area);
+
+    let title = match app.round {
+        Some(RoundState::Over { next_in_secs }) => {
+            format!(" 🏁 Round over, next one in {}s ", next_in_secs)
+        }
+        _ => " 🏁 Round over ".to_string(),
+    };
+    let mut lines = vec![
+        Line::from(""),
+        Line::from(Span::styled(
+            if sentence.is_empty() {
+                "Nobody wrote anything".to_string()
+            } else {
+                sentence.to_string()
+            },
+            theme.text,
+        )),
+        Line::from(""),
+        Line::from(Span::styled(
+            format!("  {:<4}{:<24} {:>5} {:>5}", "", "Player", "Words", "WPM"),
+            theme.muted,
+        )),
+    ];
+    for (rank, score) in scores.iter().enumerate() {
+        let style = if score.name == app.username {
+            theme.accent.add_modifier(Modifier::BOLD)
+        } else {
+            theme.author(&score.name)
+        };
+        let wpm = score
+            .wpm
+            .map_or_else(|| "--".to_string(), |wpm| wpm.to_string());
+        lines.push(Line::from(vec![
+            Span::raw(format!("  {:>2}. ", rank + 1)),
+            Span::styled(format!("{:<24}", score.name), style),
+            Span::raw(format!(" {:>5} {:>5}", score.words, wpm)),
+        ]));
+    }
+
+    let results = Paragraph::new(lines).wrap(Wrap { trim: false }).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(theme.accent),
+    );
+    f.render_widget(results, popup_area);
+}
+
+fn draw_command_palette(f: &mut Frame, palette: &Palette, state: &AppState, theme: &Theme) {
+    let popup_area = centered_rect(50, 40, f.size());
+    f.render_widget(Clear, popup_area);
+
+    let mut lines = vec![
+        Line::from(vec![
+            Span::styled(": ", theme.accent),
+            Span::styled(palette.query.clone(), theme.text),
+        ]),
+        Line::from(""),
+    ];
+
+    let matches = palette.matches(state);
+    if matches.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "No matching commands",
+            theme.muted,
+        )));
+    }
+    for (i, action) in matches.iter().enumerate() {
+        let style = if i == palette.selected {
+            theme.selected
+        } else {
+            theme.text
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!(" {:<28}", action.label()), style),
+            Span::styled(format!(" {}", action.key_hint()), theme.muted),
+        ]));
+    }
+
+    let commands = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Commands (↑/↓ select, ENTER run, ESC close) ")
+            .border_style(theme.accent),
+    );
+    f.render_widget(commands, popup_area);
 }
 
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
@@ -765,26 +3590,411 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         .split(popup_layout[1])[1]
 }
 
-fn is_valid_ip_or_hostname(addr: &str) -> bool {
-    // Check if it's a valid IPv4 address
-    if addr.parse::<std::net::Ipv4Addr>().is_ok() {
-        return true;
+/// Replaces every whole word found in `blocklist` with one asterisk per character.
+/// Only the displayed text is masked, the server copy is left alone.
+fn mask_words(text: &str, blocklist: &HashSet<String>) -> String {
+    fn flush(word: &mut String, out: &mut String, blocklist: &HashSet<String>) {
+        if blocklist.contains(&word.to_lowercase()) {
+            out.push_str(&"*".repeat(word.chars().count()));
+        } else {
+            out.push_str(word);
+        }
+        word.clear();
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut word = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut out, blocklist);
+            out.push(c);
+        }
+    }
+    flush(&mut word, &mut out, blocklist);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const PASTE: &str = "first line\n\n  second  \nthird\n";
+
+    fn pasting(mode: PasteMode) -> App {
+        let mut app = App {
+            state: AppState::Connected,
+            ..App::default()
+        };
+        app.settings.paste_mode = mode;
+        app
+    }
+
+    #[test]
+    fn split_paste_sends_every_line() {
+        let mut app = pasting(PasteMode::Split);
+        assert_eq!(
+            app.paste(PASTE),
+            ["first line", "second", "third"].map(String::from)
+        );
+        assert_eq!(app.unacked.len(), 3);
+        assert_eq!(app.chars_typed, "first line second third ".len());
+    }
+
+    #[test]
+    fn join_paste_sends_one_line() {
+        let mut app = pasting(PasteMode::Join);
+        assert_eq!(app.paste(PASTE), ["first line second third".to_string()]);
+        assert_eq!(app.unacked.len(), 1);
+    }
+
+    #[test]
+    fn reject_paste_sends_nothing() {
+        let mut app = pasting(PasteMode::Reject);
+        assert!(app.paste(PASTE).is_empty());
+        assert!(app.unacked.is_empty());
+        assert_eq!(app.chars_typed, 0);
+        assert!(app.notice.is_some());
+    }
+
+    #[test]
+    fn one_line_paste_goes_into_the_input() {
+        let mut app = pasting(PasteMode::Split);
+        assert!(app.paste("  hello \n").is_empty());
+        assert_eq!(app.current_input, "hello");
+    }
+
+    #[test]
+    fn paste_while_reconnecting_is_dropped_uncounted() {
+        let mut app = pasting(PasteMode::Split);
+        app.reconnect_attempt = Some(1);
+        assert!(app.paste(PASTE).is_empty());
+        assert!(app.unacked.is_empty());
+        assert_eq!(app.chars_typed, 0);
+    }
+
+    #[test]
+    fn pasted_slashes_are_just_words() {
+        let mut app = pasting(PasteMode::Split);
+        // Commands are their own messages, so these can't run one
+        assert_eq!(
+            app.paste("/retract\nhello\n/clear"),
+            ["/retract", "hello", "/clear"].map(String::from)
+        );
+        assert_eq!(app.retract_armed, None);
+        assert_eq!(app.clear_armed, None);
+    }
+
+    #[test]
+    fn chat_waits_out_a_reconnect() {
+        let mut app = App {
+            chat_mode: true,
+            current_input: "hi all".to_string(),
+            reconnect_attempt: Some(1),
+            ..App::default()
+        };
+        assert_eq!(app.send_chat(), None);
+        assert_eq!(app.current_input, "hi all");
+
+        app.reconnect_attempt = None;
+        assert_eq!(app.send_chat(), Some("hi all".to_string()));
+    }
+
+    #[test]
+    fn edits_undo_and_redo_in_order() {
+        let mut history = EditHistory::default();
+        let mut input = String::new();
+        for edit in ["h", "he", "hel", "he"] {
+            history.record(std::mem::replace(&mut input, edit.to_string()));
+        }
+
+        assert!(history.undo(&mut input));
+        assert_eq!(input, "hel");
+        assert!(history.undo(&mut input));
+        assert_eq!(input, "he");
+        history.redo(&mut input);
+        assert_eq!(input, "hel");
+        for _ in 0..3 {
+            assert!(history.undo(&mut input));
+        }
+        assert_eq!(input, "");
+        assert!(!history.undo(&mut input));
+        assert_eq!(input, "");
+    }
+
+    #[test]
+    fn a_new_edit_drops_what_could_be_redone() {
+        let mut history = EditHistory::default();
+        let mut input = "ab".to_string();
+        history.record("a".to_string());
+        assert!(history.undo(&mut input));
+        history.record(std::mem::replace(&mut input, "ax".to_string()));
+        history.redo(&mut input);
+        assert_eq!(input, "ax");
+    }
+
+    #[test]
+    fn edit_history_forgets_the_oldest() {
+        let mut history = EditHistory::default();
+        let mut input = String::new();
+        for len in 1..=MAX_EDIT_HISTORY + 5 {
+            history.record(std::mem::replace(&mut input, "x".repeat(len)));
+        }
+        let mut undone = 0;
+        while history.undo(&mut input) {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_EDIT_HISTORY);
+        assert_eq!(input, "x".repeat(5));
+    }
+
+    #[test]
+    fn undo_and_redo_act_on_the_input_until_it_is_sent() {
+        let mut app = App {
+            state: AppState::Connected,
+            server_protocol: PROTOCOL_VERSION,
+            ..App::default()
+        };
+        app.edit_input(|input| input.push_str("helo"));
+        app.edit_input(|input| {
+            input.pop();
+        });
+        app.edit_input(|input| input.push_str("lo"));
+        // Not an edit, nothing to undo
+        app.edit_input(|_| {});
+
+        assert!(app.apply_action(Action::Undo).is_none());
+        assert_eq!(app.current_input, "hel");
+        assert!(app.apply_action(Action::Redo).is_none());
+        assert_eq!(app.current_input, "hello");
+
+        assert_eq!(app.send_word(), Some("hello".to_string()));
+        // Once sent, undo takes the word back from the server instead
+        assert!(matches!(
+            app.apply_action(Action::Undo),
+            Some(AppEvent::UndoWord)
+        ));
+        assert_eq!(app.current_input, "");
+    }
+
+    /// A terminal that went away: drawing fails, everything else works
+    struct GoneBackend(ratatui::backend::TestBackend);
+
+    impl Backend for GoneBackend {
+        fn draw<'a, I>(&mut self, _content: I) -> io::Result<()>
+        where
+            I: Iterator<Item = (u16, u16, &'a ratatui::buffer::Cell)>,
+        {
+            Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "terminal is gone",
+            ))
+        }
+
+        fn hide_cursor(&mut self) -> io::Result<()> {
+            self.0.hide_cursor()
+        }
+
+        fn show_cursor(&mut self) -> io::Result<()> {
+            self.0.show_cursor()
+        }
+
+        fn get_cursor(&mut self) -> io::Result<(u16, u16)> {
+            self.0.get_cursor()
+        }
+
+        fn set_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
+            self.0.set_cursor(x, y)
+        }
+
+        fn clear(&mut self) -> io::Result<()> {
+            self.0.clear()
+        }
+
+        fn size(&self) -> io::Result<Rect> {
+            self.0.size()
+        }
+
+        fn window_size(&mut self) -> io::Result<ratatui::backend::WindowSize> {
+            self.0.window_size()
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    static RESTORED: AtomicUsize = AtomicUsize::new(0);
+
+    fn count_restore() {
+        RESTORED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[tokio::test]
+    async fn a_draw_error_still_restores_the_terminal() {
+        let restored = RESTORED.load(Ordering::SeqCst);
+        let mut terminal =
+            Terminal::new(GoneBackend(ratatui::backend::TestBackend::new(80, 24))).unwrap();
+        let app = Arc::new(Mutex::new(App::default()));
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let result = {
+            let _guard = TerminalGuard {
+                restore: count_restore,
+            };
+            run_app(&mut terminal, app, event_tx).await
+        };
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        assert!(RESTORED.load(Ordering::SeqCst) > restored);
+
+        // A panic mid-frame unwinds through the guard too
+        let restored = RESTORED.load(Ordering::SeqCst);
+        let panicked = std::panic::catch_unwind(|| {
+            let _guard = TerminalGuard {
+                restore: count_restore,
+            };
+            panic!("drawing went wrong");
+        });
+        assert!(panicked.is_err());
+        assert!(RESTORED.load(Ordering::SeqCst) > restored);
+    }
+
+    #[test]
+    fn rejected_words_flash_with_the_reason() {
+        let mut app = App {
+            state: AppState::Connected,
+            server_protocol: PROTOCOL_VERSION,
+            ..App::default()
+        };
+        app.current_input = "helo".to_string();
+        app.send_word();
+        app.current_input = "world".to_string();
+        app.send_word();
+
+        let nack = ServerMessage::Nack("Not in the dictionary".to_string());
+        app.handle_server_text(&nack.encode());
+        assert_eq!(app.notice.as_deref(), Some("✗ helo: Not in the dictionary"));
+        assert!(app.rejected_at.is_some());
+        assert_eq!(app.unacked, ["world"]);
+
+        app.rejected_at = None;
+        app.handle_server_text(&ServerMessage::Ack.encode());
+        assert!(app.unacked.is_empty());
+        assert!(app.rejected_at.is_none());
+    }
+
+    #[test]
+    fn multibyte_words_count_their_characters() {
+        let mut app = App {
+            state: AppState::Connected,
+            server_protocol: PROTOCOL_VERSION,
+            ..App::default()
+        };
+        app.edit_input(|input| input.push_str("日本語é"));
+        app.edit_input(|input| {
+            input.pop();
+        });
+        assert_eq!(app.current_input, "日本語");
+
+        assert_eq!(app.send_word(), Some("日本語".to_string()));
+        assert_eq!(app.chars_typed, 3 + 1);
+    }
+
+    #[test]
+    fn sentence_size_counts_words_and_single_spaces() {
+        assert_eq!(sentence_size(""), (0, 0));
+        assert_eq!(sentence_size("   \n\t "), (0, 0));
+        assert_eq!(sentence_size("hello"), (1, 5));
+        assert_eq!(sentence_size("  hello   wide\tworld "), (3, 16));
+        assert_eq!(sentence_size("héllo wörld"), (2, 11));
+    }
+
+    #[test]
+    fn sentence_size_follows_the_sentence() {
+        let mut app = App {
+            state: AppState::Connected,
+            server_protocol: PROTOCOL_VERSION,
+            ..App::default()
+        };
+        app.update_sentence("the quick".to_string());
+        assert_eq!(sentence_size(&app.sentence), (2, 9));
+        app.update_sentence("the quick brown fox".to_string());
+        assert_eq!(sentence_size(&app.sentence), (4, 19));
+    }
+
+    #[test]
+    fn blocklisted_words_are_masked_on_screen() {
+        let blocklist: HashSet<String> = ["darn", "heck"].map(String::from).into();
+        assert_eq!(
+            mask_words("Darn it, what the heck!", &blocklist),
+            "**** it, what the ****!"
+        );
+        assert_eq!(mask_words("darning hecks", &blocklist), "darning hecks");
+        assert_eq!(mask_words("all clean here", &blocklist), "all clean here");
+        assert_eq!(mask_words("", &blocklist), "");
     }
 
-    // Check if it's a valid IPv6 address
-    if addr.parse::<std::net::Ipv6Addr>().is_ok() {
-        return true;
+    #[test]
+    fn quiet_reconnects_keep_the_chat_and_flag_unacked_words() {
+        let mut app = App {
+            state: AppState::Connected,
+            server_protocol: PROTOCOL_VERSION,
+            ..App::default()
+        };
+        app.chat_history.push("ada: wait for me".to_string());
+        app.chat_scroll = 1;
+        app.unacked.push_back("unsure".to_string());
+        app.start_quiet_reconnect(1);
+        app.set_connected();
+
+        assert_eq!(app.chat_history, ["ada: wait for me"]);
+        assert_eq!(app.chat_scroll, 1);
+        assert!(app.unacked.is_empty());
+        assert_eq!(
+            app.notice.as_deref(),
+            Some("Reconnected, check these made it: unsure")
+        );
+
+        // A fresh connection starts a fresh chat
+        app.set_connected();
+        assert!(app.chat_history.is_empty());
+        assert_eq!(app.chat_scroll, 0);
     }
 
-    // Basic hostname validation (allows letters, numbers, dots, and hyphens)
-    if addr.is_empty() || addr.len() > 253 {
-        return false;
+    #[test]
+    fn only_untagged_servers_get_the_compat_notice() {
+        let mut app = App::default();
+        app.set_connected();
+        let hello = ServerMessage::Hello {
+            protocol: PROTOCOL_VERSION - 1,
+            session: "s".to_string(),
+        };
+        app.handle_server_text(&hello.encode());
+        assert_eq!(app.server_protocol, PROTOCOL_VERSION - 1);
+        assert_eq!(app.notice, None);
+
+        app.set_connected();
+        app.handle_server_text("the raw sentence");
+        assert_eq!(app.server_protocol, 0);
+        assert_eq!(app.sentence, "the raw sentence");
+        assert_eq!(
+            app.notice.as_deref(),
+            Some("Server is older than this client, running in compatibility mode")
+        );
     }
 
-    addr.chars()
-        .all(|c| c.is_alphanumeric() || c == '.' || c == '-')
-        && !addr.starts_with('-')
-        && !addr.ends_with('-')
-        && !addr.starts_with('.')
-        && !addr.ends_with('.')
+    #[test]
+    fn the_palette_switches_themes() {
+        let mut app = App::default();
+        let palette = Palette {
+            query: "theme".to_string(),
+            selected: 0,
+        };
+        assert_eq!(palette.matches(&app.state), [Action::NextTheme]);
+
+        app.apply_action(Action::NextTheme);
+        assert_eq!(app.settings.theme, Theme::HIGH_CONTRAST);
+        assert_eq!(app.notice.as_deref(), Some("Theme: high-contrast"));
+    }
 }
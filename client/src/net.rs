@@ -0,0 +1,584 @@
+//! The connection to the game server: connecting, the session itself and
+//! reconnecting when it drops.
+
+use crate::{App, AppEvent, AppState};
+use futures::{SinkExt, StreamExt};
+use protocol::{ClientMessage, CloseReason};
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::{lookup_host, TcpStream},
+    sync::mpsc,
+};
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{
+        self,
+        http::Uri,
+        protocol::{CloseFrame, Message},
+    },
+    MaybeTlsStream, WebSocketStream,
+};
+
+/// Attempts made to quietly restore a dropped connection before the
+/// disconnected screen takes over
+pub const MAX_QUIET_RECONNECTS: u32 = 3;
+
+/// Attempts made to restore a dropped connection in all, quiet ones included
+pub const MAX_RECONNECTS: u32 = 10;
+
+/// Wait before the first reconnect attempt, doubled for every one after
+pub const RECONNECT_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest wait between reconnect attempts
+pub const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// How often we ping the server while connected, which also measures the
+/// latency shown in the header
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Silence after which the connection counts as dead, a few missed pongs
+const SERVER_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Shown when the server closes the connection before sending anything
+const CLOSED_ON_ACCEPT: &str = "Server accepted then closed the connection, it may be full";
+
+/// How a connected session ended
+enum SessionEnd {
+    /// The user asked to disconnect
+    Disconnected,
+    /// The connection dropped without a close from the server, worth retrying
+    Dropped(String),
+    /// The server closed the connection or it could not be used
+    Closed(String),
+    /// The server is stopping and said goodbye
+    ShutDown,
+    Quit,
+}
+
+/// Where the connection task is at, it always ends up back at `Idle` after a
+/// failure so the next `AppEvent::Connect` starts over cleanly
+#[derive(Debug, Clone, Copy)]
+enum Phase {
+    /// Not connected, waiting for the player to connect
+    Idle,
+    /// Opening the connection, `attempt` counts reconnects so far
+    Connecting { attempt: u32 },
+    /// Waiting out the delay before reconnect `attempt`
+    Backoff { attempt: u32, delay: Duration },
+}
+
+/// The player stopped what the connection task was waiting on
+enum Cancelled {
+    Disconnect,
+    /// Didn't want to wait for the next reconnect attempt
+    Connect,
+    Quit,
+}
+
+impl Cancelled {
+    /// `None` once the app is quitting
+    fn next_phase(self) -> Option<Phase> {
+        match self {
+            Cancelled::Disconnect => Some(Phase::Idle),
+            Cancelled::Connect => Some(Phase::Connecting { attempt: 0 }),
+            Cancelled::Quit => None,
+        }
+    }
+}
+
+pub async fn run_websocket_client(
+    app: Arc<Mutex<App>>,
+    mut event_rx: mpsc::UnboundedReceiver<AppEvent>,
+) {
+    let mut phase = Phase::Idle;
+    loop {
+        let next = match phase {
+            Phase::Idle => match event_rx.recv().await {
+                Some(AppEvent::Connect) => Some(Phase::Connecting { attempt: 0 }),
+                Some(AppEvent::Quit) | None => None,
+                // Nothing to send while not connected
+                _ => Some(Phase::Idle),
+            },
+            Phase::Connecting { attempt } => connect_and_run(&app, &mut event_rx, attempt).await,
+            Phase::Backoff { attempt, delay } => {
+                match until_cancelled(tokio::time::sleep(delay), &mut event_rx).await {
+                    Ok(()) => Some(Phase::Connecting { attempt }),
+                    Err(cancelled) => cancelled.next_phase(),
+                }
+            }
+        };
+        match next {
+            Some(next) => phase = next,
+            None => return,
+        }
+    }
+}
+
+/// Runs `future` while still listening for the player to disconnect or quit
+async fn until_cancelled<F: Future>(
+    future: F,
+    event_rx: &mut mpsc::UnboundedReceiver<AppEvent>,
+) -> Result<F::Output, Cancelled> {
+    tokio::pin!(future);
+    loop {
+        tokio::select! {
+            output = &mut future => return Ok(output),
+            event = event_rx.recv() => match event {
+                Some(AppEvent::Disconnect) => return Err(Cancelled::Disconnect),
+                Some(AppEvent::Connect) => return Err(Cancelled::Connect),
+                Some(AppEvent::Quit) | None => return Err(Cancelled::Quit),
+                _ => {}
+            },
+        }
+    }
+}
+
+/// Connects and runs the session until it ends, returns the phase to go on
+/// with or `None` once the app is quitting
+async fn connect_and_run(
+    app: &Arc<Mutex<App>>,
+    event_rx: &mut mpsc::UnboundedReceiver<AppEvent>,
+    mut attempt: u32,
+) -> Option<Phase> {
+    let (url, timeout) = {
+        let mut app_lock = app.lock().unwrap();
+        // The retry the disconnected screen counted down to is due
+        if app_lock.state == AppState::Disconnected && app_lock.retry.is_some() {
+            app_lock.connect();
+        }
+        (app_lock.connect_url(), app_lock.settings.connect_timeout)
+    };
+
+    let connecting = tokio::time::timeout(timeout, connect(&url));
+    let result = match until_cancelled(connecting, event_rx).await {
+        Ok(result) => result
+            .unwrap_or_else(|_| Err(format!("Connection timed out after {}s", timeout.as_secs()))),
+        Err(cancelled) => return cancelled.next_phase(),
+    };
+
+    {
+        let mut app_lock = app.lock().unwrap();
+        // Esc got in just before the attempt finished, the player already
+        // left for the menu and its Disconnect is still queued
+        if app_lock.state != AppState::Connecting && app_lock.reconnect_attempt.is_none() {
+            return Some(Phase::Idle);
+        }
+        if result.is_ok() {
+            app_lock.set_connected();
+        }
+    }
+    let end = match result {
+        Ok(ws_stream) => {
+            attempt = 0;
+            run_session(ws_stream, app, event_rx).await
+        }
+        Err(e) if attempt > 0 => SessionEnd::Dropped(e),
+        Err(e) => SessionEnd::Closed(e),
+    };
+
+    let mut app_lock = app.lock().unwrap();
+    let reason = match end {
+        SessionEnd::Disconnected => return Some(Phase::Idle),
+        SessionEnd::Quit => return None,
+        SessionEnd::Closed(reason) => {
+            app_lock.set_disconnected(Some(reason));
+            return Some(Phase::Idle);
+        }
+        SessionEnd::ShutDown => {
+            app_lock.set_server_shut_down();
+            return Some(Phase::Idle);
+        }
+        SessionEnd::Dropped(reason) => reason,
+    };
+    let attempt = attempt + 1;
+    if app_lock.settings.quiet_reconnect && attempt <= MAX_QUIET_RECONNECTS {
+        let delay = app_lock.start_quiet_reconnect(attempt);
+        return Some(Phase::Backoff { attempt, delay });
+    }
+    app_lock.set_disconnected(Some(reason));
+    if attempt > MAX_RECONNECTS {
+        return Some(Phase::Idle);
+    }
+    let delay = app_lock.schedule_reconnect(attempt);
+    Some(Phase::Backoff { attempt, delay })
+}
+
+/// Opens the WebSocket, looking up the host on its own first so that a typo
+/// in it gets a clearer error than whatever the connect would fail with
+async fn connect(url: &str) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>, String> {
+    if let Ok(uri) = url.parse::<Uri>() {
+        if let Some(host) = uri.host() {
+            let port = uri
+                .port_u16()
+                .unwrap_or(if uri.scheme_str() == Some("wss") {
+                    443
+                } else {
+                    80
+                });
+            // IPv6 addresses come in brackets
+            let name = host.trim_start_matches('[').trim_end_matches(']');
+            if lookup_host((name, port)).await.is_err() {
+                return Err(format!("Could not resolve host {}", host));
+            }
+        }
+    }
+    connect_async(url)
+        .await
+        .map(|(ws_stream, _)| ws_stream)
+        .map_err(|err| connect_error(&err))
+}
+
+/// Why connecting failed, in words a player can act on
+fn connect_error(err: &tungstenite::Error) -> String {
+    // rustls reports a bad certificate as an IO error wrapping its own
+    let tls_error = match err {
+        tungstenite::Error::Tls(err) => Some(err.to_string()),
+        tungstenite::Error::Io(io) => io
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+            .map(|err| err.to_string()),
+        _ => None,
+    };
+    match tls_error {
+        Some(reason) => format!("Secure connection failed: {}", reason),
+        None => format!("Connection failed: {}", err),
+    }
+}
+
+async fn run_session(
+    ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    app: &Arc<Mutex<App>>,
+    event_rx: &mut mpsc::UnboundedReceiver<AppEvent>,
+) -> SessionEnd {
+    let (mut write, mut read) = ws_stream.split();
+    // A server that hangs up before saying anything is most likely full
+    let mut heard_from_server = false;
+    // Anything from the server counts, our pings make sure something comes
+    let mut last_seen = Instant::now();
+    // The first ping goes out right away, for a latency to show
+    let mut keepalive = tokio::time::interval(KEEPALIVE_INTERVAL);
+    // Number and send time of our latest ping, until its pong comes back
+    let mut ping_count: u64 = 0;
+    let mut ping_sent: Option<(u64, Instant)> = None;
+
+    // Handle the WebSocket connection
+    loop {
+        tokio::select! {
+            // Handle incoming WebSocket messages
+            msg_result = read.next() => {
+                if matches!(msg_result, Some(Ok(_))) {
+                    last_seen = Instant::now();
+                }
+                match msg_result {
+                    Some(Ok(Message::Text(text))) => {
+                        heard_from_server = true;
+                        let reply = app.lock().unwrap().handle_server_text(&text);
+                        if let Some(reply) = reply {
+                            if write.send(Message::Text(reply)).await.is_err() {
+                                return SessionEnd::Dropped("Failed to send message".to_string());
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Pong(payload))) => {
+                        // Pongs echo the ping, older ones are too late to count
+                        if let Some((count, sent)) = ping_sent {
+                            if payload == count.to_be_bytes() {
+                                let latency = sent.elapsed().as_millis() as u64;
+                                app.lock().unwrap().latency_ms = Some(latency);
+                                ping_sent = None;
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Close(frame))) => {
+                        return closed_by_server(frame, heard_from_server);
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(_)) | None if !heard_from_server => {
+                        return SessionEnd::Closed(CLOSED_ON_ACCEPT.to_string());
+                    }
+                    Some(Err(_)) | None => {
+                        return SessionEnd::Dropped("Connection lost".to_string());
+                    }
+                }
+            }
+
+            // Notice a dead connection even when we have nothing to send
+            _ = keepalive.tick() => {
+                if last_seen.elapsed() > SERVER_TIMEOUT {
+                    return SessionEnd::Dropped("Server stopped responding".to_string());
+                }
+                ping_count += 1;
+                ping_sent = Some((ping_count, Instant::now()));
+                let ping = Message::Ping(ping_count.to_be_bytes().to_vec());
+                if write.send(ping).await.is_err() {
+                    return SessionEnd::Dropped("Failed to send message".to_string());
+                }
+            }
+
+            // Handle outgoing events
+            event = event_rx.recv() => {
+                let msg = match event {
+                    Some(AppEvent::SendWord(word)) => ClientMessage::SubmitWord(word),
+                    Some(AppEvent::Retract) => ClientMessage::Retract,
+                    Some(AppEvent::UndoWord) => ClientMessage::Undo,
+                    Some(AppEvent::Clear) => ClientMessage::Clear,
+                    Some(AppEvent::ReportWpm(wpm)) => ClientMessage::Wpm(wpm),
+                    Some(AppEvent::SendChat(text)) => ClientMessage::Chat(text),
+                    Some(AppEvent::Join) => ClientMessage::Join,
+                    Some(AppEvent::RaceProgress(chars)) => ClientMessage::Race(chars),
+                    Some(AppEvent::Disconnect) => return SessionEnd::Disconnected,
+                    Some(AppEvent::Quit) | None => return SessionEnd::Quit,
+                    // Already connected, ignore
+                    Some(AppEvent::Connect) => continue,
+                };
+                // Servers from before the protocol only ever take plain words
+                let untagged = app.lock().unwrap().server_protocol == 0;
+                let frame = match msg {
+                    ClientMessage::SubmitWord(word) if untagged => word,
+                    _ if untagged => continue,
+                    msg => msg.encode(),
+                };
+                if write.send(Message::Text(frame)).await.is_err() {
+                    return SessionEnd::Dropped("Failed to send message".to_string());
+                }
+            }
+        }
+    }
+}
+
+/// How a session ends on a close frame from the server. A shutdown is waited
+/// out, other reasons go on the disconnected screen.
+fn closed_by_server(frame: Option<CloseFrame>, heard_from_server: bool) -> SessionEnd {
+    let frame = frame.map(|f| (CloseReason::decode(f.code.into(), &f.reason), f.reason));
+    match frame {
+        Some((Some(CloseReason::ShuttingDown), _)) => SessionEnd::ShutDown,
+        Some((_, reason)) if !reason.is_empty() => SessionEnd::Closed(reason.to_string()),
+        _ if !heard_from_server => SessionEnd::Closed(CLOSED_ON_ACCEPT.to_string()),
+        _ => SessionEnd::Closed("Connection closed by server".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::{ServerMessage, PROTOCOL_VERSION};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+    use tungstenite::protocol::frame::coding::CloseCode;
+
+    /// What the test server does with the connection it accepted
+    #[derive(Clone, Copy)]
+    enum Script {
+        /// Greets and sends the sentence, then drops without a close
+        GreetAndDrop,
+        /// Greets, sends the sentence and stays
+        GreetAndStay,
+        /// Closes without a reason before saying anything
+        CloseRightAway,
+        /// Drops before saying anything, not even a close
+        DropRightAway,
+    }
+
+    /// A server on a free port playing one script per connection, in order
+    async fn scripted_server(scripts: Vec<Script>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        serve_scripts(listener, scripts)
+    }
+
+    /// Plays one script per connection to `listener`, in order
+    fn serve_scripts(listener: TcpListener, scripts: Vec<Script>) -> String {
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut kept = Vec::new();
+            for (connection, script) in scripts.into_iter().enumerate() {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws = accept_async(stream).await.unwrap();
+                match script {
+                    Script::CloseRightAway => {
+                        let _ = ws.close(None).await;
+                        continue;
+                    }
+                    Script::DropRightAway => continue,
+                    Script::GreetAndDrop | Script::GreetAndStay => {}
+                }
+                let greeting = [
+                    ServerMessage::Hello {
+                        protocol: PROTOCOL_VERSION,
+                        session: "test".to_string(),
+                    },
+                    ServerMessage::SentenceUpdate {
+                        version: connection as u64 + 1,
+                        sentence: format!("hello from connection {connection}"),
+                        author: None,
+                    },
+                ];
+                for msg in greeting {
+                    ws.send(Message::Text(msg.encode())).await.unwrap();
+                }
+                if let Script::GreetAndStay = script {
+                    kept.push(ws);
+                }
+            }
+            // Keeps the last connections open until the test is over
+            std::future::pending::<()>().await;
+        });
+        format!("ws://{addr}")
+    }
+
+    /// An app connecting to `server_url`, with its connection task and the
+    /// sender that keeps the task running
+    fn connecting(
+        server_url: String,
+        quiet_reconnect: bool,
+    ) -> (Arc<Mutex<App>>, mpsc::UnboundedSender<AppEvent>) {
+        let mut app = App {
+            server_url,
+            ..App::default()
+        };
+        app.settings.quiet_reconnect = quiet_reconnect;
+        let app = Arc::new(Mutex::new(app));
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        tokio::spawn(run_websocket_client(Arc::clone(&app), event_rx));
+        if let Some(event) = app.lock().unwrap().apply_action(crate::Action::Connect) {
+            event_tx.send(event).unwrap();
+        }
+        (app, event_tx)
+    }
+
+    /// Polls the app until `check` holds, failing after a few seconds
+    async fn wait_for(app: &Arc<Mutex<App>>, mut check: impl FnMut(&App) -> bool) {
+        let waiting = async {
+            while !check(&app.lock().unwrap()) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), waiting)
+            .await
+            .expect("the app never got there");
+    }
+
+    #[tokio::test]
+    async fn quick_drops_recover_without_leaving_the_game() {
+        let url = scripted_server(vec![Script::GreetAndDrop, Script::GreetAndStay]).await;
+        let (app, _events) = connecting(url, true);
+        wait_for(&app, |app| app.sentence == "hello from connection 0").await;
+        wait_for(&app, |app| app.reconnect_attempt == Some(1)).await;
+        {
+            let app = app.lock().unwrap();
+            // The game stays up while it reconnects
+            assert_eq!(app.state, AppState::Connected);
+            assert_eq!(app.sentence, "hello from connection 0");
+            assert_eq!(app.error_message, None);
+        }
+        wait_for(&app, |app| app.sentence == "hello from connection 1").await;
+        let app = app.lock().unwrap();
+        assert_eq!(app.state, AppState::Connected);
+        assert_eq!(app.reconnect_attempt, None);
+        assert_eq!(app.retry, None);
+    }
+
+    #[tokio::test]
+    async fn drops_show_the_disconnected_screen_without_quiet_reconnects() {
+        let url = scripted_server(vec![Script::GreetAndDrop, Script::GreetAndStay]).await;
+        let (app, _events) = connecting(url, false);
+        wait_for(&app, |app| app.state == AppState::Disconnected).await;
+        assert_eq!(
+            app.lock().unwrap().retry.map(|(attempt, _)| attempt),
+            Some(1)
+        );
+        wait_for(&app, |app| app.sentence == "hello from connection 1").await;
+        assert_eq!(app.lock().unwrap().state, AppState::Connected);
+    }
+
+    fn frame(reason: &CloseReason) -> Option<CloseFrame<'static>> {
+        Some(CloseFrame {
+            code: CloseCode::from(reason.code()),
+            reason: reason.reason().into(),
+        })
+    }
+
+    #[test]
+    fn close_reasons_reach_the_disconnected_screen() {
+        for reason in [
+            CloseReason::TooManyConnections,
+            CloseReason::TooManyRooms,
+            CloseReason::BadRoomName,
+            CloseReason::Idle(60),
+        ] {
+            match closed_by_server(frame(&reason), true) {
+                SessionEnd::Closed(shown) => assert_eq!(shown, reason.reason()),
+                _ => panic!("{reason:?} didn't close the session"),
+            }
+        }
+    }
+
+    #[test]
+    fn shutdowns_are_waited_out() {
+        assert!(matches!(
+            closed_by_server(frame(&CloseReason::ShuttingDown), true),
+            SessionEnd::ShutDown
+        ));
+    }
+
+    #[test]
+    fn closes_without_a_reason_say_when_they_came() {
+        match closed_by_server(None, false) {
+            SessionEnd::Closed(shown) => assert_eq!(shown, CLOSED_ON_ACCEPT),
+            _ => panic!("closed on accept didn't close the session"),
+        }
+        let empty = Some(CloseFrame {
+            code: CloseCode::Normal,
+            reason: "".into(),
+        });
+        match closed_by_server(empty, true) {
+            SessionEnd::Closed(shown) => assert_eq!(shown, "Connection closed by server"),
+            _ => panic!("a plain close didn't close the session"),
+        }
+    }
+
+    #[tokio::test]
+    async fn servers_closing_right_after_accept_look_full() {
+        for script in [Script::CloseRightAway, Script::DropRightAway] {
+            let url = scripted_server(vec![script]).await;
+            let (app, _events) = connecting(url, true);
+            wait_for(&app, |app| app.state == AppState::Disconnected).await;
+            let app = app.lock().unwrap();
+            assert_eq!(app.error_message.as_deref(), Some(CLOSED_ON_ACCEPT));
+            // Not a dropped connection, so not retried
+            assert_eq!(app.retry, None);
+            assert_eq!(app.reconnect_attempt, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn connecting_again_after_a_failed_connect_starts_over() {
+        // A free port with nothing listening on it yet
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let (app, events) = connecting(format!("ws://{addr}"), true);
+        wait_for(&app, |app| app.state == AppState::Disconnected).await;
+        {
+            let app = app.lock().unwrap();
+            assert!(app.error_message.is_some());
+            // Never connected, so nothing to reconnect to
+            assert_eq!(app.retry, None);
+            assert_eq!(app.reconnect_attempt, None);
+        }
+
+        let listener = TcpListener::bind(addr).await.unwrap();
+        serve_scripts(listener, vec![Script::GreetAndStay]);
+        let event = app.lock().unwrap().apply_action(crate::Action::Connect);
+        events.send(event.expect("Enter didn't connect")).unwrap();
+        wait_for(&app, |app| app.sentence == "hello from connection 0").await;
+        let app = app.lock().unwrap();
+        assert_eq!(app.state, AppState::Connected);
+        assert_eq!(app.error_message, None);
+    }
+}
@@ -0,0 +1,146 @@
+//! Offline practice: a timed typing test against random words, no server
+//! needed.
+//!
+//! The clock starts with the first key. Once it runs out, or every word is
+//! typed, the test is over and the results screen takes its place.
+
+use std::time::{Duration, Instant};
+
+/// How long a test runs
+pub const TEST_LENGTH: Duration = Duration::from_secs(30);
+
+/// Words handed out per test unless `--practice-count` says otherwise, more
+/// than anyone types in `TEST_LENGTH`
+pub const TEST_WORDS: usize = 120;
+
+#[derive(Debug)]
+pub struct Practice {
+    pub text: String,
+    typed: String,
+    started_at: Option<Instant>,
+    /// Set when the last word was typed before the time was up
+    done_at: Option<Instant>,
+    /// Characters typed, backspaces left out
+    keystrokes: usize,
+    /// Keystrokes that didn't match the text
+    errors: usize,
+    /// Wrong characters taken back with backspace
+    corrected: usize,
+}
+
+/// How a finished test went
+#[derive(Debug, Clone, Copy)]
+pub struct Results {
+    /// Speed counting only the correctly typed characters
+    pub wpm: f64,
+    /// Speed counting every typed character
+    pub raw_wpm: f64,
+    pub accuracy: f64,
+    pub errors: usize,
+    pub corrected: usize,
+    pub time: Duration,
+}
+
+impl Practice {
+    pub fn new(words: &[String]) -> Practice {
+        Practice {
+            text: words.join(" "),
+            typed: String::new(),
+            started_at: None,
+            done_at: None,
+            keystrokes: 0,
+            errors: 0,
+            corrected: 0,
+        }
+    }
+
+    pub fn typed(&self) -> &str {
+        &self.typed
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.done_at.is_some() || self.elapsed() >= TEST_LENGTH
+    }
+
+    /// Time typed so far, never past the end of the test
+    pub fn elapsed(&self) -> Duration {
+        let Some(started_at) = self.started_at else {
+            return Duration::ZERO;
+        };
+        let end = self.done_at.unwrap_or_else(Instant::now);
+        end.duration_since(started_at).min(TEST_LENGTH)
+    }
+
+    pub fn time_left(&self) -> Duration {
+        TEST_LENGTH.saturating_sub(self.elapsed())
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        if self.is_finished() {
+            return;
+        }
+        let now = Instant::now();
+        self.started_at.get_or_insert(now);
+        let at = self.typed.chars().count();
+        self.keystrokes += 1;
+        if self.text.chars().nth(at) != Some(c) {
+            self.errors += 1;
+        }
+        self.typed.push(c);
+        if at + 1 == self.text.chars().count() {
+            self.done_at = Some(now);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if self.is_finished() {
+            return;
+        }
+        let at = self.typed.chars().count().saturating_sub(1);
+        if let Some(c) = self.typed.pop() {
+            if self.text.chars().nth(at) != Some(c) {
+                self.corrected += 1;
+            }
+        }
+    }
+
+    /// What we typed of the word we're on, for the input box
+    pub fn current_word(&self) -> &str {
+        self.typed.rsplit(' ').next().unwrap_or_default()
+    }
+
+    /// Also the live numbers while the test runs
+    pub fn results(&self) -> Results {
+        let correct = self
+            .typed
+            .chars()
+            .zip(self.text.chars())
+            .filter(|(typed, wanted)| typed == wanted)
+            .count();
+        let time = self.elapsed();
+        let minutes = time.as_secs_f64() / 60.0;
+        let per_minute = |chars: usize| {
+            if minutes > 0.0 {
+                chars as f64 / 5.0 / minutes
+            } else {
+                0.0
+            }
+        };
+        Results {
+            wpm: per_minute(correct),
+            raw_wpm: per_minute(self.typed.chars().count()),
+            accuracy: if self.keystrokes > 0 {
+                (self.keystrokes - self.errors) as f64 / self.keystrokes as f64 * 100.0
+            } else {
+                0.0
+            },
+            errors: self.errors,
+            corrected: self.corrected,
+            time,
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Small UI preferences remembered between runs.
+//!
+//! Stored as `key=value` lines in `~/.config/chaostype/prefs`. Any problem
+//! reading or writing the file just falls back to the defaults.
+
+use std::{fs, path::PathBuf};
+
+/// Most servers remembered for quick switching on the welcome screen
+const MAX_RECENT_SERVERS: usize = 5;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Prefs {
+    pub hide_stats: bool,
+    /// Servers we connected to, most recent first. The first one is the
+    /// default when no server is given on the command line.
+    pub recent_servers: Vec<String>,
+    /// Name picked on the welcome screen, used unless `--name` or the config
+    /// file gives one
+    pub name: Option<String>,
+}
+
+impl Prefs {
+    pub fn load() -> Prefs {
+        let mut prefs = Prefs::default();
+        let Some(contents) = prefs_path().and_then(|path| fs::read_to_string(path).ok()) else {
+            return prefs;
+        };
+
+        for line in contents.lines() {
+            match line.split_once('=') {
+                Some((key, value)) if key.trim() == "hide_stats" => {
+                    prefs.hide_stats = value.trim() == "true";
+                }
+                Some((key, value)) if key.trim() == "recent_servers" => {
+                    prefs.recent_servers = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|server| !server.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                }
+                Some((key, value)) if key.trim() == "name" && !value.trim().is_empty() => {
+                    prefs.name = Some(value.trim().to_string());
+                }
+                _ => {}
+            }
+        }
+        prefs
+    }
+
+    pub fn save(&self) {
+        let Some(path) = prefs_path() else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let mut contents = format!(
+            "hide_stats={}\nrecent_servers={}\n",
+            self.hide_stats,
+            self.recent_servers.join(",")
+        );
+        if let Some(name) = &self.name {
+            contents.push_str(&format!("name={}\n", name));
+        }
+        let _ = fs::write(path, contents);
+    }
+
+    /// Moves `server` to the front of the recent servers
+    pub fn remember_server(&mut self, server: &str) {
+        self.recent_servers.retain(|recent| recent != server);
+        self.recent_servers.insert(0, server.to_string());
+        self.recent_servers.truncate(MAX_RECENT_SERVERS);
+    }
+}
+
+fn prefs_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("prefs"))
+}
+
+/// Where the client keeps its files, `None` without a home directory
+pub fn config_dir() -> Option<PathBuf> {
+    // Tests connect and finish sessions too, they keep out of the player's files
+    if cfg!(test) {
+        return None;
+    }
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("chaostype"))
+}
@@ -0,0 +1,143 @@
+//! Race mode on servers started with `--race`.
+//!
+//! Everyone types the passage the server hands out, key by key instead of
+//! word by word. We keep what was typed here and only tell the server how far
+//! we got without a mistake, it does the ranking.
+
+use protocol::{RaceState, Racer};
+use std::time::Instant;
+
+/// Wrong characters we take past the last correct one, typing on is pointless
+/// until they're fixed
+const MAX_WRONG_CHARS: usize = 8;
+
+#[derive(Debug)]
+pub struct RaceView {
+    pub state: RaceState,
+    /// When `state` arrived, its seconds count down from there
+    since: Instant,
+    /// The current or, once it's over, the last passage
+    pub passage: String,
+    pub racers: Vec<Racer>,
+    typed: String,
+    /// Progress last sent to the server
+    reported: usize,
+    /// Characters typed this race, backspaces left out
+    keystrokes: usize,
+    /// Keystrokes that didn't match the passage
+    pub errors: usize,
+    /// Wrong characters taken back with backspace
+    pub corrected: usize,
+}
+
+impl RaceView {
+    pub fn new(state: RaceState) -> RaceView {
+        let mut view = RaceView {
+            state: RaceState::Waiting,
+            since: Instant::now(),
+            passage: String::new(),
+            racers: Vec::new(),
+            typed: String::new(),
+            reported: 0,
+            keystrokes: 0,
+            errors: 0,
+            corrected: 0,
+        };
+        view.update(state);
+        view
+    }
+
+    /// Takes the server's new state, a new passage starts typing over
+    pub fn update(&mut self, state: RaceState) {
+        if let RaceState::Countdown { passage, .. } | RaceState::Running { passage, .. } = &state {
+            let fresh = matches!(state, RaceState::Countdown { .. }) || self.passage != *passage;
+            if fresh {
+                self.passage = passage.clone();
+                self.typed.clear();
+                self.reported = 0;
+                self.keystrokes = 0;
+                self.errors = 0;
+                self.corrected = 0;
+            }
+        }
+        self.state = state;
+        self.since = Instant::now();
+    }
+
+    pub fn is_running(&self) -> bool {
+        matches!(self.state, RaceState::Running { .. })
+    }
+
+    /// Seconds left in the current phase, counted down locally between updates
+    pub fn secs_left(&self) -> u64 {
+        let secs = match self.state {
+            RaceState::Waiting => 0,
+            RaceState::Countdown { starts_in_secs, .. } => starts_in_secs,
+            RaceState::Running { remaining_secs, .. } => remaining_secs,
+            RaceState::Over { next_in_secs } => next_in_secs,
+        };
+        secs.saturating_sub(self.since.elapsed().as_secs())
+    }
+
+    pub fn typed(&self) -> &str {
+        &self.typed
+    }
+
+    /// Characters typed before the first mistake
+    pub fn correct(&self) -> usize {
+        self.typed
+            .chars()
+            .zip(self.passage.chars())
+            .take_while(|(typed, wanted)| typed == wanted)
+            .count()
+    }
+
+    /// Returns the progress to report, if it went up
+    pub fn type_char(&mut self, c: char) -> Option<usize> {
+        let typed = self.typed.chars().count();
+        if !self.is_running()
+            || typed >= self.passage.chars().count()
+            || typed - self.correct() >= MAX_WRONG_CHARS
+        {
+            return None;
+        }
+        self.keystrokes += 1;
+        if self.passage.chars().nth(typed) != Some(c) {
+            self.errors += 1;
+        }
+        self.typed.push(c);
+        let correct = self.correct();
+        (correct > self.reported).then(|| {
+            self.reported = correct;
+            correct
+        })
+    }
+
+    pub fn backspace(&mut self) {
+        if !self.is_running() {
+            return;
+        }
+        let at = self.typed.chars().count().saturating_sub(1);
+        if let Some(c) = self.typed.pop() {
+            if self.passage.chars().nth(at) != Some(c) {
+                self.corrected += 1;
+            }
+        }
+    }
+
+    /// Percentage of keystrokes that matched the passage, once there are any
+    pub fn accuracy(&self) -> Option<f64> {
+        (self.keystrokes > 0)
+            .then(|| (self.keystrokes - self.errors) as f64 / self.keystrokes as f64 * 100.0)
+    }
+
+    /// What we typed of the word we're on, for the input box
+    pub fn current_word(&self) -> &str {
+        self.typed.rsplit(' ').next().unwrap_or_default()
+    }
+
+    /// Our own line in the standings
+    pub fn racer(&self, name: &str) -> Option<&Racer> {
+        self.racers.iter().find(|racer| racer.name == name)
+    }
+}
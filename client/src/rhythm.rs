@@ -0,0 +1,96 @@
+//! Keystroke timing for the `--rhythm-report` practice summary.
+//!
+//! Only the gaps between keys are kept, never what was typed, and both sample
+//! lists are capped so a long session can't grow them without bound.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Samples kept per list, the oldest ones are dropped first
+const MAX_SAMPLES: usize = 2000;
+
+/// Gaps longer than this are the player looking away, not typing rhythm
+const IDLE_CUTOFF: Duration = Duration::from_secs(2);
+
+/// Upper bounds of the histogram buckets in ms, the last bucket is open ended
+pub const BUCKET_BOUNDS_MS: [u64; 6] = [50, 100, 150, 200, 300, 500];
+
+#[derive(Debug, Default)]
+pub struct Rhythm {
+    last_key: Option<Instant>,
+    /// Time between consecutive keystrokes while typing a word
+    intervals: VecDeque<Duration>,
+    /// Time between the last keystroke of a word and submitting it
+    pauses: VecDeque<Duration>,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub mean: Duration,
+    pub median: Duration,
+    pub mean_pause: Option<Duration>,
+    /// Interval counts per bucket of `BUCKET_BOUNDS_MS`, plus one for the rest
+    pub histogram: [usize; BUCKET_BOUNDS_MS.len() + 1],
+}
+
+impl Rhythm {
+    pub fn key(&mut self, now: Instant) {
+        if let Some(gap) = self.gap_since_last(now) {
+            push_bounded(&mut self.intervals, gap);
+        }
+        self.last_key = Some(now);
+    }
+
+    pub fn submit(&mut self, now: Instant) {
+        if let Some(gap) = self.gap_since_last(now) {
+            push_bounded(&mut self.pauses, gap);
+        }
+        // The next word's first key starts its own rhythm
+        self.last_key = None;
+    }
+
+    fn gap_since_last(&self, now: Instant) -> Option<Duration> {
+        let gap = now.duration_since(self.last_key?);
+        (gap <= IDLE_CUTOFF).then_some(gap)
+    }
+
+    /// `None` until at least one interval was recorded
+    pub fn report(&self) -> Option<Report> {
+        if self.intervals.is_empty() {
+            return None;
+        }
+
+        let mut sorted: Vec<Duration> = self.intervals.iter().copied().collect();
+        sorted.sort();
+        let mean = sorted.iter().sum::<Duration>() / sorted.len() as u32;
+        let median = sorted[sorted.len() / 2];
+        let mean_pause = (!self.pauses.is_empty())
+            .then(|| self.pauses.iter().sum::<Duration>() / self.pauses.len() as u32);
+
+        let mut histogram = [0; BUCKET_BOUNDS_MS.len() + 1];
+        for interval in &sorted {
+            let ms = interval.as_millis() as u64;
+            let bucket = BUCKET_BOUNDS_MS
+                .iter()
+                .position(|bound| ms < *bound)
+                .unwrap_or(BUCKET_BOUNDS_MS.len());
+            histogram[bucket] += 1;
+        }
+
+        Some(Report {
+            mean,
+            median,
+            mean_pause,
+            histogram,
+        })
+    }
+}
+
+fn push_bounded(samples: &mut VecDeque<Duration>, sample: Duration) {
+    if samples.len() == MAX_SAMPLES {
+        samples.pop_front();
+    }
+    samples.push_back(sample);
+}
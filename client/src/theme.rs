@@ -0,0 +1,190 @@
+//! `--theme`: the styles every screen is drawn with.
+//!
+//! Themes hold whole styles rather than bare colors so the monochrome one can
+//! get by on bold, dim and reversed text, which any terminal can show.
+
+use ratatui::style::{Color, Modifier, Style};
+use std::env;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    /// Regular text
+    pub text: Style,
+    /// Hints and secondary text
+    pub muted: Style,
+    /// Placeholders and whatever can't be used right now
+    pub dim: Style,
+    /// Titles and the stats
+    pub title: Style,
+    /// Our name, popups and other things worth a look
+    pub accent: Style,
+    /// The word being typed
+    pub input: Style,
+    /// Frames of the main panes, and keys named in the text
+    pub border: Style,
+    pub chat_border: Style,
+    /// Connected, sent, copied
+    pub success: Style,
+    /// Rejections, lost connections, time running out
+    pub error: Style,
+    /// Selected entry of the command palette
+    pub selected: Style,
+    /// Words of `--display-style boxed` with no author color
+    pub boxed: Style,
+    /// Colors words get from their author, none means no author colors
+    pub authors: &'static [Color],
+}
+
+impl Theme {
+    pub const DEFAULT: Theme = Theme {
+        text: Style::new().fg(Color::White),
+        muted: Style::new().fg(Color::Gray),
+        dim: Style::new().fg(Color::DarkGray),
+        title: Style::new().fg(Color::Cyan),
+        accent: Style::new().fg(Color::Yellow),
+        input: Style::new().fg(Color::Yellow),
+        border: Style::new().fg(Color::Blue),
+        chat_border: Style::new().fg(Color::Magenta),
+        success: Style::new().fg(Color::Green),
+        error: Style::new().fg(Color::Red),
+        selected: Style::new()
+            .fg(Color::Black)
+            .bg(Color::Yellow)
+            .add_modifier(Modifier::BOLD),
+        boxed: Style::new().fg(Color::Black).bg(Color::Gray),
+        // None of them used for the UI chrome
+        authors: &[
+            Color::Cyan,
+            Color::Green,
+            Color::Yellow,
+            Color::Magenta,
+            Color::LightRed,
+            Color::LightGreen,
+            Color::LightMagenta,
+            Color::LightCyan,
+        ],
+    };
+
+    /// Bright colors only, nothing dark on dark
+    pub const HIGH_CONTRAST: Theme = Theme {
+        text: Style::new().fg(Color::White),
+        muted: Style::new().fg(Color::White),
+        dim: Style::new().fg(Color::Gray),
+        title: Style::new()
+            .fg(Color::LightCyan)
+            .add_modifier(Modifier::BOLD),
+        accent: Style::new()
+            .fg(Color::LightYellow)
+            .add_modifier(Modifier::BOLD),
+        input: Style::new()
+            .fg(Color::LightYellow)
+            .add_modifier(Modifier::BOLD),
+        border: Style::new().fg(Color::White),
+        chat_border: Style::new().fg(Color::LightMagenta),
+        success: Style::new()
+            .fg(Color::LightGreen)
+            .add_modifier(Modifier::BOLD),
+        error: Style::new()
+            .fg(Color::LightRed)
+            .add_modifier(Modifier::BOLD),
+        selected: Style::new()
+            .fg(Color::Black)
+            .bg(Color::White)
+            .add_modifier(Modifier::BOLD),
+        boxed: Style::new().fg(Color::Black).bg(Color::White),
+        authors: &[
+            Color::LightCyan,
+            Color::LightGreen,
+            Color::LightYellow,
+            Color::LightMagenta,
+            Color::LightRed,
+            Color::LightBlue,
+        ],
+    };
+
+    /// The terminal's own colors, emphasis only
+    pub const MONOCHROME: Theme = Theme {
+        text: Style::new(),
+        muted: Style::new(),
+        dim: Style::new().add_modifier(Modifier::DIM),
+        title: Style::new().add_modifier(Modifier::BOLD),
+        accent: Style::new().add_modifier(Modifier::BOLD),
+        input: Style::new().add_modifier(Modifier::BOLD),
+        border: Style::new(),
+        chat_border: Style::new(),
+        success: Style::new().add_modifier(Modifier::BOLD),
+        error: Style::new().add_modifier(Modifier::BOLD),
+        selected: Style::new().add_modifier(Modifier::REVERSED),
+        boxed: Style::new().add_modifier(Modifier::REVERSED),
+        authors: &[],
+    };
+
+    /// Every theme under its `--theme` name, in the order the palette cycles
+    const ALL: [(&'static str, Theme); 3] = [
+        ("default", Theme::DEFAULT),
+        ("high-contrast", Theme::HIGH_CONTRAST),
+        ("monochrome", Theme::MONOCHROME),
+    ];
+
+    pub fn parse(value: &str) -> Option<Theme> {
+        Theme::ALL
+            .into_iter()
+            .find(|(name, _)| *name == value)
+            .map(|(_, theme)| theme)
+    }
+
+    /// The theme after this one and its name, wrapping around
+    pub fn next(&self) -> (&'static str, Theme) {
+        let at = Theme::ALL
+            .iter()
+            .position(|(_, theme)| theme == self)
+            .map_or(0, |at| at + 1);
+        Theme::ALL[at % Theme::ALL.len()]
+    }
+
+    /// Monochrome when `NO_COLOR` is set (see no-color.org), the default otherwise
+    pub fn from_env() -> Theme {
+        match env::var_os("NO_COLOR") {
+            Some(value) if !value.is_empty() => Theme::MONOCHROME,
+            _ => Theme::DEFAULT,
+        }
+    }
+
+    /// Same name, same color, on every client and every run (FNV-1a)
+    pub fn author_color(&self, name: &str) -> Option<Color> {
+        if self.authors.is_empty() {
+            return None;
+        }
+        let hash = name.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        Some(self.authors[(hash % self.authors.len() as u64) as usize])
+    }
+
+    /// How a name is shown in the legend and the leaderboard
+    pub fn author(&self, name: &str) -> Style {
+        self.author_color(name)
+            .map_or(self.text, |color| Style::new().fg(color))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_cycles_through_every_theme() {
+        assert_eq!(
+            Theme::DEFAULT.next(),
+            ("high-contrast", Theme::HIGH_CONTRAST)
+        );
+        assert_eq!(
+            Theme::HIGH_CONTRAST.next(),
+            ("monochrome", Theme::MONOCHROME)
+        );
+        assert_eq!(Theme::MONOCHROME.next(), ("default", Theme::DEFAULT));
+        for (name, theme) in Theme::ALL {
+            assert_eq!(Theme::parse(name), Some(theme));
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! What clients send: JSON messages and the query on the WebSocket URL.
+
+use serde::{Deserialize, Serialize};
+
+/// Something a client sent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// A word, or a few, for the sentence
+    SubmitWord(String),
+    /// Take back every word this connection added
+    Retract,
+    /// Take back the newest word this connection added
+    Undo,
+    /// Stop watching and start typing, see `ServerMessage::JoinRequired`
+    Join,
+    /// The name this player goes by, sent right after the hello
+    Name(String),
+    /// Empty the sentence for everyone, only honoured with `--allow-clear`
+    Clear,
+    /// The player's current typing speed
+    Wpm(u32),
+    /// A message for the other players, not for the sentence
+    Chat(String),
+    /// Characters of the race passage typed correctly so far
+    Race(usize),
+}
+
+impl ClientMessage {
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("client messages always serialize")
+    }
+
+    /// Returns `None` for anything that isn't a known message
+    pub fn decode(text: &str) -> Option<ClientMessage> {
+        serde_json::from_str(text).ok()
+    }
+}
+
+/// Where a reconnecting client left off, sent as `?session=<id>&version=<n>`
+/// on the WebSocket URL so servers that don't know about it just ignore it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resume {
+    pub session: String,
+    pub version: u64,
+}
+
+impl Resume {
+    pub fn from_query(query: &str) -> Option<Resume> {
+        let mut session = None;
+        let mut version = None;
+        for pair in query.split('&') {
+            match pair.split_once('=') {
+                Some(("session", value)) => session = Some(value.to_string()),
+                Some(("version", value)) => version = value.parse().ok(),
+                _ => {}
+            }
+        }
+        Some(Resume {
+            session: session?,
+            version: version?,
+        })
+    }
+}
+
+/// The room asked for with `?room=<name>` on the WebSocket URL, from
+/// protocol 13 on. Rooms default to the lobby.
+pub fn room_from_query(query: &str) -> Option<&str> {
+    query.split('&').find_map(|pair| pair.strip_prefix("room="))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_message_survives_the_trip() {
+        for msg in [
+            ClientMessage::SubmitWord("hello".to_string()),
+            ClientMessage::SubmitWord("two \"quoted\" words".to_string()),
+            ClientMessage::Retract,
+            ClientMessage::Undo,
+            ClientMessage::Join,
+            ClientMessage::Name("ada".to_string()),
+            ClientMessage::Clear,
+            ClientMessage::Wpm(92),
+            ClientMessage::Chat("hi all, ready?".to_string()),
+            ClientMessage::Race(20),
+        ] {
+            let frame = msg.encode();
+            assert_eq!(ClientMessage::decode(&frame), Some(msg), "{frame}");
+        }
+    }
+
+    #[test]
+    fn messages_are_tagged_json() {
+        assert_eq!(
+            ClientMessage::SubmitWord("hi".to_string()).encode(),
+            r#"{"type":"submit_word","data":"hi"}"#
+        );
+        assert_eq!(ClientMessage::Join.encode(), r#"{"type":"join"}"#);
+    }
+
+    #[test]
+    fn plain_text_and_broken_messages_are_refused() {
+        for frame in [
+            "",
+            "hello",
+            "/retract",
+            r#"{"type":"wpm","data":"fast"}"#,
+            r#"{"type":"teleport"}"#,
+            r#"{"data":"hello"}"#,
+        ] {
+            assert_eq!(ClientMessage::decode(frame), None, "{frame}");
+        }
+    }
+
+    #[test]
+    fn queries_carry_the_resume_and_room() {
+        let query = "room=poets&session=1a2b&version=12";
+        assert_eq!(
+            Resume::from_query(query),
+            Some(Resume {
+                session: "1a2b".to_string(),
+                version: 12,
+            })
+        );
+        assert_eq!(Resume::from_query("session=1a2b"), None);
+        assert_eq!(room_from_query(query), Some("poets"));
+        assert_eq!(room_from_query("session=1a2b"), None);
+    }
+}
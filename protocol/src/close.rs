@@ -0,0 +1,109 @@
+//! Why the server closed a connection, sent as a close frame's code and
+//! reason. Clients show the reason on the disconnected screen, and only a
+//! shutdown is worth reconnecting after.
+
+/// Close code for a server going away, RFC 6455
+pub const GOING_AWAY: u16 = 1001;
+
+/// Close code for a connection breaking the server's rules, RFC 6455
+pub const POLICY: u16 = 1008;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CloseReason {
+    /// The server is stopping
+    ShuttingDown,
+    /// Too many connections from one address in a short time
+    TooManyConnections,
+    /// Every room slot is taken
+    TooManyRooms,
+    /// The room asked for on the URL can't be a room name
+    BadRoomName,
+    /// Nothing was typed for this many seconds
+    Idle(u64),
+}
+
+impl CloseReason {
+    pub fn code(&self) -> u16 {
+        match self {
+            CloseReason::ShuttingDown => GOING_AWAY,
+            _ => POLICY,
+        }
+    }
+
+    /// The reason as sent, and shown to the player
+    pub fn reason(&self) -> String {
+        match self {
+            CloseReason::ShuttingDown => "Server shutting down".to_string(),
+            CloseReason::TooManyConnections => "Too many connections, try again later".to_string(),
+            CloseReason::TooManyRooms => "Too many rooms, try again later".to_string(),
+            CloseReason::BadRoomName => "Room names are letters, digits, - and _".to_string(),
+            CloseReason::Idle(secs) => format!("Disconnected after {secs}s without typing"),
+        }
+    }
+
+    /// The reason a close frame stands for, `None` for ones this protocol
+    /// doesn't send, like a proxy's
+    pub fn decode(code: u16, reason: &str) -> Option<CloseReason> {
+        let close = match (code, reason) {
+            (GOING_AWAY, _) => CloseReason::ShuttingDown,
+            (POLICY, _) => {
+                let idle = reason
+                    .strip_prefix("Disconnected after ")
+                    .and_then(|rest| rest.strip_suffix("s without typing"))
+                    .and_then(|secs| secs.parse().ok());
+                match idle {
+                    Some(secs) => CloseReason::Idle(secs),
+                    None => [
+                        CloseReason::TooManyConnections,
+                        CloseReason::TooManyRooms,
+                        CloseReason::BadRoomName,
+                    ]
+                    .into_iter()
+                    .find(|close| close.reason() == reason)?,
+                }
+            }
+            _ => return None,
+        };
+        Some(close)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reasons_survive_the_trip() {
+        for close in [
+            CloseReason::ShuttingDown,
+            CloseReason::TooManyConnections,
+            CloseReason::TooManyRooms,
+            CloseReason::BadRoomName,
+            CloseReason::Idle(300),
+        ] {
+            let decoded = CloseReason::decode(close.code(), &close.reason());
+            assert_eq!(decoded, Some(close));
+        }
+    }
+
+    #[test]
+    fn only_a_shutdown_goes_away() {
+        assert_eq!(CloseReason::ShuttingDown.code(), GOING_AWAY);
+        assert_eq!(CloseReason::Idle(5).code(), POLICY);
+        assert_eq!(CloseReason::TooManyRooms.code(), POLICY);
+    }
+
+    #[test]
+    fn unknown_frames_are_left_alone() {
+        assert_eq!(CloseReason::decode(1000, ""), None);
+        assert_eq!(CloseReason::decode(POLICY, "Banned by the proxy"), None);
+        assert_eq!(
+            CloseReason::decode(POLICY, "Disconnected after soons without typing"),
+            None
+        );
+        assert_eq!(
+            CloseReason::decode(GOING_AWAY, ""),
+            Some(CloseReason::ShuttingDown)
+        );
+    }
+}
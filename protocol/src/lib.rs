@@ -0,0 +1,58 @@
+//! Wire format spoken between the client and the server.
+//!
+//! Both ways, every message is one JSON text frame tagged with its `type`,
+//! with the rest under `data`. Both sides build on the same types here, so
+//! they can't drift apart. The word lists both sides make typing text from
+//! live here too, and so do the seeded random generator and the reasons the
+//! server closes connections with, along with the default port and what
+//! makes a valid player name.
+
+mod client;
+mod close;
+pub mod rng;
+mod server;
+pub mod wordlist;
+
+pub use client::{room_from_query, ClientMessage, Resume};
+pub use close::CloseReason;
+pub use server::{Player, RaceState, Racer, RoundState, Score, ServerMessage, TurnState};
+
+/// Bumped whenever a message is added or changes shape
+pub const PROTOCOL_VERSION: u32 = 21;
+
+/// Room everyone without a room of their own shares. Others are asked for
+/// with `?room=<name>` on the URL, understood from protocol 13 on.
+pub const LOBBY: &str = "lobby";
+
+/// Longest room name a client may ask for
+pub const MAX_ROOM_CHARS: usize = 24;
+
+/// Port the server listens on unless told otherwise
+pub const DEFAULT_PORT: u16 = 9001;
+
+/// Longest name a player may pick
+pub const MAX_NAME_CHARS: usize = 24;
+
+/// Names are shown next to words, keep them short and on one line
+pub fn valid_name(name: &str) -> bool {
+    let chars = name.chars().count();
+    (1..=MAX_NAME_CHARS).contains(&chars)
+        && !name.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_need_characters_and_no_spaces() {
+        assert!(valid_name("ada"));
+        assert!(valid_name("Zoë_42"));
+        assert!(valid_name(&"é".repeat(MAX_NAME_CHARS)));
+        assert!(!valid_name(""));
+        assert!(!valid_name("ada lovelace"));
+        assert!(!valid_name("tab\there"));
+        assert!(!valid_name("bell\u{7}"));
+        assert!(!valid_name(&"a".repeat(MAX_NAME_CHARS + 1)));
+    }
+}
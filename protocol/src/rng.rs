@@ -0,0 +1,43 @@
+//! The one source of randomness on either side.
+//!
+//! Everything random the server or the client does is drawn from a generator
+//! made here, so starting either with `--seed` makes a run reproducible.
+
+use rand::{rngs::StdRng, SeedableRng};
+
+pub fn new(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wordlist::{WordList, WordOptions};
+    use rand::Rng;
+
+    #[test]
+    fn same_seed_same_draws() {
+        let draws = |seed| {
+            let mut rng = new(Some(seed));
+            (0..10).map(|_| rng.gen::<u64>()).collect::<Vec<_>>()
+        };
+        assert_eq!(draws(42), draws(42));
+        assert_ne!(draws(42), draws(43));
+    }
+
+    #[test]
+    fn same_seed_same_words() {
+        let words = WordList::built_in();
+        let options = WordOptions {
+            count: 30,
+            capitalize: true,
+            punctuation: true,
+            numbers: true,
+        };
+        let generate = |seed| words.generate(&mut new(Some(seed)), &options);
+        assert_eq!(generate(7), generate(7));
+    }
+}
@@ -0,0 +1,346 @@
+//! What the server sends: JSON messages tagged with their `type`.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// First frame on every connection, lets the client detect old servers.
+    /// `session` changes on every server start so stale resumes are caught.
+    Hello { protocol: u32, session: String },
+    /// Crate version of the server, sent right after the hello
+    ServerVersion(String),
+    /// The full collaborative sentence as of `version`, and who wrote its
+    /// newest word if anyone is known to have
+    SentenceUpdate {
+        version: u64,
+        sentence: String,
+        author: Option<String>,
+    },
+    /// Words a resuming client missed, bringing it up to `version`
+    Append { version: u64, text: String },
+    /// Who wrote each word of the sentence at `version`, follows every
+    /// `SentenceUpdate` and `Append`. `None` for words nobody is known to have written.
+    Authors {
+        version: u64,
+        names: Vec<Option<String>>,
+    },
+    /// The sentence starts over after this many words
+    MaxWords(usize),
+    /// What to show between words instead of a space, sent when it isn't one.
+    /// The sentence itself still separates words with spaces.
+    Separator(String),
+    /// The oldest unanswered word the client sent was added to the sentence
+    Ack,
+    /// The oldest unanswered word the client sent was dropped, and why
+    Nack(String),
+    /// Something the player should know about
+    Notice(String),
+    /// Words are turned away for a while, with the reason to show
+    Cooldown { retry_after_ms: u64, reason: String },
+    /// Connections only watch until they send `ClientMessage::Join`
+    JoinRequired,
+    /// How many clients are connected and how many of them type
+    Presence { connected: usize, active: usize },
+    /// Fastest players and their WPM, fastest first
+    Leaderboard(Vec<(String, u32)>),
+    /// Everyone in the room in the order they arrived, from protocol 16 on
+    Players(Vec<Player>),
+    /// Who wrote the most words since they arrived, with their speed, from
+    /// protocol 19 on. Sent every few seconds while it changes.
+    Scoreboard(Vec<Score>),
+    /// A chat line from `name`, kept out of the sentence
+    Chat { name: String, text: String },
+    /// Where the server's timed round is at
+    Round(RoundState),
+    /// How the round that just ended went, from protocol 18 on. Sent once,
+    /// right before `Round(RoundState::Over { .. })`.
+    RoundResults {
+        /// The sentence as it stood when time ran out
+        sentence: String,
+        /// Most words first
+        scores: Vec<Score>,
+    },
+    /// Whose turn it is to add a word
+    Turn(TurnState),
+    /// Where the server's race is at, from protocol 17 on
+    Race(RaceState),
+    /// Everyone in the current race, leaders first
+    Racers(Vec<Racer>),
+    /// The server is going away after the countdown
+    Shutdown { in_secs: u64 },
+}
+
+/// Someone in the room, as listed by `ServerMessage::Players`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Player {
+    pub name: String,
+    /// Speed they last reported, `None` until they do
+    pub wpm: Option<u32>,
+    /// Still watching under `--require-join`
+    pub watching: bool,
+}
+
+/// A timed round, from servers started with `--round-seconds`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundState {
+    /// The clock starts with the next word
+    Waiting {
+        length_secs: u64,
+    },
+    Running {
+        remaining_secs: u64,
+    },
+    /// No words are taken until the next round
+    Over {
+        next_in_secs: u64,
+    },
+}
+
+/// What one player wrote, as listed by `ServerMessage::RoundResults` and
+/// `ServerMessage::Scoreboard`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Score {
+    pub name: String,
+    /// Words of theirs in the final sentence, or since they arrived
+    pub words: usize,
+    /// Speed they last reported, `None` if they never did or already left
+    pub wpm: Option<u32>,
+}
+
+/// A race over the same passage, from servers started with `--race`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RaceState {
+    /// Nobody is around to race
+    Waiting,
+    /// The passage to type once the countdown is over
+    Countdown {
+        starts_in_secs: u64,
+        passage: String,
+    },
+    Running {
+        remaining_secs: u64,
+        passage: String,
+    },
+    /// Standings are final until the next race
+    Over { next_in_secs: u64 },
+}
+
+/// Someone in the race, as listed by `ServerMessage::Racers`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Racer {
+    pub name: String,
+    /// Characters of the passage typed correctly
+    pub chars: usize,
+    pub wpm: u32,
+    /// Finishing place, `None` until they're done
+    pub place: Option<u32>,
+}
+
+/// Players take turns adding a word on servers started with `--turns`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TurnState {
+    Yours,
+    /// Someone else's turn, by name
+    Waiting(String),
+    /// Nobody is typing
+    Nobody,
+}
+
+impl ServerMessage {
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("server messages always serialize")
+    }
+
+    /// Returns `None` for anything that isn't a known message
+    pub fn decode(text: &str) -> Option<ServerMessage> {
+        serde_json::from_str(text).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    /// One of each message, every state of the nested enums included
+    fn examples() -> Vec<ServerMessage> {
+        let score = |name: &str, words, wpm| Score {
+            name: name.to_string(),
+            words,
+            wpm,
+        };
+        vec![
+            ServerMessage::Hello {
+                protocol: 20,
+                session: "1a2b3c".to_string(),
+            },
+            ServerMessage::ServerVersion("0.1.0".to_string()),
+            ServerMessage::SentenceUpdate {
+                version: 7,
+                sentence: "once upon a \"time\"".to_string(),
+                author: Some("ada".to_string()),
+            },
+            ServerMessage::SentenceUpdate {
+                version: 0,
+                sentence: String::new(),
+                author: None,
+            },
+            ServerMessage::Append {
+                version: 9,
+                text: "there was".to_string(),
+            },
+            ServerMessage::Authors {
+                version: 9,
+                names: vec![None, Some("ada".to_string()), Some("bo".to_string())],
+            },
+            ServerMessage::MaxWords(50),
+            ServerMessage::Separator(" / ".to_string()),
+            ServerMessage::Ack,
+            ServerMessage::Nack("Too fast".to_string()),
+            ServerMessage::Notice("Welcome to the lobby".to_string()),
+            ServerMessage::Cooldown {
+                retry_after_ms: 1500,
+                reason: "Slow down, too many words at once".to_string(),
+            },
+            ServerMessage::JoinRequired,
+            ServerMessage::Presence {
+                connected: 4,
+                active: 2,
+            },
+            ServerMessage::Leaderboard(vec![("ada".to_string(), 92), ("bo".to_string(), 40)]),
+            ServerMessage::Leaderboard(Vec::new()),
+            ServerMessage::Players(vec![
+                Player {
+                    name: "ada".to_string(),
+                    wpm: Some(92),
+                    watching: false,
+                },
+                Player {
+                    name: "bo".to_string(),
+                    wpm: None,
+                    watching: false,
+                },
+                Player {
+                    name: "cy".to_string(),
+                    wpm: None,
+                    watching: true,
+                },
+            ]),
+            ServerMessage::Scoreboard(vec![score("ada", 12, Some(92)), score("bo", 3, None)]),
+            ServerMessage::Chat {
+                name: "ada".to_string(),
+                text: "hi all, ready?".to_string(),
+            },
+            ServerMessage::Round(RoundState::Waiting { length_secs: 60 }),
+            ServerMessage::Round(RoundState::Running { remaining_secs: 42 }),
+            ServerMessage::Round(RoundState::Over { next_in_secs: 10 }),
+            ServerMessage::RoundResults {
+                sentence: "the end".to_string(),
+                scores: vec![score("ada", 1, Some(80)), score("bo", 1, None)],
+            },
+            ServerMessage::RoundResults {
+                sentence: String::new(),
+                scores: Vec::new(),
+            },
+            ServerMessage::Turn(TurnState::Yours),
+            ServerMessage::Turn(TurnState::Waiting("bo".to_string())),
+            ServerMessage::Turn(TurnState::Nobody),
+            ServerMessage::Race(RaceState::Waiting),
+            ServerMessage::Race(RaceState::Countdown {
+                starts_in_secs: 5,
+                passage: "The quick brown fox.".to_string(),
+            }),
+            ServerMessage::Race(RaceState::Running {
+                remaining_secs: 55,
+                passage: "The quick brown fox.".to_string(),
+            }),
+            ServerMessage::Race(RaceState::Over { next_in_secs: 10 }),
+            ServerMessage::Racers(vec![
+                Racer {
+                    name: "ada".to_string(),
+                    chars: 20,
+                    wpm: 88,
+                    place: Some(1),
+                },
+                Racer {
+                    name: "bo".to_string(),
+                    chars: 7,
+                    wpm: 31,
+                    place: None,
+                },
+            ]),
+            ServerMessage::Shutdown { in_secs: 30 },
+        ]
+    }
+
+    /// Fails to compile when a message is added, so it gets an example too
+    fn variant(msg: &ServerMessage) -> &'static str {
+        match msg {
+            ServerMessage::Hello { .. } => "hello",
+            ServerMessage::ServerVersion(_) => "server_version",
+            ServerMessage::SentenceUpdate { .. } => "sentence_update",
+            ServerMessage::Append { .. } => "append",
+            ServerMessage::Authors { .. } => "authors",
+            ServerMessage::MaxWords(_) => "max_words",
+            ServerMessage::Separator(_) => "separator",
+            ServerMessage::Ack => "ack",
+            ServerMessage::Nack(_) => "nack",
+            ServerMessage::Notice(_) => "notice",
+            ServerMessage::Cooldown { .. } => "cooldown",
+            ServerMessage::JoinRequired => "join_required",
+            ServerMessage::Presence { .. } => "presence",
+            ServerMessage::Leaderboard(_) => "leaderboard",
+            ServerMessage::Players(_) => "players",
+            ServerMessage::Scoreboard(_) => "scoreboard",
+            ServerMessage::Chat { .. } => "chat",
+            ServerMessage::Round(_) => "round",
+            ServerMessage::RoundResults { .. } => "round_results",
+            ServerMessage::Turn(_) => "turn",
+            ServerMessage::Race(_) => "race",
+            ServerMessage::Racers(_) => "racers",
+            ServerMessage::Shutdown { .. } => "shutdown",
+        }
+    }
+
+    #[test]
+    fn every_message_survives_the_trip() {
+        for msg in examples() {
+            let frame = msg.encode();
+            assert_eq!(ServerMessage::decode(&frame), Some(msg), "{frame}");
+        }
+    }
+
+    #[test]
+    fn every_message_has_an_example() {
+        let covered: HashSet<_> = examples().iter().map(variant).collect();
+        assert_eq!(covered.len(), 23);
+    }
+
+    #[test]
+    fn messages_are_tagged_by_variant() {
+        for msg in examples() {
+            let frame: serde_json::Value = serde_json::from_str(&msg.encode()).unwrap();
+            assert_eq!(frame["type"], variant(&msg), "{frame}");
+        }
+    }
+
+    #[test]
+    fn unknown_and_broken_messages_are_ignored() {
+        for frame in [
+            "",
+            "hello 20 abc",
+            "sentence 7 once upon a time",
+            r#"{"type":"hello","data":{"protocol":"twenty","session":"abc"}}"#,
+            r#"{"type":"presence","data":{"connected":4}}"#,
+            r#"{"type":"round","data":{"paused":{"secs":3}}}"#,
+            r#"{"type":"turn","data":"maybe"}"#,
+            r#"{"type":"teleport","data":[1,2]}"#,
+        ] {
+            assert_eq!(ServerMessage::decode(frame), None, "{frame}");
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Random words to type, for the client's offline practice and the server's
+//! generated races.
+//!
+//! The built-in list holds common English words. Capitals, punctuation and
+//! numbers are optional, so the same list makes anything from a warm-up to a
+//! test of the whole keyboard.
+
+use rand::Rng;
+use std::{io, path::Path};
+
+/// One per line
+const BUILT_IN: &str = include_str!("words.txt");
+
+/// Chance a word is swapped for a number, with `WordOptions::numbers`
+const NUMBER_ODDS: f64 = 0.1;
+
+/// Chance a word is followed by a comma, with `WordOptions::punctuation`
+const COMMA_ODDS: f64 = 0.1;
+
+/// Chance a word ends its sentence, with `WordOptions::punctuation`
+const SENTENCE_END_ODDS: f64 = 0.12;
+
+/// Chance a word gets a capital with `WordOptions::capitalize` alone, where
+/// there are no sentences to start
+const CAPITAL_ODDS: f64 = 0.15;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordList {
+    words: Vec<String>,
+}
+
+/// What goes into generated text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WordOptions {
+    pub count: usize,
+    /// Capitals at the start of sentences, or on the odd word without punctuation
+    pub capitalize: bool,
+    /// Commas, and sentences ending in `.`, `?` or `!`
+    pub punctuation: bool,
+    /// The odd number among the words
+    pub numbers: bool,
+}
+
+impl WordList {
+    pub fn built_in() -> WordList {
+        WordList {
+            words: BUILT_IN.lines().map(str::to_string).collect(),
+        }
+    }
+
+    /// Whitespace separated words, typically one per line
+    pub fn load(path: &Path) -> io::Result<WordList> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(WordList {
+            words: text.split_whitespace().map(str::to_string).collect(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// `options.count` words drawn at random, repeats allowed
+    pub fn generate(&self, rng: &mut impl Rng, options: &WordOptions) -> Vec<String> {
+        if self.words.is_empty() {
+            return Vec::new();
+        }
+        let mut words = Vec::with_capacity(options.count);
+        let mut sentence_start = true;
+        for i in 0..options.count {
+            let mut word = if options.numbers && rng.gen_bool(NUMBER_ODDS) {
+                rng.gen_range(0..10_000).to_string()
+            } else {
+                self.words[rng.gen_range(0..self.words.len())].clone()
+            };
+            let capital = if options.punctuation {
+                sentence_start
+            } else {
+                rng.gen_bool(CAPITAL_ODDS)
+            };
+            if options.capitalize && capital {
+                word = capitalized(&word);
+            }
+            sentence_start = false;
+            if options.punctuation {
+                let last = i + 1 == options.count;
+                if last || rng.gen_bool(SENTENCE_END_ODDS) {
+                    word.push(['.', '.', '.', '?', '!'][rng.gen_range(0..5)]);
+                    sentence_start = true;
+                } else if rng.gen_bool(COMMA_ODDS) {
+                    word.push(',');
+                }
+            }
+            words.push(word);
+        }
+        words
+    }
+}
+
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
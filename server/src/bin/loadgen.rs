@@ -0,0 +1,252 @@
+//! Load generator for the server.
+//!
+//! Connects a number of clients to a running server, each introducing itself
+//! like the real client and then sending words at a fixed rate, then reports
+//! how many words the server accepted per second, why it turned any down and
+//! how long it took for a word to reach every client in a broadcast.
+
+use futures::{SinkExt, StreamExt};
+use protocol::{ClientMessage, ServerMessage, DEFAULT_PORT};
+use std::{
+    collections::HashMap,
+    env,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::task::JoinSet;
+use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+
+/// Load generator options set from the command line
+#[derive(Debug)]
+struct Config {
+    url: String,
+    clients: usize,
+    /// Words per second sent by each client
+    rate: f64,
+    duration: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            url: format!("ws://127.0.0.1:{}", DEFAULT_PORT),
+            clients: 10,
+            // The server takes 5 words/s per connection unless told otherwise
+            rate: 4.0,
+            duration: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Shared between every client task
+#[derive(Default)]
+struct Stats {
+    /// When each word was sent, looked up when a broadcast ends with it
+    sent_at: HashMap<String, Instant>,
+    sent: usize,
+    acked: usize,
+    /// Turned down words by the server's reason, "Too fast" when throttled
+    nacked: HashMap<String, usize>,
+    /// Time from sending a word to each client seeing it in the sentence
+    latencies: Vec<Duration>,
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().collect();
+    let config = match parse_args(&args[1..]) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            eprintln!(
+                "Usage: {} [--url URL] [--clients N] [--rate WORDS_PER_SEC] [--duration SECS]",
+                args[0]
+            );
+            std::process::exit(1);
+        }
+    };
+
+    println!(
+        "Running {} clients at {} words/s each against {} for {}s",
+        config.clients,
+        config.rate,
+        config.url,
+        config.duration.as_secs()
+    );
+
+    let stats = Arc::new(Mutex::new(Stats::default()));
+    let start = Instant::now();
+    let deadline = start + config.duration;
+    let interval = Duration::from_secs_f64(1.0 / config.rate);
+    let mut clients = JoinSet::new();
+
+    for id in 0..config.clients {
+        let url = config.url.clone();
+        let stats = Arc::clone(&stats);
+        clients.spawn(async move {
+            let ws_stream = match connect_async(&url).await {
+                Ok((ws_stream, _)) => ws_stream,
+                Err(err) => {
+                    eprintln!("Client {id} failed to connect: {err}");
+                    return;
+                }
+            };
+            let (mut write, mut read) = ws_stream.split();
+
+            // Name ourselves after the hello and join if we have to, words
+            // only count once we have the sentence
+            loop {
+                let text = match read.next().await {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(_)) => continue,
+                    _ => {
+                        eprintln!("Client {id} was closed before it got the sentence");
+                        return;
+                    }
+                };
+                let reply = match ServerMessage::decode(&text) {
+                    Some(ServerMessage::Hello { .. }) => {
+                        ClientMessage::Name(format!("load-{id}")).encode()
+                    }
+                    Some(ServerMessage::JoinRequired) => ClientMessage::Join.encode(),
+                    Some(ServerMessage::SentenceUpdate { .. } | ServerMessage::Append { .. }) => {
+                        break
+                    }
+                    _ => continue,
+                };
+                if write.send(reply.into()).await.is_err() {
+                    return;
+                }
+            }
+
+            let reader_stats = Arc::clone(&stats);
+            let reader = tokio::spawn(async move {
+                while let Some(Ok(msg)) = read.next().await {
+                    let Message::Text(text) = msg else {
+                        continue;
+                    };
+                    let mut stats = reader_stats.lock().unwrap();
+                    match ServerMessage::decode(&text) {
+                        Some(ServerMessage::Ack) => stats.acked += 1,
+                        Some(ServerMessage::Nack(reason)) => {
+                            *stats.nacked.entry(reason).or_default() += 1;
+                        }
+                        Some(ServerMessage::SentenceUpdate { sentence, .. }) => {
+                            // Only the newest word tells us how long the broadcast took
+                            let last_word = sentence.rsplit(' ').next().unwrap_or("");
+                            if let Some(sent_at) = stats.sent_at.get(last_word).copied() {
+                                stats.latencies.push(sent_at.elapsed());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            });
+
+            let mut ticker = tokio::time::interval(interval);
+            let mut n = 0;
+            while Instant::now() < deadline {
+                ticker.tick().await;
+                let word = format!("c{id}w{n}");
+                n += 1;
+                {
+                    let mut stats = stats.lock().unwrap();
+                    stats.sent_at.insert(word.clone(), Instant::now());
+                    stats.sent += 1;
+                }
+                if write
+                    .send(ClientMessage::SubmitWord(word.clone()).encode().into())
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+            }
+
+            // Give the last broadcasts a moment to arrive
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            let _ = write.close().await;
+            reader.abort();
+        });
+    }
+
+    while clients.join_next().await.is_some() {}
+
+    let elapsed = deadline.min(Instant::now()) - start;
+    let mut stats = stats.lock().unwrap();
+    stats.latencies.sort();
+    println!("Words sent: {}", stats.sent);
+    println!("Words acked: {}", stats.acked);
+    let mut nacked: Vec<_> = stats.nacked.iter().collect();
+    nacked.sort();
+    for (reason, count) in nacked {
+        println!("Words nacked ({reason}): {count}");
+    }
+    println!(
+        "Appended words/s: {:.1}",
+        stats.acked as f64 / elapsed.as_secs_f64()
+    );
+    if stats.latencies.is_empty() {
+        println!("No broadcasts received");
+        return;
+    }
+    println!(
+        "Broadcast latency over {} deliveries: p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+        stats.latencies.len(),
+        percentile(&stats.latencies, 50.0),
+        percentile(&stats.latencies, 90.0),
+        percentile(&stats.latencies, 99.0),
+        stats.latencies[stats.latencies.len() - 1]
+    );
+}
+
+/// Nearest-rank percentile of already sorted samples
+fn percentile(sorted: &[Duration], pct: f64) -> Duration {
+    let rank = (pct / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn parse_args(args: &[String]) -> Result<Config, String> {
+    let mut config = Config::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--url" => config.url = flag_value(&mut iter, arg)?.to_string(),
+            "--clients" => {
+                config.clients = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|clients| *clients > 0)
+                    .ok_or_else(|| format!("{arg} needs a positive number"))?;
+            }
+            "--rate" => {
+                config.rate = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|rate: &f64| *rate > 0.0 && rate.is_finite())
+                    .ok_or_else(|| format!("{arg} needs a positive number"))?;
+            }
+            "--duration" => {
+                let secs = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or_else(|| format!("{arg} needs a positive number of seconds"))?;
+                config.duration = Duration::from_secs(secs);
+            }
+            other => return Err(format!("Unknown argument: {other}")),
+        }
+    }
+
+    Ok(config)
+}
+
+fn flag_value<'a>(
+    iter: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<&'a str, String> {
+    iter.next()
+        .map(String::as_str)
+        .ok_or_else(|| format!("Missing value for {flag}"))
+}
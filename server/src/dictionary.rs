@@ -0,0 +1,86 @@
+//! `--dictionary`: only real words make it into the sentence.
+//!
+//! The word list is a plain text file, whitespace separated. Words are
+//! compared lowercased with surrounding punctuation stripped, so `Hello,`
+//! matches `hello` while `don't` keeps its apostrophe.
+
+use std::{collections::HashSet, io, path::Path};
+
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    pub fn load(path: &Path) -> io::Result<Dictionary> {
+        let text = std::fs::read_to_string(path)?;
+        let words = text
+            .split_whitespace()
+            .map(normalize)
+            .filter(|word| !word.is_empty())
+            .collect();
+        Ok(Dictionary { words })
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Whether every word of a submission is in the list
+    pub fn accepts(&self, text: &str) -> bool {
+        text.split_whitespace()
+            .all(|word| self.words.contains(&normalize(word)))
+    }
+}
+
+/// `word` as it's looked up, here and by `--filter`
+pub fn normalize(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dictionary(words: &[&str]) -> Dictionary {
+        Dictionary {
+            words: words.iter().map(|word| normalize(word)).collect(),
+        }
+    }
+
+    #[test]
+    fn case_and_surrounding_punctuation_are_ignored() {
+        let dictionary = dictionary(&["hello", "Don't", "(world)"]);
+        assert_eq!(dictionary.len(), 3);
+        for word in ["hello", "HeLLo", "hello,", "\"hello!\"", "don't", "World?"] {
+            assert!(dictionary.accepts(word), "{word}");
+        }
+        for word in ["helo", "dont", "hello-world", "w0rld"] {
+            assert!(!dictionary.accepts(word), "{word}");
+        }
+    }
+
+    #[test]
+    fn every_word_of_a_submission_must_be_known() {
+        let dictionary = dictionary(&["hello", "world"]);
+        assert!(dictionary.accepts("hello world"));
+        assert!(!dictionary.accepts("hello there world"));
+    }
+
+    #[test]
+    fn word_lists_are_whitespace_separated() {
+        let path =
+            std::env::temp_dir().join(format!("chaos-type-dictionary-{}", std::process::id()));
+        std::fs::write(&path, "one two\n\tthree\n\n,,,\n").unwrap();
+        let dictionary = Dictionary::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dictionary.len(), 3);
+        assert!(dictionary.accepts("three"));
+        assert!(Dictionary::load(&path).is_err());
+    }
+}
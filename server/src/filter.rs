@@ -0,0 +1,173 @@
+//! `--filter`: keeps banned words out of the sentence and the chat.
+//!
+//! Words are matched the way `--dictionary` matches them, lowercased with
+//! surrounding punctuation stripped, so `Damn!` is caught like `damn`. The
+//! built-in list only covers common English swearing, `--filter-file` takes a
+//! list of your own in the same whitespace separated format.
+
+use crate::dictionary::normalize;
+use std::{collections::HashSet, io, path::Path};
+
+/// Used unless `--filter-file` is given
+const BUILT_IN: &[&str] = &[
+    "arse",
+    "arsehole",
+    "ass",
+    "asshole",
+    "bastard",
+    "bitch",
+    "bollocks",
+    "bullshit",
+    "crap",
+    "cunt",
+    "damn",
+    "dick",
+    "dickhead",
+    "fuck",
+    "fucked",
+    "fucker",
+    "fucking",
+    "motherfucker",
+    "piss",
+    "pissed",
+    "prick",
+    "shit",
+    "shitty",
+    "slut",
+    "twat",
+    "wanker",
+    "whore",
+];
+
+/// What happens to a submission with a banned word in it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterMode {
+    /// Added with the banned words starred out
+    Mask,
+    /// Turned away
+    Reject,
+}
+
+impl FilterMode {
+    pub fn parse(value: &str) -> Option<FilterMode> {
+        match value {
+            "mask" => Some(FilterMode::Mask),
+            "reject" => Some(FilterMode::Reject),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct WordFilter {
+    words: HashSet<String>,
+    mode: FilterMode,
+}
+
+impl WordFilter {
+    pub fn built_in(mode: FilterMode) -> WordFilter {
+        let words = BUILT_IN.iter().map(|word| word.to_string()).collect();
+        WordFilter { words, mode }
+    }
+
+    pub fn load(path: &Path, mode: FilterMode) -> io::Result<WordFilter> {
+        let text = std::fs::read_to_string(path)?;
+        let words = text
+            .split_whitespace()
+            .map(normalize)
+            .filter(|word| !word.is_empty())
+            .collect();
+        Ok(WordFilter { words, mode })
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Whether `text` is to be turned away
+    pub fn rejects(&self, text: &str) -> bool {
+        self.mode == FilterMode::Reject
+            && text
+                .split_whitespace()
+                .any(|word| self.words.contains(&normalize(word)))
+    }
+
+    /// `text` with its banned words starred out, `None` when there are none or
+    /// they get rejected instead. Punctuation around a word is kept.
+    pub fn mask(&self, text: &str) -> Option<String> {
+        if self.mode != FilterMode::Mask {
+            return None;
+        }
+        let mut masked = false;
+        let words: Vec<String> = text
+            .split_whitespace()
+            .map(|word| {
+                if !self.words.contains(&normalize(word)) {
+                    return word.to_string();
+                }
+                masked = true;
+                let is_punctuation = |c: char| !c.is_alphanumeric();
+                let rest = word.trim_start_matches(is_punctuation);
+                let core = rest.trim_end_matches(is_punctuation);
+                format!(
+                    "{}{}{}",
+                    &word[..word.len() - rest.len()],
+                    "*".repeat(core.chars().count()),
+                    &rest[core.len()..]
+                )
+            })
+            .collect();
+        masked.then(|| words.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_parse() {
+        assert_eq!(FilterMode::parse("mask"), Some(FilterMode::Mask));
+        assert_eq!(FilterMode::parse("reject"), Some(FilterMode::Reject));
+        assert_eq!(FilterMode::parse("Mask"), None);
+    }
+
+    #[test]
+    fn masking_stars_out_banned_words_only() {
+        let filter = WordFilter::built_in(FilterMode::Mask);
+        assert_eq!(filter.mask("Damn!"), Some("****!".to_string()));
+        assert_eq!(
+            filter.mask("well, (shit) happens"),
+            Some("well, (****) happens".to_string())
+        );
+        assert_eq!(filter.mask("hello"), None);
+        assert_eq!(filter.mask("classic"), None);
+        assert!(!filter.rejects("damn"));
+    }
+
+    #[test]
+    fn rejecting_turns_away_any_banned_word() {
+        let filter = WordFilter::built_in(FilterMode::Reject);
+        assert!(filter.rejects("DAMN"));
+        assert!(filter.rejects("oh \"damn\"..."));
+        assert!(!filter.rejects("hello world"));
+        assert_eq!(filter.mask("damn"), None);
+    }
+
+    #[test]
+    fn filter_files_replace_the_built_in_list() {
+        let path = std::env::temp_dir().join(format!("filter-test-{}", std::process::id()));
+        std::fs::write(&path, "Rhubarb\n  custard, \n").unwrap();
+        let filter = WordFilter::load(&path, FilterMode::Reject).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(filter.len(), 2);
+        assert!(filter.rejects("rhubarb!"));
+        assert!(filter.rejects("Custard"));
+        assert!(!filter.rejects("damn"));
+    }
+}
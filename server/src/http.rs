@@ -0,0 +1,116 @@
+//! `--http-port`: the lobby's sentence over plain HTTP, read-only.
+//!
+//! Meant for stream overlays and web pages that poll it. `/` is the sentence
+//! as text, `/state` is JSON with the word and player counts too. Just enough
+//! HTTP/1.1 for that: one `GET` per connection, answered and closed.
+
+use crate::{presence::SharedPresence, store::SharedStore};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, warn};
+
+/// Requests bigger than this are turned away
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// How long a client gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pause after a failed accept, which mostly means we're out of file
+/// descriptors and trying again right away would just spin
+pub const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+/// Answers requests on `listener` forever
+pub async fn serve(listener: TcpListener, sentence: SharedStore, presence: SharedPresence) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                warn!(%err, "could not accept an http connection");
+                tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                continue;
+            }
+        };
+        let sentence = sentence.clone();
+        let presence = presence.clone();
+        tokio::spawn(async move {
+            let answered =
+                tokio::time::timeout(REQUEST_TIMEOUT, respond(stream, &sentence, &presence)).await;
+            if !matches!(answered, Ok(Ok(()))) {
+                debug!(%peer, "http request failed");
+            }
+        });
+    }
+}
+
+async fn respond(
+    mut stream: TcpStream,
+    sentence: &SharedStore,
+    presence: &SharedPresence,
+) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..read]);
+        if request.len() > MAX_REQUEST_BYTES {
+            return write(&mut stream, "413 Payload Too Large", "text/plain", "").await;
+        }
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    if method != "GET" {
+        return write(&mut stream, "405 Method Not Allowed", "text/plain", "").await;
+    }
+
+    // Copied out, the lock isn't held while the client reads
+    let (text, words) = {
+        let store = sentence.lock().unwrap();
+        (store.current(), store.word_count())
+    };
+    let (content_type, body) = match path {
+        "/" => ("text/plain; charset=utf-8", text),
+        "/state" => {
+            let (connected, active) = presence.lock().unwrap().counts();
+            let body = serde_json::json!({
+                "sentence": text,
+                "words": words,
+                "connected": connected,
+                "active": active,
+            })
+            .to_string();
+            ("application/json", body)
+        }
+        _ => return write(&mut stream, "404 Not Found", "text/plain", "").await,
+    };
+    write(&mut stream, "200 OK", content_type, &body).await
+}
+
+async fn write(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    // Overlays poll from other origins and must never see a stale copy
+    let response = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: {content_type}\r\n\
+         Content-Length: {}\r\n\
+         Cache-Control: no-store\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,67 @@
+//! Typing speeds the clients report, ranked for everyone to see.
+//!
+//! Clients send their WPM every few seconds. A player leaves the board when
+//! their connection closes, or once their reports stop coming.
+
+use crate::store::AuthorId;
+use protocol::ServerMessage;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Leaderboard shared by every connection task
+pub type SharedLeaderboard = Arc<Mutex<Leaderboard>>;
+
+/// Players ranked in a broadcast
+const MAX_ENTRIES: usize = 10;
+
+/// A report this old means the player stopped typing or stopped reporting
+const STALE_AFTER: Duration = Duration::from_secs(15);
+
+/// Anything faster is a client making numbers up
+pub const MAX_WPM: u32 = 300;
+
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    wpm: u32,
+    reported: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct Leaderboard {
+    entries: HashMap<AuthorId, Entry>,
+}
+
+impl Leaderboard {
+    pub fn report(&mut self, author: AuthorId, name: &str, wpm: u32, now: Instant) {
+        let entry = Entry {
+            name: name.to_string(),
+            wpm: wpm.min(MAX_WPM),
+            reported: now,
+        };
+        self.entries.insert(author, entry);
+    }
+
+    /// Returns whether the player was on the board
+    pub fn remove(&mut self, author: AuthorId) -> bool {
+        self.entries.remove(&author).is_some()
+    }
+
+    /// The fastest players, stale reports dropped first
+    pub fn message(&mut self, now: Instant) -> ServerMessage {
+        self.entries
+            .retain(|_, entry| now.duration_since(entry.reported) < STALE_AFTER);
+        let mut ranked: Vec<&Entry> = self.entries.values().collect();
+        ranked.sort_by(|a, b| b.wpm.cmp(&a.wpm).then_with(|| a.name.cmp(&b.name)));
+        ServerMessage::Leaderboard(
+            ranked
+                .into_iter()
+                .take(MAX_ENTRIES)
+                .map(|entry| (entry.name.clone(), entry.wpm))
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,140 @@
+//! Rate limits protecting the server from floods.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+/// Token bucket for the words of one connection: a burst of up to `rate`
+/// words, refilled at `rate` words per second
+#[derive(Debug)]
+pub struct WordLimiter {
+    rate: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl WordLimiter {
+    pub fn new(rate: u32, now: Instant) -> WordLimiter {
+        WordLimiter {
+            rate: rate as f64,
+            tokens: rate as f64,
+            refilled: now,
+        }
+    }
+
+    /// Uses up one word, returns false if there is none left right now
+    pub fn allow(&mut self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.refilled = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+
+    /// How long until the next word is allowed
+    pub fn retry_after(&self) -> Duration {
+        Duration::from_secs_f64((1.0 - self.tokens).max(0.0) / self.rate)
+    }
+}
+
+/// Limits how many connections one IP may open within a sliding window
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    max: usize,
+    window: Duration,
+    recent: HashMap<IpAddr, VecDeque<Instant>>,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max: usize, window: Duration) -> ConnectionLimiter {
+        ConnectionLimiter {
+            max,
+            window,
+            recent: HashMap::new(),
+        }
+    }
+
+    /// Records a connection attempt, returns false if `ip` is over the limit
+    pub fn allow(&mut self, ip: IpAddr, now: Instant) -> bool {
+        // Keep the map from growing with addresses that went quiet
+        if self.recent.len() > 1024 {
+            let window = self.window;
+            self.recent.retain(|_, times| {
+                times
+                    .back()
+                    .is_some_and(|last| now.duration_since(*last) < window)
+            });
+        }
+
+        let times = self.recent.entry(ip).or_default();
+        while times
+            .front()
+            .is_some_and(|first| now.duration_since(*first) >= self.window)
+        {
+            times.pop_front();
+        }
+
+        if times.len() >= self.max {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_burst_is_cut_off_at_the_rate() {
+        let start = Instant::now();
+        let mut limiter = WordLimiter::new(5, start);
+        let allowed = (0..20).filter(|_| limiter.allow(start)).count();
+        assert_eq!(allowed, 5);
+        assert_eq!(limiter.retry_after(), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn words_come_back_at_the_rate() {
+        let start = Instant::now();
+        let mut limiter = WordLimiter::new(5, start);
+        while limiter.allow(start) {}
+        assert!(!limiter.allow(start + Duration::from_millis(100)));
+        assert!(limiter.allow(start + Duration::from_millis(300)));
+        // A quiet spell refills the burst, but not past it
+        let later = start + Duration::from_secs(60);
+        assert_eq!((0..20).filter(|_| limiter.allow(later)).count(), 5);
+    }
+
+    #[test]
+    fn rapid_connects_from_one_address_are_turned_away() {
+        let mut limiter = ConnectionLimiter::new(3, Duration::from_secs(10));
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+        let start = Instant::now();
+        for attempt in 0..3 {
+            assert!(limiter.allow(ip, start + Duration::from_millis(attempt)));
+        }
+        assert!(!limiter.allow(ip, start + Duration::from_millis(3)));
+        // Someone else isn't held up by it
+        assert!(limiter.allow("203.0.113.8".parse().unwrap(), start));
+    }
+
+    #[test]
+    fn the_window_slides() {
+        let mut limiter = ConnectionLimiter::new(2, Duration::from_secs(10));
+        let ip: IpAddr = "::1".parse().unwrap();
+        let start = Instant::now();
+        assert!(limiter.allow(ip, start));
+        assert!(limiter.allow(ip, start + Duration::from_secs(5)));
+        assert!(!limiter.allow(ip, start + Duration::from_secs(9)));
+        // The first connection left the window, the turned away one never counted
+        assert!(limiter.allow(ip, start + Duration::from_secs(10)));
+        assert!(!limiter.allow(ip, start + Duration::from_secs(11)));
+    }
+}
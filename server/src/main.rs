@@ -1,52 +1,2274 @@
+mod dictionary;
+mod filter;
+mod http;
+mod leaderboard;
+mod limits;
+mod persist;
+mod presence;
+mod race;
+mod rng;
+mod room;
+mod roster;
+mod round;
+mod rules;
+mod store;
+mod turns;
+
+use dictionary::Dictionary;
+use filter::{FilterMode, WordFilter};
 use futures::{SinkExt, StreamExt};
-use std::sync::{Arc, Mutex};
-use tokio::net::TcpListener;
-use tokio::sync::broadcast;
-use tokio_tungstenite::accept_async;
+use leaderboard::{Leaderboard, SharedLeaderboard};
+use limits::{ConnectionLimiter, WordLimiter};
+use presence::{Presence, SharedPresence};
+use protocol::{
+    room_from_query, valid_name, ClientMessage, CloseReason, Resume, ServerMessage, TurnState,
+    DEFAULT_PORT, LOBBY, MAX_NAME_CHARS, PROTOCOL_VERSION,
+};
+use race::{Passages, Race, SharedRace};
+use rand::Rng;
+use rng::SharedRng;
+use room::{Membership, Room, Rooms, SharedRooms};
+use roster::{Roster, SharedRoster};
+use round::{Round, SharedRound, Tick};
+use rules::{Case, RoomRules, Rules, DEFAULT_SEPARATOR, MAX_SEPARATOR_CHARS};
+use std::{
+    env, fs,
+    future::Future,
+    io::IsTerminal,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use store::{AuthorId, MemoryStore, SentenceStore, SharedStore, NO_AUTHOR};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast::{self, error::RecvError, WeakSender};
+use tokio::task::JoinSet;
+use tokio_tungstenite::{
+    accept_hdr_async_with_config,
+    tungstenite::{
+        handshake::server::{Request, Response},
+        protocol::{frame::coding::CloseCode, CloseFrame, Message, WebSocketConfig},
+    },
+    WebSocketStream,
+};
+use tracing::{debug, error, info, warn, Instrument, Level};
+use tracing_subscriber::{filter::LevelFilter, EnvFilter};
+use turns::{SharedTurns, Turns};
+
+/// Server options set from the command line
+#[derive(Debug)]
+struct Config {
+    /// Interface to listen on, `0.0.0.0` to accept players from the LAN
+    host: IpAddr,
+    /// Port players connect to
+    port: u16,
+    /// How long clients get to see the shutdown countdown before being closed
+    shutdown_grace: Duration,
+    /// Most connections one IP may open per `conn_rate_window`
+    conn_rate_limit: Option<usize>,
+    conn_rate_window: Duration,
+    /// Seed for every random choice the server makes
+    seed: Option<u64>,
+    /// Signature added to a finished sentence when it's archived
+    append_on_reset: Option<String>,
+    /// Words every fresh sentence starts with, whitespace already collapsed
+    prompt: Option<String>,
+    /// Start a fresh sentence once this many words were written
+    max_words: Option<usize>,
+    /// Drop the oldest words so the sentence never grows past this many
+    keep_words: Option<usize>,
+    /// Shown between words by clients that know about it
+    separator: String,
+    /// What submitted words are turned into
+    case: Case,
+    /// Rooms that play by other `max_words`, `separator` or `case` rules
+    rooms_file: Option<PathBuf>,
+    /// Take the client IP from `X-Forwarded-For`, only safe behind a proxy that sets it
+    trust_proxy: bool,
+    /// Send a client the sentence with its own word right away instead of
+    /// waiting for its turn in the broadcast queue
+    echo_to_sender: bool,
+    /// New connections watch until they ask to join as a typist
+    require_join: bool,
+    /// Typists add one word each in turn
+    turns: bool,
+    /// Restore the sentence from here on start and keep it saved there
+    save_file: Option<PathBuf>,
+    /// Players may wipe the sentence for everyone
+    allow_clear: bool,
+    /// Word list a submitted word must be in
+    dictionary: Option<PathBuf>,
+    /// Mask or reject banned words in submissions and chat
+    filter: Option<FilterMode>,
+    /// Banned words instead of the built-in list
+    filter_file: Option<PathBuf>,
+    /// Play timed rounds of this length, starting with each round's first word
+    round_length: Option<Duration>,
+    /// Words one connection may send per second, as a burst or spread out
+    max_words_per_second: Option<u32>,
+    /// Longest word we take, each word of a multi-word submission counted on its own
+    max_word_chars: Option<usize>,
+    /// How often every connection is pinged
+    ping_interval: Duration,
+    /// Connections silent for this long, pongs included, are dropped
+    ping_timeout: Duration,
+    /// Typists who send no word or chat line for this long are disconnected,
+    /// players still watching under `--require-join` never are
+    idle_timeout: Option<Duration>,
+    /// Most detailed log events shown, `RUST_LOG` decides when unset
+    log_level: Option<Level>,
+    /// Also serve the lobby's sentence over plain HTTP on this port
+    http_port: Option<u16>,
+    /// Race through passages instead of writing a sentence together
+    race: bool,
+    /// Passages to race through instead of the built-in ones
+    race_file: Option<PathBuf>,
+    /// Race on this many generated words instead of passages
+    race_words: Option<usize>,
+}
+
+/// Longest `--append-on-reset` suffix we accept
+const MAX_SUFFIX_CHARS: usize = 200;
+
+/// Biggest message a client may send. Fragmented messages are reassembled up
+/// to this size before we see them, anything bigger drops the connection.
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+/// Longest chat message we pass on
+const MAX_CHAT_CHARS: usize = 200;
+
+/// How often the scoreboard goes out, if it changed
+const SCOREBOARD_INTERVAL: Duration = Duration::from_secs(5);
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: DEFAULT_PORT,
+            shutdown_grace: Duration::ZERO,
+            conn_rate_limit: None,
+            conn_rate_window: Duration::from_secs(10),
+            seed: None,
+            append_on_reset: None,
+            prompt: None,
+            max_words: None,
+            keep_words: None,
+            separator: DEFAULT_SEPARATOR.to_string(),
+            case: Case::Keep,
+            rooms_file: None,
+            trust_proxy: false,
+            echo_to_sender: false,
+            require_join: false,
+            turns: false,
+            save_file: None,
+            allow_clear: false,
+            dictionary: None,
+            filter: None,
+            filter_file: None,
+            round_length: None,
+            log_level: None,
+            http_port: None,
+            race: false,
+            race_file: None,
+            race_words: None,
+            max_words_per_second: Some(5),
+            max_word_chars: Some(45),
+            ping_interval: Duration::from_secs(15),
+            ping_timeout: Duration::from_secs(45),
+            idle_timeout: None,
+        }
+    }
+}
+
+impl Config {
+    /// The rules of every room the rooms file doesn't say otherwise for
+    fn rules(&self) -> Rules {
+        Rules {
+            max_words: self.max_words,
+            separator: self.separator.clone(),
+            case: self.case,
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    let listener = TcpListener::bind("127.0.0.1:9001").await.unwrap();
-    println!("Server running on ws://127.0.0.1:9001");
+    let args: Vec<String> = env::args().collect();
+    if args[1..].iter().any(|arg| arg == "--version") {
+        println!(
+            "chaos-type-server {} (protocol {PROTOCOL_VERSION})",
+            env!("CARGO_PKG_VERSION")
+        );
+        return;
+    }
+    let config = match parse_args(&args[1..]) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            eprintln!(
+                "Usage: {} [--host IP] [--port PORT] [--shutdown-grace SECS] [--conn-rate-limit N] [--conn-rate-window SECS] [--seed N] [--append-on-reset TEXT] [--prompt TEXT] [--prompt-file PATH] [--max-words N] [--keep-words N] [--separator TEXT] [--case keep|lower|upper] [--rooms-file PATH] [--trust-proxy] [--echo-to-sender] [--require-join] [--turns] [--save-file PATH] [--allow-clear] [--dictionary PATH] [--filter mask|reject] [--filter-file PATH] [--round-seconds N] [--log-level LEVEL] [--max-words-per-second N] [--max-word-chars N] [--ping-interval SECS] [--ping-timeout SECS] [--idle-timeout SECS] [--http-port PORT] [--race] [--race-file PATH] [--race-words N] [--version]",
+                args[0]
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let filter = match config.log_level {
+        Some(level) => EnvFilter::default().add_directive(LevelFilter::from_level(level).into()),
+        None => EnvFilter::builder()
+            .with_default_directive(LevelFilter::INFO.into())
+            .from_env_lossy(),
+    };
+    // Plain text when the log goes to a file
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_ansi(std::io::stdout().is_terminal())
+        .init();
+
+    let addr = SocketAddr::new(config.host, config.port);
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!(%addr, %err, "could not listen");
+            std::process::exit(1);
+        }
+    };
+    let addr = listener.local_addr().unwrap_or(addr);
+    info!("Server running on ws://{addr}");
+    let http_listener = match config.http_port {
+        Some(port) => {
+            let addr = SocketAddr::new(config.host, port);
+            match TcpListener::bind(addr).await {
+                Ok(listener) => {
+                    info!("Serving the sentence on http://{addr}");
+                    Some(listener)
+                }
+                Err(err) => {
+                    error!(%addr, %err, "could not listen");
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => None,
+    };
+
+    let sentence = store::shared(MemoryStore::default());
+    if let Some(path) = &config.save_file {
+        match persist::load(path, &sentence) {
+            Ok(0) => {}
+            Ok(words) => info!(words, path = %path.display(), "restored the sentence"),
+            Err(err) => {
+                error!(path = %path.display(), %err, "could not read the save file");
+                std::process::exit(1);
+            }
+        }
+        // The file may predate a smaller --keep-words
+        if let Some(keep) = config.keep_words {
+            sentence.lock().unwrap().trim_front(keep);
+        }
+        tokio::spawn(persist::save_changes(path.clone(), Arc::clone(&sentence)));
+    }
+    // A restored sentence already has its prompt
+    {
+        let mut store = sentence.lock().unwrap();
+        if store.word_count() == 0 {
+            start_sentence(store.as_mut(), &config);
+        }
+    }
+    let dictionary = config
+        .dictionary
+        .as_ref()
+        .map(|path| match Dictionary::load(path) {
+            Ok(dictionary) if !dictionary.is_empty() => {
+                info!(
+                    words = dictionary.len(),
+                    path = %path.display(),
+                    "loaded the dictionary"
+                );
+                Arc::new(dictionary)
+            }
+            Ok(_) => {
+                error!(path = %path.display(), "the dictionary has no words");
+                std::process::exit(1);
+            }
+            Err(err) => {
+                error!(path = %path.display(), %err, "could not read the dictionary");
+                std::process::exit(1);
+            }
+        });
+    let passages = config.race.then(|| {
+        if let Some(count) = config.race_words {
+            return Arc::new(Passages::generated(count));
+        }
+        let Some(path) = &config.race_file else {
+            return Arc::new(Passages::built_in());
+        };
+        match Passages::load(path) {
+            Ok(passages) if !passages.is_empty() => {
+                info!(passages = passages.len(), path = %path.display(), "loaded the race passages");
+                Arc::new(Passages::Fixed(passages))
+            }
+            Ok(_) => {
+                error!(path = %path.display(), "the race file has no passages");
+                std::process::exit(1);
+            }
+            Err(err) => {
+                error!(path = %path.display(), %err, "could not read the race file");
+                std::process::exit(1);
+            }
+        }
+    });
+    let room_rules = match &config.rooms_file {
+        Some(path) => match RoomRules::load(path) {
+            Ok(rules) => rules,
+            Err(err) => {
+                error!(path = %path.display(), %err, "could not read the rooms file");
+                std::process::exit(1);
+            }
+        },
+        None => RoomRules::default(),
+    };
+    let prompt_words = config
+        .prompt
+        .as_ref()
+        .map_or(0, |prompt| prompt.split_whitespace().count());
+    if let Some(room) = room_rules.too_short_for(prompt_words) {
+        error!(room, "max_words leaves no room for a word after the prompt");
+        std::process::exit(1);
+    }
+    let filter = config.filter.map(|mode| {
+        let Some(path) = &config.filter_file else {
+            return Arc::new(WordFilter::built_in(mode));
+        };
+        match WordFilter::load(path, mode) {
+            Ok(filter) if !filter.is_empty() => {
+                info!(words = filter.len(), path = %path.display(), "loaded the filter");
+                Arc::new(filter)
+            }
+            Ok(_) => {
+                error!(path = %path.display(), "the filter has no words");
+                std::process::exit(1);
+            }
+            Err(err) => {
+                error!(path = %path.display(), %err, "could not read the filter");
+                std::process::exit(1);
+            }
+        }
+    });
+    let config = Arc::new(config);
+    let (server, lobby) = Server::new(
+        config,
+        sentence,
+        dictionary,
+        filter,
+        passages,
+        Arc::new(room_rules),
+    );
+    if let Some(listener) = http_listener {
+        tokio::spawn(http::serve(listener, lobby.sentence, lobby.presence));
+    }
+    serve(listener, server, async {
+        let _ = tokio::signal::ctrl_c().await;
+    })
+    .await;
+}
+
+/// What every connection shares, set up once at startup
+#[derive(Clone)]
+struct Server {
+    config: Arc<Config>,
+    rooms: SharedRooms,
+    rng: SharedRng,
+    dictionary: Option<Arc<Dictionary>>,
+    filter: Option<Arc<WordFilter>>,
+    passages: Option<Arc<Passages>>,
+    room_rules: Arc<RoomRules>,
+    limiter: Option<Arc<Mutex<ConnectionLimiter>>>,
+}
+
+impl Server {
+    /// Opens the lobby around `sentence`, handing it back for the HTTP page
+    fn new(
+        config: Arc<Config>,
+        sentence: SharedStore,
+        dictionary: Option<Arc<Dictionary>>,
+        filter: Option<Arc<WordFilter>>,
+        passages: Option<Arc<Passages>>,
+        room_rules: Arc<RoomRules>,
+    ) -> (Server, Room) {
+        let rng = rng::new(config.seed);
+        // The lobby is always open, other rooms come and go with their players
+        let rooms: SharedRooms = Arc::new(Mutex::new(Rooms::default()));
+        let (lobby_tx, _rx) = broadcast::channel::<ServerMessage>(100);
+        let lobby = new_room(
+            LOBBY,
+            sentence,
+            lobby_tx.downgrade(),
+            &config,
+            room_rules.resolve(LOBBY, &config.rules()),
+            &rng,
+            passages.as_ref(),
+        );
+        rooms.lock().unwrap().open(lobby.clone(), lobby_tx);
+        let limiter = config.conn_rate_limit.map(|max| {
+            Arc::new(Mutex::new(ConnectionLimiter::new(
+                max,
+                config.conn_rate_window,
+            )))
+        });
+        let server = Server {
+            config,
+            rooms,
+            rng,
+            dictionary,
+            filter,
+            passages,
+            room_rules,
+            limiter,
+        };
+        (server, lobby)
+    }
+}
+
+/// Takes connections until `shutdown` resolves, then says goodbye to
+/// everyone still connected
+async fn serve(listener: TcpListener, server: Server, shutdown: impl Future<Output = ()>) {
+    let mut connections = JoinSet::new();
+    tokio::pin!(shutdown);
+    let mut next_author: AuthorId = 0;
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    warn!(%err, "could not accept a connection");
+                    tokio::time::sleep(http::ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
 
-    let sentence = Arc::new(Mutex::new(String::new()));
-    let (tx, _rx) = broadcast::channel(100);
+        // Forget about connections that already finished
+        while connections.try_join_next().is_some() {}
 
-    while let Ok((stream, _)) = listener.accept().await {
-        let sentence = Arc::clone(&sentence);
-        let tx = tx.clone();
-        let mut rx = tx.subscribe();
+        let author = next_author;
+        next_author += 1;
+        let span = tracing::info_span!("client", id = author, %peer, room = tracing::field::Empty);
+        connections.spawn(handle_connection(stream, peer, author, server.clone()).instrument(span));
+    }
 
-        tokio::spawn(async move {
-            let ws_stream = accept_async(stream).await.unwrap();
-            let (mut write, mut read) = ws_stream.split();
+    let config = &server.config;
+    let rooms = server.rooms.lock().unwrap().close();
+    if !config.shutdown_grace.is_zero() {
+        let secs = config.shutdown_grace.as_secs();
+        info!("Shutting down in {secs}s...");
+        for (_, tx) in &rooms {
+            let _ = tx.send(ServerMessage::Shutdown { in_secs: secs });
+        }
+        tokio::time::sleep(config.shutdown_grace).await;
+    }
 
-            // Send current sentence to new client
-            {
-                let current = sentence.lock().unwrap().clone();
-                let _ = write.send(current.clone().into()).await;
+    // The session is over, keep the finished work around in the log
+    if let Some(path) = &config.save_file {
+        if let Some((lobby, _)) = rooms.iter().find(|(room, _)| room.name == LOBBY) {
+            let finished = lobby.sentence.lock().unwrap().current();
+            if let Err(err) = persist::save(path, &finished).await {
+                error!(path = %path.display(), %err, "could not save the sentence");
             }
+        }
+    }
+    for (room, _) in &rooms {
+        let finished = room.sentence.lock().unwrap().current();
+        if let Some(work) = completed_work(&finished, config.append_on_reset.as_deref()) {
+            info!(room = room.name, "Completed sentence: {work}");
+        }
+    }
 
-            loop {
-                tokio::select! {
-                    Some(Ok(msg)) = read.next() => {
-                        let msg_text = msg.to_text().unwrap_or("").trim();
-                        if !msg_text.is_empty() {
-                            let mut s = sentence.lock().unwrap();
-                            if !s.is_empty() {
-                                s.push(' ');
+    // Closing the channels tells every connection to say goodbye
+    info!("Shutting down");
+    drop(rooms);
+    let _ = tokio::time::timeout(Duration::from_secs(5), async {
+        while connections.join_next().await.is_some() {}
+    })
+    .await;
+}
+
+/// One client, from the WebSocket handshake until it's gone
+async fn handle_connection(stream: TcpStream, peer: SocketAddr, author: AuthorId, server: Server) {
+    let Server {
+        config,
+        rooms,
+        rng,
+        dictionary,
+        filter,
+        passages,
+        room_rules,
+        limiter,
+    } = server;
+    let mut resume = None;
+    let mut room_name = None;
+    let mut forwarded = None;
+    // The error type is fixed by tungstenite
+    #[allow(clippy::result_large_err)]
+    let read_request = |req: &Request, resp: Response| {
+        let query = req.uri().query();
+        resume = query.and_then(Resume::from_query);
+        room_name = query.and_then(room_from_query).map(str::to_lowercase);
+        if config.trust_proxy {
+            forwarded = forwarded_for(req);
+        }
+        Ok(resp)
+    };
+    let ws_config = WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE_BYTES),
+        max_frame_size: Some(MAX_MESSAGE_BYTES),
+        ..Default::default()
+    };
+    let accepting = accept_hdr_async_with_config(stream, read_request, Some(ws_config));
+    let Ok(ws_stream) = accepting.await else {
+        return;
+    };
+
+    // Behind a proxy the peer is the proxy itself, the header has the client
+    let ip = forwarded.unwrap_or(peer.ip());
+    if let Some(limiter) = limiter {
+        let allowed = limiter.lock().unwrap().allow(ip, Instant::now());
+        if !allowed {
+            warn!(%ip, "rejecting connection: rate limit");
+            reject(ws_stream, CloseReason::TooManyConnections).await;
+            return;
+        }
+    }
+    let room_name = room_name.unwrap_or_else(|| LOBBY.to_string());
+    if !room::valid_name(&room_name) {
+        warn!(%ip, "rejecting connection: invalid room name");
+        reject(ws_stream, CloseReason::BadRoomName).await;
+        return;
+    }
+    let joined_room = rooms.lock().unwrap().join(&room_name, |tx| {
+        let mut store = MemoryStore::default();
+        start_sentence(&mut store, &config);
+        new_room(
+            &room_name,
+            store::shared(store),
+            tx,
+            &config,
+            room_rules.resolve(&room_name, &config.rules()),
+            &rng,
+            passages.as_ref(),
+        )
+    });
+    let room = match joined_room {
+        Ok(room) => room,
+        Err(reason) => {
+            warn!(%ip, reason = reason.reason(), "rejecting connection");
+            reject(ws_stream, reason).await;
+            return;
+        }
+    };
+    // Leaves the room however the connection ends
+    let _membership = Membership::new(Arc::clone(&rooms), &room.name);
+    tracing::Span::current().record("room", room.name.as_str());
+    let Room {
+        session,
+        sentence,
+        presence,
+        leaderboard,
+        roster,
+        round,
+        turns,
+        race,
+        rules,
+        tx,
+        ..
+    } = room.clone();
+    info!(%ip, "client connected");
+    let (mut write, mut read) = ws_stream.split();
+
+    // Greet the client, then send it the current sentence
+    let hello = ServerMessage::Hello {
+        protocol: PROTOCOL_VERSION,
+        session: session.clone(),
+    };
+    let _ = write.send(hello.encode().into()).await;
+    let version = ServerMessage::ServerVersion(env!("CARGO_PKG_VERSION").to_string());
+    let _ = write.send(version.encode().into()).await;
+    if let Some(max_words) = rules.max_words {
+        let _ = write
+            .send(ServerMessage::MaxWords(max_words).encode().into())
+            .await;
+    }
+    if rules.separator != DEFAULT_SEPARATOR {
+        let separator = ServerMessage::Separator(rules.separator.clone());
+        let _ = write.send(separator.encode().into()).await;
+    }
+    if config.require_join {
+        let _ = write
+            .send(ServerMessage::JoinRequired.encode().into())
+            .await;
+    }
+
+    // Snapshot and subscribe under the same lock, so every update the
+    // client receives afterwards is newer than its initial sentence
+    let (sync, authors, mut rx) = {
+        let s = sentence.lock().unwrap();
+        let Some(tx) = tx.upgrade() else {
+            return;
+        };
+        // A client resuming this session only needs what it missed
+        let missed = resume
+            .filter(|resume| resume.session == session)
+            .and_then(|resume| s.words_since(resume.version));
+        let sync = match missed {
+            Some(words) => ServerMessage::Append {
+                version: s.version(),
+                text: words.join(" "),
+            },
+            None => sentence_of(s.as_ref()),
+        };
+        (sync, authors_of(s.as_ref()), tx.subscribe())
+    };
+    let _ = write.send(sync.encode().into()).await;
+    let _ = write.send(authors.encode().into()).await;
+    let board = leaderboard.lock().unwrap().message(Instant::now());
+    let _ = write.send(board.encode().into()).await;
+    let scoreboard = roster.lock().unwrap().scoreboard();
+    let _ = write.send(scoreboard.encode().into()).await;
+    if let Some(round) = &round {
+        let state = round.lock().unwrap().message(Instant::now());
+        let _ = write.send(state.encode().into()).await;
+    }
+    // Not in the rotation yet, so never our turn
+    if let Some(turns) = &turns {
+        let turn = ServerMessage::Turn(turns.lock().unwrap().state_for(author));
+        let _ = write.send(turn.encode().into()).await;
+    }
+    if let Some(race) = &race {
+        let (state, standings) = {
+            let race = race.lock().unwrap();
+            let now = Instant::now();
+            (race.message(now), race.standings(now))
+        };
+        let _ = write.send(state.encode().into()).await;
+        let _ = write.send(standings.encode().into()).await;
+    }
+    // Newest sentence echoed straight to this client, broadcasts up to
+    // it are already known here and skipped
+    let mut echoed_version = 0;
+
+    // Without --require-join everyone types from the start
+    let mut joined = !config.require_join;
+    let mut word_limiter = config
+        .max_words_per_second
+        .map(|rate| WordLimiter::new(rate, Instant::now()));
+    // Until the client introduces itself
+    let mut username = format!("Guest-{author}");
+    update_presence(&presence, &tx, |presence| presence.connect(joined));
+    update_roster(&roster, &tx, |roster| {
+        roster.connect(author, &username, joined);
+        true
+    });
+    if joined {
+        update_turns(&turns, &tx, |turns| turns.join(author, &username));
+        update_race(&race, &tx, |race| {
+            race.join(author, &username);
+            true
+        });
+    }
+    // Anything the client sends counts, pongs to our pings included
+    let mut last_seen = Instant::now();
+    let mut ping = tokio::time::interval(config.ping_interval);
+    ping.reset();
+    // Only polled with --idle-timeout, so the fallback never fires
+    let idle_timeout = config.idle_timeout.unwrap_or(config.ping_timeout);
+    let idle = tokio::time::sleep(idle_timeout);
+    tokio::pin!(idle);
+
+    loop {
+        tokio::select! {
+            _ = ping.tick() => {
+                // Gone without a goodbye, e.g. a laptop lid closed behind NAT
+                if last_seen.elapsed() > config.ping_timeout {
+                    warn!("no pong in time, dropping the connection");
+                    break;
+                }
+                if write.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+
+            _ = &mut idle, if joined && config.idle_timeout.is_some() => {
+                info!("idle, dropping the connection");
+                let frame = close_frame(&CloseReason::Idle(idle_timeout.as_secs()));
+                let _ = write.send(Message::Close(Some(frame))).await;
+                break;
+            }
+
+            msg = read.next() => {
+                let msg = match msg {
+                    Some(Ok(msg)) => {
+                        last_seen = Instant::now();
+                        msg
+                    }
+                    Some(Err(err)) => {
+                        warn!(%err, "connection dropped");
+                        break;
+                    }
+                    // Client went away
+                    None => break,
+                };
+                // Every submitted word gets an ack or nack, in order
+                let msg_text = match &msg {
+                    Message::Text(text) => text.trim(),
+                    Message::Binary(_) => {
+                        let nack = ServerMessage::Nack("Words must be sent as text".to_string());
+                        let _ = write.send(nack.encode().into()).await;
+                        continue;
+                    }
+                    // Raw frames only show up when writing, reads are
+                    // always whole messages. Pings are answered for us.
+                    Message::Frame(_)
+                    | Message::Ping(_)
+                    | Message::Pong(_)
+                    | Message::Close(_) => continue,
+                };
+                let mut echo = None;
+                let msg = match ClientMessage::decode(msg_text) {
+                    Some(ClientMessage::SubmitWord(word)) => {
+                        ClientMessage::SubmitWord(word.trim().to_string())
+                    }
+                    Some(msg) => msg,
+                    None => {
+                        let nack = ServerMessage::Nack("Not a message this server knows".to_string());
+                        let _ = write.send(nack.encode().into()).await;
+                        continue;
+                    }
+                };
+                // Speed reports come on their own, they don't show anyone's there
+                if matches!(
+                    msg,
+                    ClientMessage::SubmitWord(_)
+                        | ClientMessage::Chat(_)
+                        | ClientMessage::Join
+                        | ClientMessage::Race(_)
+                ) {
+                    idle.as_mut().reset(tokio::time::Instant::now() + idle_timeout);
+                }
+                let reply = match msg {
+                    ClientMessage::Join if joined => {
+                        ServerMessage::Notice("You are already typing".to_string())
+                    }
+                    ClientMessage::Join => {
+                        joined = true;
+                        update_presence(&presence, &tx, Presence::join);
+                        update_roster(&roster, &tx, |roster| {
+                            roster.join(author);
+                            true
+                        });
+                        update_turns(&turns, &tx, |turns| turns.join(author, &username));
+                        update_race(&race, &tx, |race| {
+                            race.join(author, &username);
+                            true
+                        });
+                        info!("joined as a typist");
+                        ServerMessage::Notice("You joined as a typist".to_string())
+                    }
+                    // The roster checks the name is free and takes it in one go
+                    ClientMessage::Name(name)
+                        if valid_name(&name)
+                            && update_roster(&roster, &tx, |roster| roster.rename(author, &name)) =>
+                    {
+                        info!(name, "named");
+                        update_turns(&turns, &tx, |turns| turns.rename(author, &name));
+                        update_race(&race, &tx, |race| {
+                            race.rename(author, &name);
+                            true
+                        });
+                        username = name;
+                        ServerMessage::Notice(format!("Playing as {username}"))
+                    }
+                    ClientMessage::Name(name) if valid_name(&name) => ServerMessage::Notice(format!(
+                        "Someone here already goes by {name}, still playing as {username}"
+                    )),
+                    ClientMessage::Name(_) => ServerMessage::Notice(format!(
+                        "Names need 1 to {MAX_NAME_CHARS} characters and no spaces, still playing as {username}"
+                    )),
+                    ClientMessage::Race(chars) => {
+                        // Progress only moves the standings, it gets no answer
+                        if joined {
+                            update_race(&race, &tx, |race| {
+                                race.progress(author, chars, Instant::now())
+                            });
+                        }
+                        continue;
+                    }
+                    ClientMessage::SubmitWord(_) if race.is_some() => {
+                        ServerMessage::Nack("Words aren't taken during races".to_string())
+                    }
+                    ClientMessage::SubmitWord(_) if !joined => {
+                        ServerMessage::Nack("Join before typing".to_string())
+                    }
+                    ClientMessage::SubmitWord(_)
+                        if turns
+                            .as_ref()
+                            .is_some_and(|t| !t.lock().unwrap().is_turn(author)) =>
+                    {
+                        ServerMessage::Nack("Not your turn".to_string())
+                    }
+                    ClientMessage::Wpm(wpm) => {
+                        // Speed reports only update the board, they get no answer
+                        if joined {
+                            update_leaderboard(&leaderboard, &tx, |board| {
+                                board.report(author, &username, wpm, Instant::now());
+                                true
+                            });
+                            update_roster(&roster, &tx, |roster| roster.report(author, wpm));
+                        }
+                        continue;
+                    }
+                    ClientMessage::Chat(text) if text.chars().count() > MAX_CHAT_CHARS => {
+                        ServerMessage::Notice(format!("Chat messages are limited to {MAX_CHAT_CHARS} characters"))
+                    }
+                    ClientMessage::Chat(text)
+                        if filter.as_ref().is_some_and(|f| f.rejects(&text)) =>
+                    {
+                        ServerMessage::Notice("Keep the chat clean".to_string())
+                    }
+                    ClientMessage::Chat(text) => {
+                        // Everyone, the sender included, sees it through the broadcast
+                        let text = filter
+                            .as_ref()
+                            .and_then(|f| f.mask(&text))
+                            .unwrap_or_else(|| {
+                                text.split_whitespace().collect::<Vec<_>>().join(" ")
+                            });
+                        if !text.is_empty() {
+                            info!(%username, text, "chat");
+                            let Some(tx) = tx.upgrade() else {
+                                break;
+                            };
+                            let _ = tx.send(ServerMessage::Chat {
+                                name: username.clone(),
+                                text,
+                            });
+                        }
+                        continue;
+                    }
+                    ClientMessage::Clear if !config.allow_clear => {
+                        ServerMessage::Notice("Clearing is turned off on this server".to_string())
+                    }
+                    ClientMessage::Clear if !joined => {
+                        ServerMessage::Notice("Join before clearing the sentence".to_string())
+                    }
+                    ClientMessage::Clear => {
+                        let mut store = sentence.lock().unwrap();
+                        info!(%username, "cleared the sentence");
+                        reset_sentence(store.as_mut(), &config);
+                        let Some(tx) = tx.upgrade() else {
+                            break;
+                        };
+                        broadcast_sentence(store.as_ref(), &tx);
+                        ServerMessage::Notice("You cleared the sentence".to_string())
+                    }
+                    ClientMessage::Retract => {
+                        let mut store = sentence.lock().unwrap();
+                        let removed = store.remove_author(author);
+                        info!(removed, "took back words");
+                        roster.lock().unwrap().take_back(author, removed);
+                        if removed > 0 {
+                            let Some(tx) = tx.upgrade() else {
+                                break;
+                            };
+                            broadcast_sentence(store.as_ref(), &tx);
+                        }
+                        ServerMessage::Notice(format!("Removed {removed} of your words"))
+                    }
+                    ClientMessage::Undo => {
+                        let mut store = sentence.lock().unwrap();
+                        // Nothing of ours left, nothing to say
+                        let Some(word) = store.remove_last(author) else {
+                            continue;
+                        };
+                        info!(word, "took back a word");
+                        roster.lock().unwrap().take_back(author, 1);
+                        let Some(tx) = tx.upgrade() else {
+                            break;
+                        };
+                        broadcast_sentence(store.as_ref(), &tx);
+                        ServerMessage::Notice(format!("Took back \"{word}\""))
+                    }
+                    ClientMessage::SubmitWord(word) if word.is_empty() => {
+                        ServerMessage::Nack("Empty word".to_string())
+                    }
+                    ClientMessage::SubmitWord(word)
+                        if config.max_word_chars.is_some_and(|max| {
+                            word.split_whitespace().any(|w| w.chars().count() > max)
+                        }) =>
+                    {
+                        ServerMessage::Nack(format!(
+                            "Words are limited to {} characters",
+                            config.max_word_chars.unwrap_or_default()
+                        ))
+                    }
+                    ClientMessage::SubmitWord(_)
+                        if word_limiter
+                            .as_mut()
+                            .is_some_and(|limiter| !limiter.allow(Instant::now())) =>
+                    {
+                        let retry_after = word_limiter.as_ref().map(WordLimiter::retry_after);
+                        debug!(?retry_after, "word throttled");
+                        // The nack keeps acks in order, the cooldown pauses the client
+                        let nack = ServerMessage::Nack("Too fast".to_string());
+                        let _ = write.send(nack.encode().into()).await;
+                        ServerMessage::Cooldown {
+                            retry_after_ms: retry_after.unwrap_or_default().as_millis() as u64,
+                            reason: "Slow down, too many words at once".to_string(),
+                        }
+                    }
+                    ClientMessage::SubmitWord(word)
+                        if dictionary.as_ref().is_some_and(|d| !d.accepts(&word)) =>
+                    {
+                        ServerMessage::Nack("Not in the dictionary".to_string())
+                    }
+                    ClientMessage::SubmitWord(word)
+                        if filter.as_ref().is_some_and(|f| f.rejects(&word)) =>
+                    {
+                        ServerMessage::Nack("Keep it clean".to_string())
+                    }
+                    ClientMessage::SubmitWord(_)
+                        if round
+                            .as_ref()
+                            .is_some_and(|r| r.lock().unwrap().is_closed(Instant::now())) =>
+                    {
+                        ServerMessage::Nack("The round is over".to_string())
+                    }
+                    ClientMessage::SubmitWord(word) => {
+                        // Appending and broadcasting under the lock keeps every
+                        // client's view in server arrival order
+                        let masked = filter.as_ref().and_then(|f| f.mask(&word));
+                        let word = rules.case.apply(masked.as_deref().unwrap_or(&word));
+                        let word = word.as_str();
+                        let mut store = sentence.lock().unwrap();
+                        store.append(word, author, &username);
+                        roster.lock().unwrap().credit(author, 1);
+                        if let Some(keep) = config.keep_words {
+                            store.trim_front(keep);
+                        }
+                        debug!(word, version = store.version(), "word received");
+                        let Some(tx) = tx.upgrade() else {
+                            break;
+                        };
+                        broadcast_sentence(store.as_ref(), &tx);
+                        if let Some(round) = &round {
+                            let mut round = round.lock().unwrap();
+                            let now = Instant::now();
+                            if round.start(now) {
+                                info!(%username, "round started");
+                                let _ = tx.send(round.message(now));
                             }
-                            s.push_str(msg_text);
-                            println!("Updated sentence: {s}" );
-                            let _ = tx.send(s.clone());
                         }
+                        if let Some(turns) = &turns {
+                            let mut turns = turns.lock().unwrap();
+                            turns.advance();
+                            let _ = tx.send(turns.message());
+                        }
+
+                        // Word budget used up, start over
+                        if rules.max_words.is_some_and(|max| store.word_count() >= max) {
+                            reset_sentence(store.as_mut(), &config);
+                            broadcast_sentence(store.as_ref(), &tx);
+                        }
+                        if config.echo_to_sender {
+                            echo = Some(sentence_of(store.as_ref()));
+                        }
+                        ServerMessage::Ack
+                    }
+                };
+                if let Some(echo) = echo {
+                    if let ServerMessage::SentenceUpdate { version, .. } = echo {
+                        echoed_version = version;
                     }
+                    let _ = write.send(echo.encode().into()).await;
+                }
+                let _ = write.send(reply.encode().into()).await;
+            }
 
-                    Ok(msg) = rx.recv() => {
-                        let _ = write.send(msg.into()).await;
+            msg = rx.recv() => {
+                match msg {
+                    Ok(ServerMessage::SentenceUpdate { version, .. })
+                        if version <= echoed_version => {}
+                    // Whose turn it is depends on who's asking
+                    Ok(ServerMessage::Turn(_)) => {
+                        let state = turns
+                            .as_ref()
+                            .map_or(TurnState::Nobody, |turns| {
+                                turns.lock().unwrap().state_for(author)
+                            });
+                        let turn = ServerMessage::Turn(state);
+                        let _ = write.send(turn.encode().into()).await;
+                    }
+                    Ok(msg) => {
+                        let _ = write.send(msg.encode().into()).await;
+                    }
+                    // Missed some updates, catch up on all of the room at once
+                    Err(RecvError::Lagged(missed)) => {
+                        debug!(missed, "fell behind, resending the room");
+                        for msg in room_state(&room, author) {
+                            let _ = write.send(msg.encode().into()).await;
+                        }
+                    }
+                    // Server is shutting down, close the client cleanly
+                    Err(RecvError::Closed) => {
+                        let frame = close_frame(&CloseReason::ShuttingDown);
+                        let _ = write.send(Message::Close(Some(frame))).await;
+                        break;
                     }
                 }
             }
+        }
+    }
+
+    info!(%username, "client disconnected");
+    update_presence(&presence, &tx, |presence| presence.leave(joined));
+    update_leaderboard(&leaderboard, &tx, |board| board.remove(author));
+    update_turns(&turns, &tx, |turns| turns.leave(author));
+    update_race(&race, &tx, |race| race.leave(author));
+    update_roster(&roster, &tx, |roster| {
+        roster.leave(author);
+        true
+    });
+}
+
+/// The finished sentence with the configured suffix, `{time}` in the suffix
+/// becomes the unix time. `None` when there's nothing worth keeping.
+fn completed_work(sentence: &str, suffix: Option<&str>) -> Option<String> {
+    if sentence.is_empty() {
+        return None;
+    }
+    let Some(suffix) = suffix else {
+        return Some(sentence.to_string());
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    Some(format!(
+        "{sentence} {}",
+        suffix.replace("{time}", &now.to_string())
+    ))
+}
+
+/// Sends everyone the sentence, followed by who wrote each of its words
+fn broadcast_sentence(store: &dyn SentenceStore, tx: &broadcast::Sender<ServerMessage>) {
+    let _ = tx.send(sentence_of(store));
+    let _ = tx.send(authors_of(store));
+}
+
+fn sentence_of(store: &dyn SentenceStore) -> ServerMessage {
+    ServerMessage::SentenceUpdate {
+        version: store.version(),
+        sentence: store.current(),
+        author: store.credits().pop().filter(|name| !name.is_empty()),
+    }
+}
+
+fn authors_of(store: &dyn SentenceStore) -> ServerMessage {
+    ServerMessage::Authors {
+        version: store.version(),
+        names: store
+            .credits()
+            .into_iter()
+            .map(|name| (!name.is_empty()).then_some(name))
+            .collect(),
+    }
+}
+
+/// Everything `author` is shown of the room, for a connection that fell
+/// too far behind the broadcasts to piece it together
+fn room_state(room: &Room, author: AuthorId) -> Vec<ServerMessage> {
+    let now = Instant::now();
+    let mut state = {
+        let store = room.sentence.lock().unwrap();
+        vec![sentence_of(store.as_ref()), authors_of(store.as_ref())]
+    };
+    state.push(room.presence.lock().unwrap().message());
+    {
+        let roster = room.roster.lock().unwrap();
+        state.push(roster.message());
+        state.push(roster.scoreboard());
+    }
+    state.push(room.leaderboard.lock().unwrap().message(now));
+    if let Some(round) = &room.round {
+        state.push(round.lock().unwrap().message(now));
+    }
+    if let Some(turns) = &room.turns {
+        state.push(ServerMessage::Turn(turns.lock().unwrap().state_for(author)));
+    }
+    if let Some(race) = &room.race {
+        let race = race.lock().unwrap();
+        state.push(race.message(now));
+        state.push(race.standings(now));
+    }
+    state
+}
+
+/// Changes the presence counts and tells everyone. Broadcasting under the
+/// lock keeps the counts clients see in order.
+fn update_presence(
+    presence: &SharedPresence,
+    tx: &WeakSender<ServerMessage>,
+    change: impl FnOnce(&mut Presence),
+) {
+    let mut presence = presence.lock().unwrap();
+    change(&mut presence);
+    if let Some(tx) = tx.upgrade() {
+        let _ = tx.send(presence.message());
+    }
+}
+
+/// Changes the `--turns` rotation, if we play turns, and tells everyone whose
+/// turn it is now
+fn update_turns(
+    turns: &Option<SharedTurns>,
+    tx: &WeakSender<ServerMessage>,
+    change: impl FnOnce(&mut Turns),
+) {
+    let Some(turns) = turns else {
+        return;
+    };
+    let mut turns = turns.lock().unwrap();
+    change(&mut turns);
+    if let Some(tx) = tx.upgrade() {
+        let _ = tx.send(turns.message());
+    }
+}
+
+/// Changes the roster and, if `change` says it did, tells everyone who's in
+/// the room now. Returns what `change` said.
+fn update_roster(
+    roster: &SharedRoster,
+    tx: &WeakSender<ServerMessage>,
+    change: impl FnOnce(&mut Roster) -> bool,
+) -> bool {
+    let mut roster = roster.lock().unwrap();
+    if !change(&mut roster) {
+        return false;
+    }
+    if let Some(tx) = tx.upgrade() {
+        let _ = tx.send(roster.message());
+    }
+    true
+}
+
+/// Changes the `--race`, if we race, and tells everyone the standings if
+/// `change` says they moved
+fn update_race(
+    race: &Option<SharedRace>,
+    tx: &WeakSender<ServerMessage>,
+    change: impl FnOnce(&mut Race) -> bool,
+) {
+    let Some(race) = race else {
+        return;
+    };
+    let mut race = race.lock().unwrap();
+    if !change(&mut race) {
+        return;
+    }
+    if let Some(tx) = tx.upgrade() {
+        let _ = tx.send(race.standings(Instant::now()));
+    }
+}
+
+/// Changes the leaderboard and, if `change` says it did, tells everyone
+fn update_leaderboard(
+    leaderboard: &SharedLeaderboard,
+    tx: &WeakSender<ServerMessage>,
+    change: impl FnOnce(&mut Leaderboard) -> bool,
+) {
+    let mut leaderboard = leaderboard.lock().unwrap();
+    if !change(&mut leaderboard) {
+        return;
+    }
+    if let Some(tx) = tx.upgrade() {
+        let _ = tx.send(leaderboard.message(Instant::now()));
+    }
+}
+
+/// Ends rounds when their time is up and starts the next one after the
+/// intermission, telling everyone the time left every second and who wrote
+/// what once it's over
+async fn run_rounds(
+    round: SharedRound,
+    sentence: SharedStore,
+    roster: SharedRoster,
+    tx: WeakSender<ServerMessage>,
+    config: Arc<Config>,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        let Some(tx) = tx.upgrade() else {
+            return;
+        };
+        // Same lock order as a connection adding a word: sentence, then round
+        let mut store = sentence.lock().unwrap();
+        let mut round = round.lock().unwrap();
+        let now = Instant::now();
+        match round.tick(now) {
+            Tick::Ended => {
+                info!(words = store.word_count(), "round over");
+                let results = roster
+                    .lock()
+                    .unwrap()
+                    .results(store.current(), &store.credits());
+                let _ = tx.send(results);
+            }
+            Tick::Restarted => {
+                reset_sentence(store.as_mut(), &config);
+                broadcast_sentence(store.as_ref(), &tx);
+                info!("new round, waiting for the first word");
+            }
+            // Nothing changes while nobody has typed yet
+            Tick::Unchanged if round.is_waiting() => continue,
+            Tick::Unchanged => {}
+        }
+        let _ = tx.send(round.message(now));
+    }
+}
+
+/// Tells everyone who wrote the most every few seconds, and who's still
+/// fast enough for the leaderboard, whenever either changed
+async fn run_scoreboard(
+    roster: SharedRoster,
+    leaderboard: SharedLeaderboard,
+    tx: WeakSender<ServerMessage>,
+) {
+    let mut interval = tokio::time::interval(SCOREBOARD_INTERVAL);
+    let mut last_scores = None;
+    let mut last_board = None;
+    loop {
+        interval.tick().await;
+        let Some(tx) = tx.upgrade() else {
+            return;
+        };
+        let scores = roster.lock().unwrap().scoreboard();
+        if last_scores.as_ref() != Some(&scores) {
+            let _ = tx.send(scores.clone());
+            last_scores = Some(scores);
+        }
+        // Drops players whose reports stopped even if nobody else reports
+        let board = leaderboard.lock().unwrap().message(Instant::now());
+        if last_board.as_ref() != Some(&board) {
+            let _ = tx.send(board.clone());
+            last_board = Some(board);
+        }
+    }
+}
+
+/// Moves races through their countdown, the race itself and the results,
+/// telling everyone whenever the race changes phase
+async fn run_races(race: SharedRace, tx: WeakSender<ServerMessage>) {
+    let mut interval = tokio::time::interval(Duration::from_millis(250));
+    loop {
+        interval.tick().await;
+        let Some(tx) = tx.upgrade() else {
+            return;
+        };
+        let mut race = race.lock().unwrap();
+        let now = Instant::now();
+        let Some(state) = race.tick(now) else {
+            continue;
+        };
+        debug!(?state, "race moved on");
+        let _ = tx.send(state);
+        let _ = tx.send(race.standings(now));
+    }
+}
+
+/// Archives the finished sentence to the log and starts a fresh one
+fn reset_sentence(store: &mut dyn SentenceStore, config: &Config) {
+    if let Some(work) = completed_work(&store.current(), config.append_on_reset.as_deref()) {
+        info!("Completed sentence: {work}");
+    }
+    store.reset();
+    start_sentence(store, config);
+}
+
+/// Puts the `--prompt` at the start of an empty sentence. Nobody wrote it,
+/// so nobody can take it back.
+fn start_sentence(store: &mut dyn SentenceStore, config: &Config) {
+    if let Some(prompt) = &config.prompt {
+        store.append(prompt, NO_AUTHOR, "");
+    }
+}
+
+/// A room around `sentence`, with its round clock running if rounds are on
+/// and its races if `passages` are given
+fn new_room(
+    name: &str,
+    sentence: SharedStore,
+    tx: WeakSender<ServerMessage>,
+    config: &Arc<Config>,
+    rules: Rules,
+    rng: &SharedRng,
+    passages: Option<&Arc<Passages>>,
+) -> Room {
+    let roster = Arc::new(Mutex::new(Roster::default()));
+    let leaderboard = Arc::new(Mutex::new(Leaderboard::default()));
+    let scoreboard = run_scoreboard(Arc::clone(&roster), Arc::clone(&leaderboard), tx.clone());
+    tokio::spawn(scoreboard.instrument(tracing::info_span!("scoreboard", room = name)));
+    let round = config
+        .round_length
+        .map(|length| Arc::new(Mutex::new(Round::new(length))));
+    if let Some(round) = &round {
+        let rounds = run_rounds(
+            Arc::clone(round),
+            Arc::clone(&sentence),
+            Arc::clone(&roster),
+            tx.clone(),
+            Arc::clone(config),
+        );
+        tokio::spawn(rounds.instrument(tracing::info_span!("rounds", room = name)));
+    }
+    let race = passages
+        .map(|passages| Arc::new(Mutex::new(Race::new(Arc::clone(passages), Arc::clone(rng)))));
+    if let Some(race) = &race {
+        let races = run_races(Arc::clone(race), tx.clone());
+        tokio::spawn(races.instrument(tracing::info_span!("races", room = name)));
+    }
+    Room {
+        name: name.to_string(),
+        session: format!("{:016x}", rng.lock().unwrap().gen::<u64>()),
+        sentence,
+        presence: Arc::new(Mutex::new(Presence::default())),
+        leaderboard,
+        roster,
+        round,
+        turns: config.turns.then(|| Arc::new(Mutex::new(Turns::default()))),
+        race,
+        rules,
+        tx,
+    }
+}
+
+/// Closes a freshly accepted connection again, telling the client why
+async fn reject(mut ws_stream: WebSocketStream<TcpStream>, reason: CloseReason) {
+    let _ = ws_stream
+        .send(Message::Close(Some(close_frame(&reason))))
+        .await;
+}
+
+fn close_frame(reason: &CloseReason) -> CloseFrame<'static> {
+    CloseFrame {
+        code: CloseCode::from(reason.code()),
+        reason: reason.reason().into(),
+    }
+}
+
+/// The client address as seen by the proxy in front of us. The proxy appends
+/// the address it got the request from, so the last entry is the only one we
+/// can trust, anything before it came from the client.
+fn forwarded_for(req: &Request) -> Option<IpAddr> {
+    let header = req.headers().get("x-forwarded-for")?.to_str().ok()?;
+    header.rsplit(',').next()?.trim().parse().ok()
+}
+
+fn parse_args(args: &[String]) -> Result<Config, String> {
+    let mut config = Config::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--host" => {
+                let host = flag_value(&mut iter, arg)?;
+                config.host = host
+                    .parse()
+                    .map_err(|_| format!("{arg} needs an IP address like 0.0.0.0, got {host}"))?;
+            }
+            "--port" => {
+                config.port = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|port| *port > 0)
+                    .ok_or_else(|| format!("{arg} needs a port number"))?;
+            }
+            "--shutdown-grace" => {
+                let secs = flag_value(&mut iter, arg)?
+                    .parse()
+                    .map_err(|_| format!("Invalid number of seconds for {arg}"))?;
+                config.shutdown_grace = Duration::from_secs(secs);
+            }
+            "--conn-rate-limit" => {
+                let max = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|max| *max > 0)
+                    .ok_or_else(|| format!("{arg} needs a positive number"))?;
+                config.conn_rate_limit = Some(max);
+            }
+            "--append-on-reset" => {
+                let suffix = flag_value(&mut iter, arg)?.trim();
+                if suffix.is_empty() || suffix.chars().count() > MAX_SUFFIX_CHARS {
+                    return Err(format!(
+                        "{arg} needs between 1 and {MAX_SUFFIX_CHARS} characters"
+                    ));
+                }
+                config.append_on_reset = Some(suffix.to_string());
+            }
+            "--prompt" => config.prompt = Some(flag_value(&mut iter, arg)?.to_string()),
+            "--prompt-file" => {
+                let path = flag_value(&mut iter, arg)?;
+                let prompt = fs::read_to_string(path)
+                    .map_err(|err| format!("Could not read {path}: {err}"))?;
+                config.prompt = Some(prompt);
+            }
+            "--max-words" => {
+                let max = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|max| *max > 0)
+                    .ok_or_else(|| format!("{arg} needs a positive number"))?;
+                config.max_words = Some(max);
+            }
+            "--separator" => {
+                let separator = flag_value(&mut iter, arg)?;
+                if !rules::valid_separator(separator) {
+                    return Err(format!(
+                        "{arg} takes up to {MAX_SEPARATOR_CHARS} characters on one line"
+                    ));
+                }
+                config.separator = separator.to_string();
+            }
+            "--case" => {
+                let case = flag_value(&mut iter, arg)?;
+                config.case = Case::parse(case)
+                    .ok_or_else(|| format!("{arg} needs keep, lower or upper, got {case}"))?;
+            }
+            "--rooms-file" => config.rooms_file = Some(flag_value(&mut iter, arg)?.into()),
+            "--keep-words" => {
+                let keep = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|keep| *keep > 0)
+                    .ok_or_else(|| format!("{arg} needs a positive number"))?;
+                config.keep_words = Some(keep);
+            }
+            "--trust-proxy" => config.trust_proxy = true,
+            "--echo-to-sender" => config.echo_to_sender = true,
+            "--require-join" => config.require_join = true,
+            "--turns" => config.turns = true,
+            "--allow-clear" => config.allow_clear = true,
+            "--save-file" => config.save_file = Some(flag_value(&mut iter, arg)?.into()),
+            "--round-seconds" => {
+                let secs = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or_else(|| format!("{arg} needs a positive number of seconds"))?;
+                config.round_length = Some(Duration::from_secs(secs));
+            }
+            "--log-level" => {
+                let level = flag_value(&mut iter, arg)?;
+                config.log_level = Some(level.parse().map_err(|_| {
+                    format!("{arg} needs one of error, warn, info, debug or trace, got {level}")
+                })?);
+            }
+            "--ping-interval" | "--ping-timeout" => {
+                let secs = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or_else(|| format!("{arg} needs a positive number of seconds"))?;
+                if arg == "--ping-interval" {
+                    config.ping_interval = Duration::from_secs(secs);
+                } else {
+                    config.ping_timeout = Duration::from_secs(secs);
+                }
+            }
+            "--idle-timeout" => {
+                let secs = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or_else(|| format!("{arg} needs a positive number of seconds"))?;
+                config.idle_timeout = Some(Duration::from_secs(secs));
+            }
+            "--max-word-chars" => {
+                let max: usize = flag_value(&mut iter, arg)?
+                    .parse()
+                    .map_err(|_| format!("{arg} needs a number, 0 turns the limit off"))?;
+                config.max_word_chars = (max > 0).then_some(max);
+            }
+            "--max-words-per-second" => {
+                let rate: u32 = flag_value(&mut iter, arg)?
+                    .parse()
+                    .map_err(|_| format!("{arg} needs a number, 0 turns the limit off"))?;
+                config.max_words_per_second = (rate > 0).then_some(rate);
+            }
+            "--dictionary" => config.dictionary = Some(flag_value(&mut iter, arg)?.into()),
+            "--filter" => {
+                let mode = flag_value(&mut iter, arg)?;
+                config.filter = Some(
+                    FilterMode::parse(mode)
+                        .ok_or_else(|| format!("{arg} needs mask or reject, got {mode}"))?,
+                );
+            }
+            "--race" => config.race = true,
+            "--race-file" => config.race_file = Some(flag_value(&mut iter, arg)?.into()),
+            "--race-words" => {
+                let count = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or_else(|| format!("{arg} needs a positive number"))?;
+                config.race_words = Some(count);
+            }
+            "--filter-file" => config.filter_file = Some(flag_value(&mut iter, arg)?.into()),
+            "--http-port" => {
+                let port = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|port| *port > 0)
+                    .ok_or_else(|| format!("{arg} needs a port number"))?;
+                config.http_port = Some(port);
+            }
+            "--seed" => {
+                let seed = flag_value(&mut iter, arg)?
+                    .parse()
+                    .map_err(|_| format!("{arg} needs a number"))?;
+                config.seed = Some(seed);
+            }
+            "--conn-rate-window" => {
+                let secs = flag_value(&mut iter, arg)?
+                    .parse()
+                    .ok()
+                    .filter(|secs| *secs > 0)
+                    .ok_or_else(|| format!("{arg} needs a positive number of seconds"))?;
+                config.conn_rate_window = Duration::from_secs(secs);
+            }
+            other => return Err(format!("Unknown argument: {other}")),
+        }
+    }
+
+    // A list of your own is of no use unfiltered
+    if config.filter_file.is_some() && config.filter.is_none() {
+        config.filter = Some(FilterMode::Mask);
+    }
+
+    // Racing from a file of your own or on generated words needs no --race of its own
+    if config.race_file.is_some() || config.race_words.is_some() {
+        config.race = true;
+    }
+    if config.race_file.is_some() && config.race_words.is_some() {
+        return Err("--race-file and --race-words can't be combined".to_string());
+    }
+    if config.http_port == Some(config.port) {
+        return Err(format!(
+            "--http-port needs a port other than the game's {}",
+            config.port
+        ));
+    }
+
+    if config.race && (config.turns || config.round_length.is_some()) {
+        return Err("--race can't be combined with --turns or --round-seconds".to_string());
+    }
+
+    // Normalized like a submitted word, and cut short so that it leaves room
+    // for at least one word under --max-words and fits --keep-words
+    if let Some(prompt) = config.prompt.take() {
+        let limit = [config.max_words.map(|max| max - 1), config.keep_words]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(usize::MAX);
+        if prompt.trim().is_empty() {
+            return Err("The prompt needs at least one word".to_string());
+        }
+        if limit == 0 {
+            return Err("A prompt needs --max-words of at least 2".to_string());
+        }
+        let words: Vec<_> = prompt.split_whitespace().take(limit).collect();
+        config.prompt = Some(words.join(" "));
+    }
+
+    Ok(config)
+}
+
+fn flag_value<'a>(
+    iter: &mut impl Iterator<Item = &'a String>,
+    flag: &str,
+) -> Result<&'a str, String> {
+    iter.next()
+        .map(String::as_str)
+        .ok_or_else(|| format!("Missing value for {flag}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{sync::oneshot, task::JoinHandle};
+    use tokio_tungstenite::{connect_async, MaybeTlsStream};
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    /// What main loads from files for a server, besides its config
+    #[derive(Default)]
+    struct Extras {
+        dictionary: Option<Dictionary>,
+        filter: Option<WordFilter>,
+        room_rules: RoomRules,
+    }
+
+    /// A server on a free port, until the sender is used or dropped
+    async fn start(config: Config) -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+        start_with(config, Extras::default()).await
+    }
+
+    async fn start_with(
+        config: Config,
+        extras: Extras,
+    ) -> (SocketAddr, oneshot::Sender<()>, JoinHandle<()>) {
+        // Listens like main does, on a free port instead of `DEFAULT_PORT`
+        let listener = TcpListener::bind(SocketAddr::new(config.host, 0))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let config = Arc::new(config);
+        let sentence = store::shared(MemoryStore::default());
+        start_sentence(sentence.lock().unwrap().as_mut(), &config);
+        let (server, _lobby) = Server::new(
+            config,
+            sentence,
+            extras.dictionary.map(Arc::new),
+            extras.filter.map(Arc::new),
+            None,
+            Arc::new(extras.room_rules),
+        );
+        let (stop, stopped) = oneshot::channel();
+        let serving = tokio::spawn(serve(listener, server, async {
+            let _ = stopped.await;
+        }));
+        (addr, stop, serving)
+    }
+
+    /// A file with `contents` for the server to load, gone with the machine's temp files
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chaos-type-tests-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    async fn connect(addr: SocketAddr) -> Client {
+        connect_async(format!("ws://{addr}")).await.unwrap().0
+    }
+
+    async fn connect_to_room(addr: SocketAddr, room: &str) -> Client {
+        connect_async(format!("ws://{addr}/?room={room}"))
+            .await
+            .unwrap()
+            .0
+    }
+
+    /// The next frame from the server that isn't a ping
+    async fn next(client: &mut Client) -> Option<Message> {
+        let reading = async {
+            loop {
+                match client.next().await {
+                    Some(Ok(Message::Ping(_))) => continue,
+                    Some(Ok(msg)) => return Some(msg),
+                    _ => return None,
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), reading)
+            .await
+            .expect("the server went quiet")
+    }
+
+    /// Reads until the server closes the connection, and how it did
+    async fn closed(client: &mut Client) -> CloseFrame<'static> {
+        loop {
+            match next(client).await {
+                Some(Message::Close(frame)) => return frame.expect("close frame without a reason"),
+                Some(_) => continue,
+                None => panic!("closed without a close frame"),
+            }
+        }
+    }
+
+    /// Reads until `pick` likes a message
+    async fn until<T>(client: &mut Client, mut pick: impl FnMut(ServerMessage) -> Option<T>) -> T {
+        loop {
+            let Some(Message::Text(text)) = next(client).await else {
+                panic!("connection ended while waiting");
+            };
+            if let Some(found) = ServerMessage::decode(&text).and_then(&mut pick) {
+                return found;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn shutdown_closes_connected_clients() {
+        let (addr, stop, serving) = start(Config::default()).await;
+        let mut clients = vec![connect(addr).await, connect(addr).await];
+        for client in &mut clients {
+            until(client, |msg| {
+                matches!(msg, ServerMessage::SentenceUpdate { .. }).then_some(())
+            })
+            .await;
+        }
+
+        stop.send(()).unwrap();
+        for client in &mut clients {
+            let close = closed(client).await;
+            assert_eq!(close.code, CloseCode::Away);
+            assert_eq!(close.reason, "Server shutting down");
+        }
+        tokio::time::timeout(Duration::from_secs(5), serving)
+            .await
+            .expect("the server didn't stop")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn room_state_catches_up_on_the_sentence_and_turn() {
+        let config = Arc::new(Config {
+            turns: true,
+            ..Config::default()
         });
+        let sentence = store::shared(MemoryStore::default());
+        sentence.lock().unwrap().append("hello", 1, "ada");
+        let (tx, _rx) = broadcast::channel(1);
+        let room = new_room(
+            LOBBY,
+            sentence,
+            tx.downgrade(),
+            &config,
+            config.rules(),
+            &rng::new(Some(1)),
+            None,
+        );
+        room.turns.as_ref().unwrap().lock().unwrap().join(1, "ada");
+
+        let state = room_state(&room, 1);
+        assert!(state.contains(&ServerMessage::SentenceUpdate {
+            version: 1,
+            sentence: "hello".to_string(),
+            author: Some("ada".to_string()),
+        }));
+        assert!(state.contains(&ServerMessage::Turn(TurnState::Yours)));
+    }
+
+    #[tokio::test]
+    async fn rooms_play_by_their_own_rules() {
+        let path = temp_file(
+            "rooms",
+            "[shout]\nmax_words = 5\nseparator = \" / \"\ncase = upper\n",
+        );
+        let extras = Extras {
+            room_rules: RoomRules::load(&path).unwrap(),
+            ..Extras::default()
+        };
+        let config = Config {
+            max_words: Some(50),
+            ..Config::default()
+        };
+        let (addr, _stop, _serving) = start_with(config, extras).await;
+
+        let mut shout = connect_to_room(addr, "shout").await;
+        let max_words = until(&mut shout, |msg| match msg {
+            ServerMessage::MaxWords(max) => Some(max),
+            _ => None,
+        })
+        .await;
+        assert_eq!(max_words, 5);
+        let separator = until(&mut shout, |msg| match msg {
+            ServerMessage::Separator(separator) => Some(separator),
+            _ => None,
+        })
+        .await;
+        assert_eq!(separator, " / ");
+        shout
+            .send(
+                ClientMessage::SubmitWord("hello".to_string())
+                    .encode()
+                    .into(),
+            )
+            .await
+            .unwrap();
+        let sentence = until(&mut shout, |msg| match msg {
+            ServerMessage::SentenceUpdate { sentence: text, .. } if !text.is_empty() => Some(text),
+            _ => None,
+        })
+        .await;
+        assert_eq!(sentence, "HELLO");
+
+        let mut lobby = connect(addr).await;
+        let max_words = until(&mut lobby, |msg| match msg {
+            ServerMessage::MaxWords(max) => Some(max),
+            ServerMessage::Separator(_) => panic!("the lobby keeps the default separator"),
+            _ => None,
+        })
+        .await;
+        assert_eq!(max_words, 50);
+        lobby
+            .send(
+                ClientMessage::SubmitWord("Hello".to_string())
+                    .encode()
+                    .into(),
+            )
+            .await
+            .unwrap();
+        let sentence = until(&mut lobby, |msg| match msg {
+            ServerMessage::SentenceUpdate { sentence: text, .. } if !text.is_empty() => Some(text),
+            _ => None,
+        })
+        .await;
+        assert_eq!(sentence, "Hello");
+    }
+
+    #[test]
+    fn close_frames_carry_their_reason() {
+        for (reason, code, text) in [
+            (
+                CloseReason::ShuttingDown,
+                CloseCode::Away,
+                "Server shutting down",
+            ),
+            (
+                CloseReason::TooManyConnections,
+                CloseCode::Policy,
+                "Too many connections, try again later",
+            ),
+            (
+                CloseReason::TooManyRooms,
+                CloseCode::Policy,
+                "Too many rooms, try again later",
+            ),
+            (
+                CloseReason::BadRoomName,
+                CloseCode::Policy,
+                "Room names are letters, digits, - and _",
+            ),
+            (
+                CloseReason::Idle(90),
+                CloseCode::Policy,
+                "Disconnected after 90s without typing",
+            ),
+        ] {
+            let frame = close_frame(&reason);
+            assert_eq!(frame.code, code, "{reason:?}");
+            assert_eq!(frame.reason, text, "{reason:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn bad_room_names_are_closed_with_the_reason() {
+        let (addr, _stop, _serving) = start(Config::default()).await;
+        let mut client = connect_to_room(addr, "no%20spaces").await;
+        let frame = closed(&mut client).await;
+        assert_eq!(
+            CloseReason::decode(frame.code.into(), &frame.reason),
+            Some(CloseReason::BadRoomName)
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_clients_agree_on_every_word() {
+        const CLIENTS: usize = 4;
+        const WORDS: usize = 25;
+        let config = Config {
+            max_words_per_second: None,
+            ..Config::default()
+        };
+        let (addr, _stop, _serving) = start(config).await;
+        let mut typing = Vec::new();
+        for client in 0..CLIENTS {
+            typing.push(tokio::spawn(async move {
+                let mut ws = connect(addr).await;
+                for word in 0..WORDS {
+                    let word = format!("c{client}w{word}");
+                    ws.send(ClientMessage::SubmitWord(word.clone()).encode().into())
+                        .await
+                        .unwrap();
+                }
+                let mut acks = 0;
+                until(&mut ws, |msg| {
+                    acks += usize::from(msg == ServerMessage::Ack);
+                    (acks == WORDS).then_some(())
+                })
+                .await;
+                // Every client is done once the others' words arrived too
+                until(&mut ws, |msg| match msg {
+                    ServerMessage::SentenceUpdate { sentence: text, .. }
+                        if text.split_whitespace().count() == CLIENTS * WORDS =>
+                    {
+                        Some(text)
+                    }
+                    _ => None,
+                })
+                .await
+            }));
+        }
+        let mut sentences = Vec::new();
+        for client in typing {
+            sentences.push(client.await.unwrap());
+        }
+
+        assert!(sentences.iter().all(|sentence| *sentence == sentences[0]));
+        let words: Vec<&str> = sentences[0].split_whitespace().collect();
+        let unique: std::collections::HashSet<_> = words.iter().collect();
+        assert_eq!(unique.len(), CLIENTS * WORDS, "a word was duplicated");
+        for client in 0..CLIENTS {
+            // Each client's words land in the order it sent them
+            let prefix = format!("c{client}w");
+            let own: Vec<&str> = words
+                .iter()
+                .copied()
+                .filter(|word| word.starts_with(&prefix))
+                .collect();
+            let sent: Vec<String> = (0..WORDS).map(|word| format!("{prefix}{word}")).collect();
+            assert_eq!(own, sent);
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_floods_are_closed_with_the_reason() {
+        let config = Config {
+            conn_rate_limit: Some(2),
+            ..Config::default()
+        };
+        let (addr, _stop, _serving) = start(config).await;
+        let _first = connect(addr).await;
+        let _second = connect(addr).await;
+        let mut third = connect(addr).await;
+        let frame = closed(&mut third).await;
+        assert_eq!(
+            CloseReason::decode(frame.code.into(), &frame.reason),
+            Some(CloseReason::TooManyConnections)
+        );
+    }
+
+    /// `text` as a fragmented message, split into frames at `splits`
+    fn fragments(text: &str, splits: &[usize]) -> Vec<Message> {
+        use tokio_tungstenite::tungstenite::protocol::frame::{
+            coding::{Data, OpCode},
+            Frame,
+        };
+        let mut bounds = vec![0];
+        bounds.extend_from_slice(splits);
+        bounds.push(text.len());
+        bounds
+            .windows(2)
+            .enumerate()
+            .map(|(i, bound)| {
+                let opcode = if i == 0 { Data::Text } else { Data::Continue };
+                let data = text.as_bytes()[bound[0]..bound[1]].to_vec();
+                let last = bound[1] == text.len();
+                Message::Frame(Frame::message(data, OpCode::Data(opcode), last))
+            })
+            .collect()
+    }
+
+    async fn next_sentence(client: &mut Client) -> String {
+        until(client, |msg| match msg {
+            ServerMessage::SentenceUpdate { sentence: text, .. } => Some(text),
+            _ => None,
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn fragmented_words_are_added_once_whole() {
+        let (addr, _stop, _serving) = start(Config::default()).await;
+        let mut client = connect(addr).await;
+        next_sentence(&mut client).await;
+        let frame = ClientMessage::SubmitWord("héllo".to_string()).encode();
+        let word = frame.find('h').unwrap();
+        // Split inside a multibyte character too
+        for frame in fragments(&frame, &[word + 1, word + 2, word + 4]) {
+            client.send(frame).await.unwrap();
+        }
+        let ack = until(&mut client, |msg| match msg {
+            ServerMessage::Ack | ServerMessage::Nack(_) => Some(msg),
+            _ => None,
+        })
+        .await;
+        assert_eq!(ack, ServerMessage::Ack);
+        let mut other = connect(addr).await;
+        assert_eq!(next_sentence(&mut other).await, "héllo");
+
+        // Too big once put together, none of it may end up in the sentence
+        let huge = "a".repeat(MAX_MESSAGE_BYTES + 1);
+        for frame in fragments(&huge, &[MAX_MESSAGE_BYTES / 2]) {
+            let _ = client.send(frame).await;
+        }
+        while next(&mut client).await.is_some() {}
+        let mut other = connect(addr).await;
+        assert_eq!(next_sentence(&mut other).await, "héllo");
+    }
+
+    #[tokio::test]
+    async fn echoes_keep_every_client_on_one_sentence() {
+        const WORDS: usize = 20;
+        let config = Config {
+            echo_to_sender: true,
+            max_words_per_second: None,
+            ..Config::default()
+        };
+        let (addr, _stop, _serving) = start(config).await;
+        let mut typing = Vec::new();
+        for client in 0..2 {
+            typing.push(tokio::spawn(async move {
+                let mut ws = connect(addr).await;
+                let words: Vec<String> =
+                    (0..WORDS).map(|word| format!("c{client}w{word}")).collect();
+                for word in &words {
+                    ws.send(ClientMessage::SubmitWord(word.to_string()).encode().into())
+                        .await
+                        .unwrap();
+                }
+                let mut acked = 0;
+                let mut last: Option<(u64, String)> = None;
+                loop {
+                    let Some(Message::Text(text)) = next(&mut ws).await else {
+                        panic!("connection ended while waiting");
+                    };
+                    match ServerMessage::decode(&text) {
+                        Some(ServerMessage::SentenceUpdate {
+                            version,
+                            sentence: text,
+                            ..
+                        }) => {
+                            if let Some((seen, seen_text)) = &last {
+                                assert!(version > *seen, "sentence went back to {version}");
+                                assert!(text.starts_with(seen_text.as_str()));
+                            }
+                            last = Some((version, text));
+                        }
+                        Some(ServerMessage::Ack) => {
+                            // The echo comes first, so the word is already shown
+                            let shown = last.as_ref().map_or("", |(_, text)| text.as_str());
+                            assert!(shown.split_whitespace().any(|w| w == words[acked]));
+                            acked += 1;
+                        }
+                        _ => {}
+                    }
+                    let shown = last
+                        .as_ref()
+                        .map_or(0, |(_, text)| text.split_whitespace().count());
+                    if acked == WORDS && shown == 2 * WORDS {
+                        break;
+                    }
+                }
+                last.unwrap().1
+            }));
+        }
+        let first = typing.remove(0).await.unwrap();
+        let second = typing.remove(0).await.unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn servers_on_free_ports_take_connections() {
+        let mut hosts = vec![Config::default().host];
+        // Not every machine has IPv6 loopback
+        if std::net::TcpListener::bind("[::1]:0").is_ok() {
+            hosts.push("::1".parse().unwrap());
+        }
+        for host in hosts {
+            let config = Config {
+                host,
+                ..Config::default()
+            };
+            let (addr, _stop, _serving) = start(config).await;
+            assert_ne!(addr.port(), 0);
+            let (mut client, _) = connect_async(format!("ws://{addr}")).await.unwrap();
+            let hello = until(&mut client, |msg| match msg {
+                ServerMessage::Hello { protocol, .. } => Some(protocol),
+                _ => None,
+            })
+            .await;
+            assert_eq!(hello, PROTOCOL_VERSION);
+        }
+    }
+
+    #[tokio::test]
+    async fn two_clients_both_see_the_combined_sentence() {
+        let (addr, _stop, _serving) = start(Config::default()).await;
+        let mut clients = [connect(addr).await, connect(addr).await];
+        for client in &mut clients {
+            next_sentence(client).await;
+        }
+
+        for (from, word) in [(0, "  once "), (1, "upon\n\ta"), (0, "   "), (1, "time")] {
+            // One at a time, so the order is known
+            send_word(&mut clients[from], word).await;
+        }
+
+        for client in &mut clients {
+            let sentence = until(client, |msg| match msg {
+                ServerMessage::SentenceUpdate { sentence: text, .. } if text.ends_with("time") => {
+                    Some(text)
+                }
+                _ => None,
+            })
+            .await;
+            assert_eq!(sentence, "once upon a time");
+        }
+    }
+
+    /// Sends `word` and waits for the server to answer it
+    async fn send_word(client: &mut Client, word: &str) -> ServerMessage {
+        client
+            .send(ClientMessage::SubmitWord(word.to_string()).encode().into())
+            .await
+            .unwrap();
+        until(client, |msg| match msg {
+            ServerMessage::Ack | ServerMessage::Nack(_) => Some(msg),
+            _ => None,
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn long_sentences_keep_the_newest_words() {
+        let config = Config {
+            keep_words: Some(3),
+            max_words_per_second: None,
+            ..Config::default()
+        };
+        let (addr, _stop, _serving) = start(config).await;
+        let mut client = connect(addr).await;
+        next_sentence(&mut client).await;
+        for word in ["w0", "w1", "w2", "w3 w4", "w5"] {
+            assert_eq!(send_word(&mut client, word).await, ServerMessage::Ack);
+        }
+        let mut watcher = connect(addr).await;
+        assert_eq!(next_sentence(&mut watcher).await, "w3 w4 w5");
+
+        // Every broadcast along the way stayed within the limit
+        loop {
+            let sentence = next_sentence(&mut client).await;
+            assert!(sentence.split_whitespace().count() <= 3, "{sentence}");
+            if sentence == "w3 w4 w5" {
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn only_dictionary_words_are_added() {
+        let path = temp_file("dictionary", "once upon a time\ndon't\n");
+        let extras = Extras {
+            dictionary: Some(Dictionary::load(&path).unwrap()),
+            ..Extras::default()
+        };
+        let (addr, _stop, _serving) = start_with(Config::default(), extras).await;
+        let mut client = connect(addr).await;
+        next_sentence(&mut client).await;
+
+        assert_eq!(send_word(&mut client, "Once").await, ServerMessage::Ack);
+        assert_eq!(
+            send_word(&mut client, "upun").await,
+            ServerMessage::Nack("Not in the dictionary".to_string())
+        );
+        assert_eq!(
+            send_word(&mut client, "upon a tyme").await,
+            ServerMessage::Nack("Not in the dictionary".to_string())
+        );
+        assert_eq!(send_word(&mut client, "upon,").await, ServerMessage::Ack);
+        assert_eq!(send_word(&mut client, "don't!").await, ServerMessage::Ack);
+
+        let mut watcher = connect(addr).await;
+        assert_eq!(next_sentence(&mut watcher).await, "Once upon, don't!");
+    }
+
+    #[tokio::test]
+    async fn banned_words_are_masked_or_rejected() {
+        for (mode, banned) in [
+            (FilterMode::Mask, ServerMessage::Ack),
+            (
+                FilterMode::Reject,
+                ServerMessage::Nack("Keep it clean".to_string()),
+            ),
+        ] {
+            let extras = Extras {
+                filter: Some(WordFilter::built_in(mode)),
+                ..Extras::default()
+            };
+            let (addr, _stop, _serving) = start_with(Config::default(), extras).await;
+            let mut client = connect(addr).await;
+            next_sentence(&mut client).await;
+
+            assert_eq!(send_word(&mut client, "hello").await, ServerMessage::Ack);
+            assert_eq!(send_word(&mut client, "Damn!").await, banned);
+
+            let mut watcher = connect(addr).await;
+            let expected = match mode {
+                FilterMode::Mask => "hello ****!",
+                FilterMode::Reject => "hello",
+            };
+            assert_eq!(next_sentence(&mut watcher).await, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn bursts_are_rate_limited() {
+        let config = Config {
+            max_words_per_second: Some(3),
+            ..Config::default()
+        };
+        let (addr, _stop, _serving) = start(config).await;
+        let mut client = connect(addr).await;
+        next_sentence(&mut client).await;
+        for word in 0..10 {
+            let word = format!("w{word}");
+            client
+                .send(ClientMessage::SubmitWord(word.clone()).encode().into())
+                .await
+                .unwrap();
+        }
+        let (mut acks, mut nacks, mut cooldowns) = (0, 0, 0);
+        // A throttled word gets its nack, then the cooldown
+        while acks + cooldowns < 10 {
+            match until(&mut client, Some).await {
+                ServerMessage::Ack => acks += 1,
+                ServerMessage::Nack(reason) => {
+                    assert_eq!(reason, "Too fast");
+                    nacks += 1;
+                }
+                ServerMessage::Cooldown { retry_after_ms, .. } => {
+                    assert!(retry_after_ms > 0 && retry_after_ms <= 1000);
+                    cooldowns += 1;
+                }
+                _ => {}
+            }
+        }
+        // Sent far quicker than a word refills, so the burst is all that got in
+        assert_eq!(acks, 3);
+        assert_eq!(nacks, 7);
+        assert_eq!(cooldowns, 7);
+
+        // The connection stays up and the throttled words never made it in
+        let mut watcher = connect(addr).await;
+        assert_eq!(next_sentence(&mut watcher).await, "w0 w1 w2");
+    }
+
+    #[test]
+    fn port_flags_need_two_different_ports() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(parse_args(&[]).unwrap().port, DEFAULT_PORT);
+        let config = parse_args(&args(&["--port", "8080", "--http-port", "9001"])).unwrap();
+        assert_eq!((config.port, config.http_port), (8080, Some(9001)));
+        assert!(parse_args(&args(&["--port", "0"])).is_err());
+        assert!(parse_args(&args(&["--http-port", "9001"])).is_err());
+        assert!(parse_args(&args(&["--http-port", "8080", "--port", "8080"])).is_err());
     }
 }
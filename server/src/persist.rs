@@ -0,0 +1,61 @@
+//! `--save-file`: keeps the sentence on disk across restarts.
+//!
+//! The file holds the plain sentence. It's read once at startup and written
+//! back by a background task at most once per `SAVE_INTERVAL`, so a burst of
+//! words costs one write.
+
+use crate::store::{SharedStore, NO_AUTHOR};
+use std::{
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tracing::error;
+
+/// Longest a change waits before it's written
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Fills the store from `path`, returns how many words were restored. A
+/// missing file is a fresh start, not an error.
+pub fn load(path: &Path, store: &SharedStore) -> io::Result<usize> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    let mut store = store.lock().unwrap();
+    let mut restored = 0;
+    for word in text.split_whitespace() {
+        store.append(word, NO_AUTHOR, "");
+        restored += 1;
+    }
+    Ok(restored)
+}
+
+/// Writes the sentence whenever it changed since the last write, forever
+pub async fn save_changes(path: PathBuf, store: SharedStore) {
+    let mut saved_version = store.lock().unwrap().version();
+    let mut interval = tokio::time::interval(SAVE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let (version, text) = {
+            let store = store.lock().unwrap();
+            (store.version(), store.current())
+        };
+        if version == saved_version {
+            continue;
+        }
+        match save(&path, &text).await {
+            Ok(()) => saved_version = version,
+            Err(err) => error!(path = %path.display(), %err, "could not save the sentence"),
+        }
+    }
+}
+
+/// Replaces the file in one go, a crash mid-write never leaves half a sentence
+pub async fn save(path: &Path, text: &str) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    tokio::fs::write(&tmp, text).await?;
+    tokio::fs::rename(&tmp, path).await
+}
@@ -0,0 +1,49 @@
+//! Who is connected and who of them is actually typing.
+//!
+//! With `--require-join` new connections only watch until they ask to join,
+//! otherwise every connection counts as a typist straight away.
+
+use protocol::ServerMessage;
+use std::sync::{Arc, Mutex};
+
+/// Presence shared by every connection task
+pub type SharedPresence = Arc<Mutex<Presence>>;
+
+#[derive(Debug, Default)]
+pub struct Presence {
+    connected: usize,
+    active: usize,
+}
+
+impl Presence {
+    pub fn connect(&mut self, active: bool) {
+        self.connected += 1;
+        if active {
+            self.active += 1;
+        }
+    }
+
+    /// A watching connection became a typist
+    pub fn join(&mut self) {
+        self.active += 1;
+    }
+
+    pub fn leave(&mut self, active: bool) {
+        self.connected = self.connected.saturating_sub(1);
+        if active {
+            self.active = self.active.saturating_sub(1);
+        }
+    }
+
+    /// Connected clients and how many of them type
+    pub fn counts(&self) -> (usize, usize) {
+        (self.connected, self.active)
+    }
+
+    pub fn message(&self) -> ServerMessage {
+        ServerMessage::Presence {
+            connected: self.connected,
+            active: self.active,
+        }
+    }
+}
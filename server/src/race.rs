@@ -0,0 +1,290 @@
+//! `--race`: everyone types the same passage, the first to finish wins.
+//!
+//! A race counts down once someone is around to type, then runs until every
+//! racer is done or the time is up. The standings stay up for a while before
+//! the next passage comes along. Clients report how much of the passage they
+//! typed correctly, the server ranks them and works out their speed.
+
+use crate::{rng::SharedRng, store::AuthorId};
+use protocol::{
+    wordlist::{WordList, WordOptions},
+    RaceState, Racer, ServerMessage,
+};
+use rand::Rng;
+use std::{
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Race shared by every connection task of a room
+pub type SharedRace = Arc<Mutex<Race>>;
+
+/// Time to get ready once the passage is shown
+const COUNTDOWN: Duration = Duration::from_secs(5);
+
+/// How long the final standings stay up
+const RESULTS: Duration = Duration::from_secs(10);
+
+/// Time allowed per character of the passage, enough to finish at 20 WPM
+const SECS_PER_CHAR: f64 = 0.6;
+
+/// Shortest time limit, for very short passages
+const MIN_TIME_LIMIT: Duration = Duration::from_secs(30);
+
+/// Used unless `--race-file` is given
+const BUILT_IN: &[&str] = &[
+    "The quick brown fox jumps over the lazy dog, then naps in the warm afternoon sun while the farmer looks on.",
+    "Every great journey begins with a single step, but it is the hundreds of small steps after it that get you there.",
+    "She packed her bag with a map, a flashlight, two apples and a notebook, then set off before anyone else was awake.",
+    "Typing fast is less about moving your fingers quickly and more about never having to stop and fix a mistake.",
+    "The old lighthouse keeper climbed the spiral stairs each night to light the lamp, even after the ships stopped coming.",
+    "A good cup of coffee, a quiet room and a clear plan can turn an ordinary morning into the most productive hours of the week.",
+];
+
+/// What races are typed on
+#[derive(Debug)]
+pub enum Passages {
+    /// Picked from, whitespace collapsed
+    Fixed(Vec<String>),
+    /// Made up fresh for every race, `--race-words`
+    Generated(WordList, WordOptions),
+}
+
+impl Passages {
+    pub fn built_in() -> Passages {
+        Passages::Fixed(BUILT_IN.iter().map(|passage| passage.to_string()).collect())
+    }
+
+    /// `count` words from the built-in word list, written like sentences
+    pub fn generated(count: usize) -> Passages {
+        let options = WordOptions {
+            count,
+            capitalize: true,
+            punctuation: true,
+            numbers: false,
+        };
+        Passages::Generated(WordList::built_in(), options)
+    }
+
+    /// One passage per line, blank lines skipped, for `Passages::Fixed`
+    pub fn load(path: &Path) -> io::Result<Vec<String>> {
+        let text = std::fs::read_to_string(path)?;
+        let passages = text
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|passage| !passage.is_empty())
+            .collect();
+        Ok(passages)
+    }
+
+    /// A passage for the next race, a different one than `last` if there is one
+    fn next(&self, rng: &mut impl Rng, last: &str) -> String {
+        match self {
+            Passages::Fixed(passages) => {
+                let mut next = &passages[rng.gen_range(0..passages.len())];
+                while passages.len() > 1 && next == last {
+                    next = &passages[rng.gen_range(0..passages.len())];
+                }
+                next.clone()
+            }
+            Passages::Generated(words, options) => words.generate(rng, options).join(" "),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Waiting,
+    Countdown {
+        starts_at: Instant,
+    },
+    Running {
+        started_at: Instant,
+        ends_at: Instant,
+    },
+    /// `took` is how long the race ran, for the speed of those who didn't finish
+    Over {
+        next_at: Instant,
+        took: Duration,
+    },
+}
+
+#[derive(Debug)]
+struct Entry {
+    author: AuthorId,
+    name: String,
+    chars: usize,
+    /// Place and how long it took, once they're done
+    finished: Option<(u32, Duration)>,
+}
+
+#[derive(Debug)]
+pub struct Race {
+    passages: Arc<Passages>,
+    rng: SharedRng,
+    passage: String,
+    phase: Phase,
+    /// In the order they joined
+    racers: Vec<Entry>,
+}
+
+impl Race {
+    pub fn new(passages: Arc<Passages>, rng: SharedRng) -> Race {
+        Race {
+            passages,
+            rng,
+            passage: String::new(),
+            phase: Phase::Waiting,
+            racers: Vec::new(),
+        }
+    }
+
+    /// Adds a typist to the race, mid-race ones start from scratch
+    pub fn join(&mut self, author: AuthorId, name: &str) {
+        self.racers.push(Entry {
+            author,
+            name: name.to_string(),
+            chars: 0,
+            finished: None,
+        });
+    }
+
+    pub fn rename(&mut self, author: AuthorId, name: &str) {
+        if let Some(entry) = self.racers.iter_mut().find(|e| e.author == author) {
+            entry.name = name.to_string();
+        }
+    }
+
+    /// Returns whether they were racing
+    pub fn leave(&mut self, author: AuthorId) -> bool {
+        let before = self.racers.len();
+        self.racers.retain(|entry| entry.author != author);
+        self.racers.len() != before
+    }
+
+    /// Records how far `author` got, returns whether the standings changed.
+    /// Only counts while the race runs, and never goes backwards.
+    pub fn progress(&mut self, author: AuthorId, chars: usize, now: Instant) -> bool {
+        let Phase::Running { started_at, .. } = self.phase else {
+            return false;
+        };
+        let length = self.passage.chars().count();
+        let places_taken = self.racers.iter().filter(|e| e.finished.is_some()).count();
+        let Some(entry) = self.racers.iter_mut().find(|e| e.author == author) else {
+            return false;
+        };
+        let chars = chars.min(length);
+        if entry.finished.is_some() || chars <= entry.chars {
+            return false;
+        }
+        entry.chars = chars;
+        if chars == length {
+            entry.finished = Some((places_taken as u32 + 1, now.duration_since(started_at)));
+        }
+        true
+    }
+
+    /// Moves the race along, returns the new state to tell everyone if it
+    /// changed
+    pub fn tick(&mut self, now: Instant) -> Option<ServerMessage> {
+        let racing = !self.racers.is_empty();
+        self.phase = match self.phase {
+            Phase::Waiting if racing => self.next_race(now),
+            Phase::Countdown { .. } if !racing => Phase::Waiting,
+            Phase::Countdown { starts_at } if now >= starts_at => {
+                let limit =
+                    Duration::from_secs_f64(self.passage.chars().count() as f64 * SECS_PER_CHAR)
+                        .max(MIN_TIME_LIMIT);
+                Phase::Running {
+                    started_at: now,
+                    ends_at: now + limit,
+                }
+            }
+            Phase::Running {
+                started_at,
+                ends_at,
+            } if now >= ends_at || self.racers.iter().all(|e| e.finished.is_some()) => {
+                Phase::Over {
+                    next_at: now + RESULTS,
+                    took: now.duration_since(started_at),
+                }
+            }
+            Phase::Over { next_at, .. } if now >= next_at && racing => self.next_race(now),
+            Phase::Over { next_at, .. } if now >= next_at => Phase::Waiting,
+            _ => return None,
+        };
+        Some(self.message(now))
+    }
+
+    fn next_race(&mut self, now: Instant) -> Phase {
+        let mut rng = self.rng.lock().unwrap();
+        self.passage = self.passages.next(&mut *rng, &self.passage);
+        for entry in &mut self.racers {
+            entry.chars = 0;
+            entry.finished = None;
+        }
+        Phase::Countdown {
+            starts_at: now + COUNTDOWN,
+        }
+    }
+
+    pub fn message(&self, now: Instant) -> ServerMessage {
+        let secs_until =
+            |at: Instant| at.saturating_duration_since(now).as_secs_f64().ceil() as u64;
+        ServerMessage::Race(match self.phase {
+            Phase::Waiting => RaceState::Waiting,
+            Phase::Countdown { starts_at } => RaceState::Countdown {
+                starts_in_secs: secs_until(starts_at),
+                passage: self.passage.clone(),
+            },
+            Phase::Running { ends_at, .. } => RaceState::Running {
+                remaining_secs: secs_until(ends_at),
+                passage: self.passage.clone(),
+            },
+            Phase::Over { next_at, .. } => RaceState::Over {
+                next_in_secs: secs_until(next_at),
+            },
+        })
+    }
+
+    /// Finished racers by place, then everyone else by how far they got
+    pub fn standings(&self, now: Instant) -> ServerMessage {
+        let elapsed = match self.phase {
+            Phase::Running { started_at, .. } => now.duration_since(started_at),
+            Phase::Over { took, .. } => took,
+            _ => Duration::ZERO,
+        };
+        let mut racers: Vec<&Entry> = self.racers.iter().collect();
+        racers.sort_by_key(|entry| {
+            (
+                entry.finished.map_or(u32::MAX, |(place, _)| place),
+                usize::MAX - entry.chars,
+            )
+        });
+        ServerMessage::Racers(
+            racers
+                .into_iter()
+                .map(|entry| {
+                    let time = entry.finished.map_or(elapsed, |(_, time)| time);
+                    Racer {
+                        name: entry.name.clone(),
+                        chars: entry.chars,
+                        wpm: wpm(entry.chars, time),
+                        place: entry.finished.map(|(place, _)| place),
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Words of five characters per minute
+fn wpm(chars: usize, time: Duration) -> u32 {
+    let minutes = time.as_secs_f64() / 60.0;
+    if minutes <= 0.0 {
+        return 0;
+    }
+    (chars as f64 / 5.0 / minutes).round() as u32
+}
@@ -0,0 +1,14 @@
+//! The server's single source of randomness.
+//!
+//! Everything random goes through one [`SharedRng`], made by
+//! [`protocol::rng`], so starting the server with `--seed` makes a run
+//! reproducible.
+
+use rand::rngs::StdRng;
+use std::sync::{Arc, Mutex};
+
+pub type SharedRng = Arc<Mutex<StdRng>>;
+
+pub fn new(seed: Option<u64>) -> SharedRng {
+    Arc::new(Mutex::new(protocol::rng::new(seed)))
+}
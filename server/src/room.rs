@@ -0,0 +1,151 @@
+//! Named rooms, each with its own sentence, players and leaderboard.
+//!
+//! Clients pick a room with `?room=<name>` on the WebSocket URL, and servers
+//! that don't know about rooms just ignore it. A room is made when its first
+//! player arrives and dropped with its last one. The lobby is the exception:
+//! everyone who doesn't name a room shares it, it's always there and it's the
+//! room `--save-file` keeps.
+
+use crate::{
+    leaderboard::SharedLeaderboard, presence::SharedPresence, race::SharedRace,
+    roster::SharedRoster, round::SharedRound, rules::Rules, store::SharedStore, turns::SharedTurns,
+};
+use protocol::{CloseReason, ServerMessage, MAX_ROOM_CHARS};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast::{self, WeakSender};
+
+/// Rooms shared by every connection task
+pub type SharedRooms = Arc<Mutex<Rooms>>;
+
+/// Most rooms open at once, the lobby included
+pub const MAX_ROOMS: usize = 100;
+
+/// What a connection needs of its room
+#[derive(Clone)]
+pub struct Room {
+    pub name: String,
+    /// Made up with the room, so a resume into a room that was dropped and
+    /// made again meanwhile gets a full sync rather than the wrong words
+    pub session: String,
+    pub sentence: SharedStore,
+    pub presence: SharedPresence,
+    pub leaderboard: SharedLeaderboard,
+    pub roster: SharedRoster,
+    pub round: Option<SharedRound>,
+    pub turns: Option<SharedTurns>,
+    pub race: Option<SharedRace>,
+    /// The room's own `--rooms-file` rules, or the server's
+    pub rules: Rules,
+    /// Only `Rooms` holds the strong sender, dropping the room closes the channel
+    pub tx: WeakSender<ServerMessage>,
+}
+
+struct Entry {
+    room: Room,
+    tx: broadcast::Sender<ServerMessage>,
+    /// Connections in the room, it's dropped when the last one leaves
+    members: usize,
+}
+
+#[derive(Default)]
+pub struct Rooms {
+    rooms: HashMap<String, Entry>,
+    /// Shutting down, nobody may join anymore
+    closed: bool,
+}
+
+impl Rooms {
+    /// Adds a room that stays when empty, like the lobby
+    pub fn open(&mut self, room: Room, tx: broadcast::Sender<ServerMessage>) {
+        let entry = Entry {
+            room,
+            tx,
+            // Never drops to zero, so it's never dropped
+            members: 1,
+        };
+        self.rooms.insert(entry.room.name.clone(), entry);
+    }
+
+    /// Counts a connection into the room called `name`, made with `make` if
+    /// it doesn't exist yet. Fails with the reason to tell the client.
+    pub fn join(
+        &mut self,
+        name: &str,
+        make: impl FnOnce(WeakSender<ServerMessage>) -> Room,
+    ) -> Result<Room, CloseReason> {
+        if self.closed {
+            return Err(CloseReason::ShuttingDown);
+        }
+        if let Some(entry) = self.rooms.get_mut(name) {
+            entry.members += 1;
+            return Ok(entry.room.clone());
+        }
+        if self.rooms.len() >= MAX_ROOMS {
+            return Err(CloseReason::TooManyRooms);
+        }
+        let (tx, _rx) = broadcast::channel(100);
+        let room = make(tx.downgrade());
+        self.rooms.insert(
+            name.to_string(),
+            Entry {
+                room: room.clone(),
+                tx,
+                members: 1,
+            },
+        );
+        Ok(room)
+    }
+
+    pub fn leave(&mut self, name: &str) {
+        let Some(entry) = self.rooms.get_mut(name) else {
+            return;
+        };
+        entry.members -= 1;
+        if entry.members == 0 {
+            self.rooms.remove(name);
+        }
+    }
+
+    /// Turns away anyone still joining and hands back every room with its
+    /// sender, dropping those closes the rooms' channels
+    pub fn close(&mut self) -> Vec<(Room, broadcast::Sender<ServerMessage>)> {
+        self.closed = true;
+        self.rooms
+            .drain()
+            .map(|(_, entry)| (entry.room, entry.tx))
+            .collect()
+    }
+}
+
+/// Counts a connection out of its room when dropped, however it ends
+pub struct Membership {
+    rooms: SharedRooms,
+    name: String,
+}
+
+impl Membership {
+    pub fn new(rooms: SharedRooms, name: &str) -> Membership {
+        Membership {
+            rooms,
+            name: name.to_string(),
+        }
+    }
+}
+
+impl Drop for Membership {
+    fn drop(&mut self) {
+        self.rooms.lock().unwrap().leave(&self.name);
+    }
+}
+
+/// Room names go into URLs and log lines, so they're kept to letters, digits,
+/// `-` and `_`. Compared lowercased.
+pub fn valid_name(name: &str) -> bool {
+    (1..=MAX_ROOM_CHARS).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
@@ -0,0 +1,189 @@
+//! Everyone in a room, watchers included, with the speed they last reported
+//! and how many words they wrote since they arrived.
+//!
+//! Unlike the leaderboard nobody drops off for going quiet, players stay
+//! listed until their connection closes. Names are unique within the room,
+//! ignoring case, so clients can tell players apart by name.
+
+use crate::{leaderboard::MAX_WPM, store::AuthorId};
+use protocol::{Player, Score, ServerMessage};
+use std::sync::{Arc, Mutex};
+
+/// Roster shared by every connection task of a room
+pub type SharedRoster = Arc<Mutex<Roster>>;
+
+/// Players ranked on the scoreboard
+const MAX_SCORES: usize = 10;
+
+#[derive(Debug)]
+struct Member {
+    author: AuthorId,
+    player: Player,
+    /// Words added, less the ones taken back
+    words: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Roster {
+    /// In the order they connected
+    players: Vec<Member>,
+}
+
+impl Roster {
+    pub fn connect(&mut self, author: AuthorId, name: &str, typing: bool) {
+        let player = Player {
+            name: name.to_string(),
+            wpm: None,
+            watching: !typing,
+        };
+        self.players.push(Member {
+            author,
+            player,
+            words: 0,
+        });
+    }
+
+    /// A watcher became a typist
+    pub fn join(&mut self, author: AuthorId) {
+        if let Some(player) = self.player(author) {
+            player.watching = false;
+        }
+    }
+
+    /// Returns whether the name was free to take
+    pub fn rename(&mut self, author: AuthorId, name: &str) -> bool {
+        let taken = self
+            .players
+            .iter()
+            .any(|member| member.author != author && member.player.name.eq_ignore_ascii_case(name));
+        if taken {
+            return false;
+        }
+        if let Some(player) = self.player(author) {
+            player.name = name.to_string();
+        }
+        true
+    }
+
+    /// Returns whether the listed speed changed
+    pub fn report(&mut self, author: AuthorId, wpm: u32) -> bool {
+        let Some(player) = self.player(author) else {
+            return false;
+        };
+        let wpm = Some(wpm.min(MAX_WPM));
+        let changed = player.wpm != wpm;
+        player.wpm = wpm;
+        changed
+    }
+
+    pub fn credit(&mut self, author: AuthorId, words: usize) {
+        if let Some(member) = self.member(author) {
+            member.words += words;
+        }
+    }
+
+    pub fn take_back(&mut self, author: AuthorId, words: usize) {
+        if let Some(member) = self.member(author) {
+            member.words = member.words.saturating_sub(words);
+        }
+    }
+
+    pub fn leave(&mut self, author: AuthorId) {
+        self.players.retain(|member| member.author != author);
+    }
+
+    pub fn message(&self) -> ServerMessage {
+        ServerMessage::Players(
+            self.players
+                .iter()
+                .map(|member| member.player.clone())
+                .collect(),
+        )
+    }
+
+    /// Typists with the most words first, the faster one on a tie
+    pub fn scoreboard(&self) -> ServerMessage {
+        let mut ranked: Vec<&Member> = self
+            .players
+            .iter()
+            .filter(|member| !member.player.watching)
+            .collect();
+        ranked.sort_by(|a, b| b.words.cmp(&a.words).then(b.player.wpm.cmp(&a.player.wpm)));
+        ServerMessage::Scoreboard(
+            ranked
+                .into_iter()
+                .take(MAX_SCORES)
+                .map(|member| Score {
+                    name: member.player.name.clone(),
+                    words: member.words,
+                    wpm: member.player.wpm,
+                })
+                .collect(),
+        )
+    }
+
+    /// How the round went for everyone typing, given whose each word of the
+    /// final sentence is. Players who left still count for their words.
+    pub fn results(&self, sentence: String, credits: &[String]) -> ServerMessage {
+        let mut scores: Vec<Score> = self
+            .players
+            .iter()
+            .filter(|member| !member.player.watching)
+            .map(|member| Score {
+                name: member.player.name.clone(),
+                words: 0,
+                wpm: member.player.wpm,
+            })
+            .collect();
+        for name in credits.iter().filter(|name| !name.is_empty()) {
+            match scores.iter_mut().find(|score| score.name == *name) {
+                Some(score) => score.words += 1,
+                None => scores.push(Score {
+                    name: name.clone(),
+                    words: 1,
+                    wpm: None,
+                }),
+            }
+        }
+        scores.sort_by(|a, b| b.words.cmp(&a.words).then(b.wpm.cmp(&a.wpm)));
+        ServerMessage::RoundResults { sentence, scores }
+    }
+
+    fn player(&mut self, author: AuthorId) -> Option<&mut Player> {
+        self.member(author).map(|member| &mut member.player)
+    }
+
+    fn member(&mut self, author: AuthorId) -> Option<&mut Member> {
+        self.players
+            .iter_mut()
+            .find(|member| member.author == author)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(roster: &Roster) -> Vec<String> {
+        let ServerMessage::Players(players) = roster.message() else {
+            unreachable!();
+        };
+        players.into_iter().map(|player| player.name).collect()
+    }
+
+    #[test]
+    fn names_are_unique_in_the_room() {
+        let mut roster = Roster::default();
+        roster.connect(1, "Guest-1", true);
+        roster.connect(2, "Guest-2", true);
+        assert!(roster.rename(1, "Ada"));
+        assert!(!roster.rename(2, "ada"));
+        assert_eq!(names(&roster), ["Ada", "Guest-2"]);
+
+        // Changing the case of our own name is fine
+        assert!(roster.rename(1, "ADA"));
+        roster.leave(1);
+        assert!(roster.rename(2, "Ada"));
+        assert_eq!(names(&roster), ["Ada"]);
+    }
+}
@@ -0,0 +1,111 @@
+//! `--round-seconds`: short timed rounds instead of one endless sentence.
+//!
+//! A round waits for its first word, then runs for the configured time. Once
+//! the clock runs out no words are taken until the intermission is over and
+//! the sentence starts fresh.
+
+use protocol::{RoundState, ServerMessage};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Round shared by every connection task
+pub type SharedRound = Arc<Mutex<Round>>;
+
+/// Break between the end of a round and the fresh sentence of the next one
+pub const INTERMISSION: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    /// Nobody typed yet, the clock starts with the first word
+    Waiting,
+    Running {
+        deadline: Instant,
+    },
+    Over {
+        restart_at: Instant,
+    },
+}
+
+/// What `Round::tick` moved on to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tick {
+    /// Still in the same phase
+    Unchanged,
+    /// The clock ran out
+    Ended,
+    /// The intermission is over, the sentence should start fresh
+    Restarted,
+}
+
+#[derive(Debug)]
+pub struct Round {
+    length: Duration,
+    phase: Phase,
+}
+
+impl Round {
+    pub fn new(length: Duration) -> Round {
+        Round {
+            length,
+            phase: Phase::Waiting,
+        }
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        self.phase == Phase::Waiting
+    }
+
+    /// Whether words are turned away, from the moment the clock runs out
+    pub fn is_closed(&self, now: Instant) -> bool {
+        match self.phase {
+            Phase::Waiting => false,
+            Phase::Running { deadline } => now >= deadline,
+            Phase::Over { .. } => true,
+        }
+    }
+
+    /// Starts the clock if this is the round's first word, returns whether it did
+    pub fn start(&mut self, now: Instant) -> bool {
+        if self.phase != Phase::Waiting {
+            return false;
+        }
+        self.phase = Phase::Running {
+            deadline: now + self.length,
+        };
+        true
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Tick {
+        match self.phase {
+            Phase::Running { deadline } if now >= deadline => {
+                self.phase = Phase::Over {
+                    restart_at: now + INTERMISSION,
+                };
+                Tick::Ended
+            }
+            Phase::Over { restart_at } if now >= restart_at => {
+                self.phase = Phase::Waiting;
+                Tick::Restarted
+            }
+            _ => Tick::Unchanged,
+        }
+    }
+
+    pub fn message(&self, now: Instant) -> ServerMessage {
+        let secs_until =
+            |at: Instant| at.saturating_duration_since(now).as_secs_f64().ceil() as u64;
+        match self.phase {
+            Phase::Waiting => ServerMessage::Round(RoundState::Waiting {
+                length_secs: self.length.as_secs(),
+            }),
+            Phase::Running { deadline } => ServerMessage::Round(RoundState::Running {
+                remaining_secs: secs_until(deadline),
+            }),
+            Phase::Over { restart_at } => ServerMessage::Round(RoundState::Over {
+                next_in_secs: secs_until(restart_at),
+            }),
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! `--rooms-file`: rules a room plays by when they differ from the rest.
+//!
+//! The file has a `[name]` line for every room with rules of its own, followed
+//! by the settings that room overrides, one `key = value` per line:
+//!
+//! ```text
+//! [haiku]
+//! max_words = 17
+//! separator = " / "
+//! case = lower
+//! ```
+//!
+//! `max_words = 0` lifts `--max-words` for the room, the separator is a
+//! quoted JSON string. Whatever a room leaves out, and every room not in the
+//! file, follows the command line. Lines starting with `#` are comments.
+
+use crate::room;
+use std::{collections::HashMap, fs, path::Path};
+
+/// Words are separated by a single space unless a room says otherwise
+pub const DEFAULT_SEPARATOR: &str = " ";
+
+/// Longest separator we take, it goes between every two words
+pub const MAX_SEPARATOR_CHARS: usize = 8;
+
+/// What submitted words are turned into before they're added
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Case {
+    Keep,
+    Lower,
+    Upper,
+}
+
+impl Case {
+    pub fn parse(value: &str) -> Option<Case> {
+        match value {
+            "keep" => Some(Case::Keep),
+            "lower" => Some(Case::Lower),
+            "upper" => Some(Case::Upper),
+            _ => None,
+        }
+    }
+
+    pub fn apply(self, word: &str) -> String {
+        match self {
+            Case::Keep => word.to_string(),
+            Case::Lower => word.to_lowercase(),
+            Case::Upper => word.to_uppercase(),
+        }
+    }
+}
+
+/// What a room plays by
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rules {
+    /// Start a fresh sentence once this many words were written
+    pub max_words: Option<usize>,
+    /// Shown between words by clients that know about it
+    pub separator: String,
+    pub case: Case,
+}
+
+/// What one room changes, `None` for whatever it leaves alone
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Overrides {
+    /// `Some(None)` lifts the limit
+    max_words: Option<Option<usize>>,
+    separator: Option<String>,
+    case: Option<Case>,
+}
+
+/// Separators are short and stay on one line
+pub fn valid_separator(separator: &str) -> bool {
+    separator.chars().count() <= MAX_SEPARATOR_CHARS && !separator.chars().any(char::is_control)
+}
+
+/// Every room's overrides, by lowercased room name
+#[derive(Debug, Default)]
+pub struct RoomRules {
+    rooms: HashMap<String, Overrides>,
+}
+
+impl RoomRules {
+    pub fn load(path: &Path) -> Result<RoomRules, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        RoomRules::parse(&text)
+    }
+
+    fn parse(text: &str) -> Result<RoomRules, String> {
+        let mut rooms: HashMap<String, Overrides> = HashMap::new();
+        let mut current = None;
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let at_line = |err: String| format!("line {}: {err}", number + 1);
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let name = name.trim().to_lowercase();
+                if !room::valid_name(&name) {
+                    return Err(at_line(format!("Invalid room name: {name}")));
+                }
+                if rooms.contains_key(&name) {
+                    return Err(at_line(format!("[{name}] is listed twice")));
+                }
+                rooms.insert(name.clone(), Overrides::default());
+                current = Some(name);
+                continue;
+            }
+            let Some(overrides) = current.as_ref().and_then(|name| rooms.get_mut(name)) else {
+                return Err(at_line(
+                    "Settings need a [room] line above them".to_string(),
+                ));
+            };
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| at_line(format!("Expected `key = value`, got {line}")))?;
+            let value = value.trim();
+            match key.trim() {
+                "max_words" => {
+                    let max: usize = value
+                        .parse()
+                        .map_err(|_| at_line(format!("max_words needs a number, got {value}")))?;
+                    overrides.max_words = Some((max > 0).then_some(max));
+                }
+                "separator" => {
+                    let separator = serde_json::from_str::<String>(value)
+                        .ok()
+                        .filter(|separator| valid_separator(separator))
+                        .ok_or_else(|| {
+                            at_line(format!(
+                                "separator needs a quoted string of up to {MAX_SEPARATOR_CHARS} characters, got {value}"
+                            ))
+                        })?;
+                    overrides.separator = Some(separator);
+                }
+                "case" => {
+                    let case = Case::parse(value).ok_or_else(|| {
+                        at_line(format!("case needs keep, lower or upper, got {value}"))
+                    })?;
+                    overrides.case = Some(case);
+                }
+                other => return Err(at_line(format!("Unknown setting: {other}"))),
+            }
+        }
+        Ok(RoomRules { rooms })
+    }
+
+    /// The rules `room` plays by, `defaults` for whatever it doesn't override
+    pub fn resolve(&self, room: &str, defaults: &Rules) -> Rules {
+        let Some(overrides) = self.rooms.get(room) else {
+            return defaults.clone();
+        };
+        Rules {
+            max_words: overrides.max_words.unwrap_or(defaults.max_words),
+            separator: overrides
+                .separator
+                .clone()
+                .unwrap_or_else(|| defaults.separator.clone()),
+            case: overrides.case.unwrap_or(defaults.case),
+        }
+    }
+
+    /// A room whose `max_words` leaves no room for a word after a prompt
+    /// this many words long
+    pub fn too_short_for(&self, prompt_words: usize) -> Option<&str> {
+        self.rooms
+            .iter()
+            .find(|(_, overrides)| {
+                overrides
+                    .max_words
+                    .flatten()
+                    .is_some_and(|max| max <= prompt_words)
+            })
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> Rules {
+        Rules {
+            max_words: Some(100),
+            separator: DEFAULT_SEPARATOR.to_string(),
+            case: Case::Keep,
+        }
+    }
+
+    #[test]
+    fn rooms_override_only_what_they_set() {
+        let rules = RoomRules::parse(
+            r#"
+# short and shouty
+[Shout]
+max_words = 10
+case = upper
+
+[dashes]
+separator = " - "
+
+[endless]
+max_words = 0
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            rules.resolve("shout", &defaults()),
+            Rules {
+                max_words: Some(10),
+                separator: " ".to_string(),
+                case: Case::Upper,
+            }
+        );
+        assert_eq!(
+            rules.resolve("dashes", &defaults()),
+            Rules {
+                separator: " - ".to_string(),
+                ..defaults()
+            }
+        );
+        assert_eq!(rules.resolve("endless", &defaults()).max_words, None);
+        assert_eq!(rules.resolve("lobby", &defaults()), defaults());
+    }
+
+    #[test]
+    fn prompt_needs_room_for_a_word() {
+        let rules = RoomRules::parse("[tiny]\nmax_words = 3\n").unwrap();
+        assert_eq!(rules.too_short_for(2), None);
+        assert_eq!(rules.too_short_for(3), Some("tiny"));
+    }
+
+    #[test]
+    fn bad_files_say_where() {
+        for text in [
+            "max_words = 3",
+            "[no spaces please]",
+            "[a]\n[a]",
+            "[a]\nmax_words = many",
+            "[a]\nseparator = -",
+            "[a]\nseparator = \"far too long a separator\"",
+            "[a]\ncase = title",
+            "[a]\ncolour = red",
+            "[a]\njust words",
+        ] {
+            let err = RoomRules::parse(text).unwrap_err();
+            assert!(err.starts_with("line "), "{text}: {err}");
+        }
+    }
+
+    #[test]
+    fn case_is_applied_to_words() {
+        assert_eq!(Case::Keep.apply("MiXed"), "MiXed");
+        assert_eq!(Case::Lower.apply("MiXed"), "mixed");
+        assert_eq!(Case::Upper.apply("straße"), "STRASSE");
+    }
+}
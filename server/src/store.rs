@@ -0,0 +1,330 @@
+//! Storage for the collaborative sentence.
+//!
+//! Connection tasks only talk to a [`SentenceStore`], so persistence, history
+//! or size limits can be layered on without touching the networking code.
+
+use std::sync::{Arc, Mutex};
+
+/// Store shared by every connection task
+pub type SharedStore = Arc<Mutex<Box<dyn SentenceStore>>>;
+
+/// Reconnecting clients further behind than this get a full sync instead
+pub const MAX_RESUME_WORDS: u64 = 256;
+
+/// Identifies the connection a word came from
+pub type AuthorId = u64;
+
+/// Author of words no connection can take back, e.g. restored from disk
+pub const NO_AUTHOR: AuthorId = AuthorId::MAX;
+
+pub trait SentenceStore: Send {
+    /// Adds a word to the end of the sentence, `name` is what its author
+    /// goes by at the time
+    fn append(&mut self, word: &str, author: AuthorId, name: &str);
+
+    /// Removes every word `author` added, returns how many there were
+    fn remove_author(&mut self, author: AuthorId) -> usize;
+
+    /// Removes the newest word `author` added and returns it, `None` if there
+    /// is none left
+    fn remove_last(&mut self, author: AuthorId) -> Option<String>;
+
+    /// Drops the oldest words until at most `keep` whitespace separated words
+    /// are left, returns how many were dropped
+    fn trim_front(&mut self, keep: usize) -> usize;
+
+    /// Empties the sentence
+    fn reset(&mut self);
+
+    /// Every word in the order it was appended
+    fn snapshot(&self) -> Vec<String>;
+
+    /// The sentence as sent to clients
+    fn current(&self) -> String;
+
+    /// Author name of every whitespace separated word in the sentence, empty
+    /// for words nobody is known to have written
+    fn credits(&self) -> Vec<String>;
+
+    /// Whitespace separated words in the sentence
+    fn word_count(&self) -> usize {
+        self.current().split_whitespace().count()
+    }
+
+    /// Bumped on every change to the sentence, never goes backwards
+    fn version(&self) -> u64;
+
+    /// Words appended after `version`, or `None` when the client has to resync
+    /// because the sentence was reset since or `version` is too far behind
+    fn words_since(&self, version: u64) -> Option<Vec<String>>;
+}
+
+#[derive(Debug)]
+struct Word {
+    text: String,
+    author: AuthorId,
+    name: String,
+}
+
+/// Keeps the sentence in memory only, lost on restart
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    words: Vec<Word>,
+    version: u64,
+    /// Version right after the last reset or removal, only appends happened since
+    reset_version: u64,
+}
+
+impl SentenceStore for MemoryStore {
+    fn append(&mut self, word: &str, author: AuthorId, name: &str) {
+        let text = collapse_whitespace(word);
+        if text.is_empty() {
+            return;
+        }
+        let name = name.to_string();
+        self.words.push(Word { text, author, name });
+        self.version += 1;
+    }
+
+    fn remove_author(&mut self, author: AuthorId) -> usize {
+        let before = self.words.len();
+        self.words.retain(|word| word.author != author);
+        let removed = before - self.words.len();
+        if removed > 0 {
+            // Words vanished from the middle, resuming clients need a full sync
+            self.version += 1;
+            self.reset_version = self.version;
+        }
+        removed
+    }
+
+    fn remove_last(&mut self, author: AuthorId) -> Option<String> {
+        let index = self.words.iter().rposition(|word| word.author == author)?;
+        let word = self.words.remove(index);
+        // Others may have added words after it, resuming clients need a full sync
+        self.version += 1;
+        self.reset_version = self.version;
+        Some(word.text)
+    }
+
+    fn trim_front(&mut self, keep: usize) -> usize {
+        let dropped = self.word_count().saturating_sub(keep);
+        if dropped == 0 {
+            return 0;
+        }
+        let mut excess = dropped;
+        while excess > 0 {
+            let first = &mut self.words[0];
+            let tokens = first.text.split_whitespace().count();
+            if tokens <= excess {
+                self.words.remove(0);
+                excess -= tokens;
+            } else {
+                // A message with several words only loses its oldest ones
+                first.text = first
+                    .text
+                    .split_whitespace()
+                    .skip(excess)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                excess = 0;
+            }
+        }
+        // The start of the sentence changed, resuming clients need a full sync
+        self.version += 1;
+        self.reset_version = self.version;
+        dropped
+    }
+
+    fn reset(&mut self) {
+        self.words.clear();
+        self.version += 1;
+        self.reset_version = self.version;
+    }
+
+    fn snapshot(&self) -> Vec<String> {
+        self.words.iter().map(|word| word.text.clone()).collect()
+    }
+
+    fn current(&self) -> String {
+        self.snapshot().join(" ")
+    }
+
+    fn credits(&self) -> Vec<String> {
+        self.words
+            .iter()
+            .flat_map(|word| word.text.split_whitespace().map(|_| word.name.clone()))
+            .collect()
+    }
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+
+    fn words_since(&self, version: u64) -> Option<Vec<String>> {
+        if version < self.reset_version
+            || version > self.version
+            || self.version - version > MAX_RESUME_WORDS
+        {
+            return None;
+        }
+        let missed = (self.version - version) as usize;
+        let missed = &self.words[self.words.len() - missed..];
+        Some(missed.iter().map(|word| word.text.clone()).collect())
+    }
+}
+
+/// Trims and turns every run of (Unicode) whitespace into one space, so
+/// joining words never leaves double or stray spaces in the sentence
+fn collapse_whitespace(word: &str) -> String {
+    word.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub fn shared(store: impl SentenceStore + 'static) -> SharedStore {
+    Arc::new(Mutex::new(Box::new(store)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What connection tasks rely on from any store, run against each one
+    fn check_contract(store: &mut dyn SentenceStore) {
+        assert_eq!(store.current(), "");
+        assert_eq!(store.word_count(), 0);
+
+        store.append("once", 1, "ada");
+        store.append("upon a", 2, "bo");
+        store.append("time", 1, "ada");
+        assert_eq!(store.snapshot(), ["once", "upon a", "time"]);
+        assert_eq!(store.current(), "once upon a time");
+        assert_eq!(store.word_count(), 4);
+        assert_eq!(store.credits(), ["ada", "bo", "bo", "ada"]);
+
+        assert_eq!(store.remove_last(1), Some("time".to_string()));
+        assert_eq!(store.remove_last(3), None);
+        assert_eq!(store.current(), "once upon a");
+
+        store.append("there", 1, "ada");
+        assert_eq!(store.remove_author(1), 2);
+        assert_eq!(store.remove_author(1), 0);
+        assert_eq!(store.current(), "upon a");
+
+        store.append("was", NO_AUTHOR, "");
+        assert_eq!(store.credits(), ["bo", "bo", ""]);
+        assert_eq!(store.trim_front(2), 1);
+        assert_eq!(store.current(), "a was");
+        assert_eq!(store.trim_front(5), 0);
+
+        store.reset();
+        assert_eq!(store.current(), "");
+        assert!(store.snapshot().is_empty());
+        assert!(store.credits().is_empty());
+    }
+
+    #[test]
+    fn memory_store_keeps_the_contract() {
+        check_contract(&mut MemoryStore::default());
+    }
+
+    #[test]
+    fn shared_stores_are_one_store() {
+        let store = shared(MemoryStore::default());
+        let other = Arc::clone(&store);
+        store.lock().unwrap().append("hello", 1, "ada");
+        assert_eq!(other.lock().unwrap().current(), "hello");
+    }
+
+    #[test]
+    fn resuming_clients_get_only_what_they_missed() {
+        let mut store = MemoryStore::default();
+        store.append("one", 1, "ada");
+        let seen = store.version();
+        store.append("two", 2, "bo");
+        store.append("three", 1, "ada");
+
+        assert_eq!(
+            store.words_since(seen),
+            Some(vec!["two".into(), "three".into()])
+        );
+        assert_eq!(store.words_since(store.version()), Some(Vec::new()));
+        // From the future, e.g. a client of an earlier server run
+        assert_eq!(store.words_since(store.version() + 1), None);
+
+        store.remove_last(2);
+        assert_eq!(store.words_since(seen), None);
+        let seen = store.version();
+        store.append("four", 2, "bo");
+        assert_eq!(store.words_since(seen), Some(vec!["four".into()]));
+    }
+
+    #[test]
+    fn clients_far_behind_resync() {
+        let mut store = MemoryStore::default();
+        for word in 0..=MAX_RESUME_WORDS {
+            store.append(&word.to_string(), 1, "ada");
+        }
+        assert_eq!(store.words_since(0), None);
+        assert_eq!(
+            store.words_since(1).map(|words| words.len()),
+            Some(MAX_RESUME_WORDS as usize)
+        );
+    }
+
+    #[test]
+    fn every_change_bumps_the_version() {
+        let mut store = MemoryStore::default();
+        let mut last = store.version();
+        let mut changed = |store: &MemoryStore| {
+            assert!(store.version() > last, "version didn't go up");
+            last = store.version();
+        };
+        store.append("one two", 1, "ada");
+        changed(&store);
+        store.append("three", 2, "bo");
+        changed(&store);
+        store.remove_last(2);
+        changed(&store);
+        store.append("four", 2, "bo");
+        changed(&store);
+        store.trim_front(2);
+        changed(&store);
+        store.remove_author(1);
+        changed(&store);
+        store.reset();
+        changed(&store);
+        store.append("again", 1, "ada");
+        changed(&store);
+    }
+
+    #[test]
+    fn no_op_changes_keep_the_version() {
+        let mut store = MemoryStore::default();
+        store.append("one", 1, "ada");
+        let version = store.version();
+        store.append("   ", 1, "ada");
+        store.remove_last(2);
+        store.remove_author(2);
+        store.trim_front(5);
+        assert_eq!(store.version(), version);
+    }
+
+    #[test]
+    fn whitespace_never_piles_up() {
+        let mut store = MemoryStore::default();
+        store.append("  once\tupon ", 1, "ada");
+        store.append("\u{3000}a\n\ntime\u{a0}", 2, "bo");
+        store.append(" \t ", 2, "bo");
+        assert_eq!(store.current(), "once upon a time");
+        assert_eq!(store.credits(), ["ada", "ada", "bo", "bo"]);
+
+        store.remove_last(1);
+        assert_eq!(store.current(), "a time");
+        store.append("there  was", 1, "ada");
+        store.remove_author(2);
+        assert_eq!(store.current(), "there was");
+        store.trim_front(1);
+        assert_eq!(store.current(), "was");
+        assert_eq!(store.snapshot(), ["was"]);
+    }
+}
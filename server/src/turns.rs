@@ -0,0 +1,77 @@
+//! `--turns`: players add one word each, taking turns in the order they
+//! joined.
+//!
+//! Only typists take part, so with `--require-join` watchers are skipped until
+//! they join. Whoever leaves drops out of the rotation, and if it was their
+//! turn it passes to the next player.
+
+use crate::store::{AuthorId, NO_AUTHOR};
+use protocol::{ServerMessage, TurnState};
+use std::sync::{Arc, Mutex};
+
+/// Rotation shared by every connection task of a room
+pub type SharedTurns = Arc<Mutex<Turns>>;
+
+#[derive(Debug, Default)]
+pub struct Turns {
+    /// Typists in the order they joined, with the name they go by
+    players: Vec<(AuthorId, String)>,
+    /// Index into `players` of whoever's turn it is
+    current: usize,
+}
+
+impl Turns {
+    /// Adds a typist at the end of the rotation
+    pub fn join(&mut self, author: AuthorId, name: &str) {
+        self.players.push((author, name.to_string()));
+    }
+
+    pub fn rename(&mut self, author: AuthorId, name: &str) {
+        if let Some(player) = self.players.iter_mut().find(|(id, _)| *id == author) {
+            player.1 = name.to_string();
+        }
+    }
+
+    pub fn leave(&mut self, author: AuthorId) {
+        let Some(index) = self.players.iter().position(|(id, _)| *id == author) else {
+            return;
+        };
+        self.players.remove(index);
+        // Whoever came after them moved up one, the turn stays with the same
+        // player or, if it was theirs, passes to the next
+        if index < self.current {
+            self.current -= 1;
+        }
+        if self.current >= self.players.len() {
+            self.current = 0;
+        }
+    }
+
+    pub fn is_turn(&self, author: AuthorId) -> bool {
+        self.players
+            .get(self.current)
+            .is_some_and(|(id, _)| *id == author)
+    }
+
+    /// Passes the turn on once the current player added their word
+    pub fn advance(&mut self) {
+        if !self.players.is_empty() {
+            self.current = (self.current + 1) % self.players.len();
+        }
+    }
+
+    /// Whose turn it is, as `author` sees it
+    pub fn state_for(&self, author: AuthorId) -> TurnState {
+        match self.players.get(self.current) {
+            Some((id, _)) if *id == author => TurnState::Yours,
+            Some((_, name)) => TurnState::Waiting(name.clone()),
+            None => TurnState::Nobody,
+        }
+    }
+
+    /// Tells everyone the turn moved on. Each connection asks `state_for` to
+    /// see whether it's theirs now.
+    pub fn message(&self) -> ServerMessage {
+        ServerMessage::Turn(self.state_for(NO_AUTHOR))
+    }
+}